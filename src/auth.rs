@@ -1,35 +1,126 @@
 use crate::validator_whitelist::ValidatorWhitelist;
 use dashmap::DashMap;
 use schnorrkel::{PublicKey, Signature};
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tracing::warn;
 
-const NONCE_TTL: Duration = Duration::from_secs(300);
-const NONCE_REAP_INTERVAL: Duration = Duration::from_secs(60);
+const PUBLIC_KEY_CACHE_SIZE: usize = 256;
 
 const MAX_HOTKEY_LEN: usize = 128;
 const MIN_NONCE_LEN: usize = 1;
 const MAX_NONCE_LEN: usize = 256;
 const MAX_SIGNATURE_LEN: usize = 256;
 
+/// sr25519 signing context used unless `SIGNING_CONTEXT` says otherwise.
+pub const DEFAULT_SIGNING_CONTEXT: &str = "substrate";
+
 fn is_valid_nonce(s: &str) -> bool {
     s.len() >= MIN_NONCE_LEN
         && s.len() <= MAX_NONCE_LEN
         && s.bytes().all(|b| b.is_ascii_graphic() || b == b' ')
 }
 
+/// Bounded LRU of parsed sr25519 public keys keyed by SS58 hotkey, so a
+/// validator signing repeatedly skips the bs58 decode, checksum and key
+/// parse. Only the deterministic address→key mapping is cached, never
+/// verification results.
+pub struct PublicKeyCache {
+    capacity: usize,
+    inner: parking_lot::Mutex<KeyCacheInner>,
+}
+
+struct KeyCacheInner {
+    keys: HashMap<String, (PublicKey, u64)>,
+    tick: u64,
+}
+
+impl PublicKeyCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            inner: parking_lot::Mutex::new(KeyCacheInner {
+                keys: HashMap::new(),
+                tick: 0,
+            }),
+        }
+    }
+
+    /// Parsed public key for `hotkey`, or `None` if it is not a valid
+    /// generic-substrate SS58 address. Invalid addresses are not cached.
+    pub fn get(&self, hotkey: &str) -> Option<PublicKey> {
+        {
+            let mut inner = self.inner.lock();
+            inner.tick += 1;
+            let tick = inner.tick;
+            if let Some((key, used)) = inner.keys.get_mut(hotkey) {
+                *used = tick;
+                return Some(*key);
+            }
+        }
+
+        if !validate_ss58(hotkey) {
+            return None;
+        }
+        let key = PublicKey::from_bytes(&ss58_to_public_key_bytes(hotkey)?).ok()?;
+
+        let mut inner = self.inner.lock();
+        if inner.keys.len() >= self.capacity && !inner.keys.contains_key(hotkey) {
+            let oldest = inner
+                .keys
+                .iter()
+                .min_by_key(|(_, (_, used))| *used)
+                .map(|(k, _)| k.clone());
+            if let Some(oldest) = oldest {
+                inner.keys.remove(&oldest);
+            }
+        }
+        let tick = inner.tick;
+        inner.keys.insert(hotkey.to_string(), (key, tick));
+        Some(key)
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.inner.lock().keys.len()
+    }
+}
+
 pub struct NonceStore {
     seen: DashMap<String, Instant>,
+    /// How long a nonce stays burned (`NONCE_TTL_SECS`).
+    ttl: Duration,
+    public_keys: PublicKeyCache,
+    /// sr25519 signing context request signatures are checked under
+    /// (`SIGNING_CONTEXT`).
+    signing_context: Vec<u8>,
+    /// Also accept signatures over `<Bytes>hotkey+nonce</Bytes>`
+    /// (`SIGNING_ACCEPT_BYTES_WRAPPED`).
+    accept_bytes_wrapped: bool,
 }
 
 impl NonceStore {
-    pub fn new() -> Self {
+    pub fn new(ttl: Duration) -> Self {
         Self {
             seen: DashMap::new(),
+            ttl,
+            public_keys: PublicKeyCache::new(PUBLIC_KEY_CACHE_SIZE),
+            signing_context: DEFAULT_SIGNING_CONTEXT.as_bytes().to_vec(),
+            accept_bytes_wrapped: true,
         }
     }
 
+    pub fn with_signing_context(mut self, context: &[u8]) -> Self {
+        self.signing_context = context.to_vec();
+        self
+    }
+
+    pub fn with_bytes_wrapped(mut self, accept: bool) -> Self {
+        self.accept_bytes_wrapped = accept;
+        self
+    }
+
     pub fn check_and_insert(&self, nonce: &str) -> bool {
         use dashmap::mapref::entry::Entry;
         match self.seen.entry(nonce.to_string()) {
@@ -41,12 +132,17 @@ impl NonceStore {
         }
     }
 
-    pub async fn reaper_loop(self: Arc<Self>) {
-        let mut interval = tokio::time::interval(NONCE_REAP_INTERVAL);
+    /// Forget nonces older than the TTL, making them usable again.
+    fn reap_expired(&self) {
+        let cutoff = Instant::now() - self.ttl;
+        self.seen.retain(|_, ts| *ts > cutoff);
+    }
+
+    pub async fn reaper_loop(self: Arc<Self>, every: Duration) {
+        let mut interval = tokio::time::interval(every);
         loop {
             interval.tick().await;
-            let cutoff = Instant::now() - NONCE_TTL;
-            self.seen.retain(|_, ts| *ts > cutoff);
+            self.reap_expired();
         }
     }
 }
@@ -95,12 +191,18 @@ pub fn verify_request(
         return Err(AuthError::UnauthorizedHotkey);
     }
 
-    if !validate_ss58(&auth.hotkey) {
+    let Some(public_key) = nonce_store.public_keys.get(&auth.hotkey) else {
         return Err(AuthError::InvalidHotkey);
-    }
+    };
 
     let message = format!("{}{}", auth.hotkey, auth.nonce);
-    if !verify_sr25519_signature(&auth.hotkey, &message, &auth.signature) {
+    if !verify_sr25519_signature(
+        &public_key,
+        &nonce_store.signing_context,
+        nonce_store.accept_bytes_wrapped,
+        &message,
+        &auth.signature,
+    ) {
         return Err(AuthError::InvalidSignature);
     }
 
@@ -139,23 +241,17 @@ impl AuthError {
     }
 }
 
-fn verify_sr25519_signature(ss58_hotkey: &str, message: &str, signature_hex: &str) -> bool {
-    let pub_bytes = match ss58_to_public_key_bytes(ss58_hotkey) {
-        Some(b) => b,
-        None => {
-            warn!("Failed to decode SS58 address");
-            return false;
-        }
-    };
-
-    let public_key = match PublicKey::from_bytes(&pub_bytes) {
-        Ok(pk) => pk,
-        Err(_) => {
-            warn!("Failed to parse sr25519 public key");
-            return false;
-        }
-    };
-
+/// Verify `signature_hex` over `message` under `context`. With
+/// `accept_wrapped`, signatures over the polkadot-js `<Bytes>message</Bytes>`
+/// wrapping are accepted too, since browser wallets apply it to every raw
+/// payload they sign.
+fn verify_sr25519_signature(
+    public_key: &PublicKey,
+    context: &[u8],
+    accept_wrapped: bool,
+    message: &str,
+    signature_hex: &str,
+) -> bool {
     let sig_bytes = match hex::decode(signature_hex.strip_prefix("0x").unwrap_or(signature_hex)) {
         Ok(b) if b.len() == 64 => b,
         _ => {
@@ -172,10 +268,13 @@ fn verify_sr25519_signature(ss58_hotkey: &str, message: &str, signature_hex: &st
         }
     };
 
-    let context = schnorrkel::signing_context(b"substrate");
-    public_key
-        .verify(context.bytes(message.as_bytes()), &signature)
-        .is_ok()
+    let context = schnorrkel::signing_context(context);
+    let verifies = |m: &str| {
+        public_key
+            .verify(context.bytes(m.as_bytes()), &signature)
+            .is_ok()
+    };
+    verifies(message) || (accept_wrapped && verifies(&format!("<Bytes>{}</Bytes>", message)))
 }
 
 fn ss58_checksum(data: &[u8]) -> [u8; 2] {
@@ -246,12 +345,25 @@ mod tests {
 
     #[test]
     fn test_nonce_store_accepts_first_rejects_replay() {
-        let store = NonceStore::new();
+        let store = NonceStore::new(Duration::from_secs(300));
         assert!(store.check_and_insert("nonce-1"));
         assert!(!store.check_and_insert("nonce-1"));
         assert!(store.check_and_insert("nonce-2"));
     }
 
+    #[test]
+    fn test_nonce_reusable_after_ttl() {
+        let store = NonceStore::new(Duration::from_secs(300));
+        assert!(store.check_and_insert("nonce-1"));
+        store.reap_expired();
+        assert!(!store.check_and_insert("nonce-1"));
+
+        let store = NonceStore::new(Duration::ZERO);
+        assert!(store.check_and_insert("nonce-1"));
+        store.reap_expired();
+        assert!(store.check_and_insert("nonce-1"));
+    }
+
     #[test]
     fn test_extract_auth_headers_present() {
         let mut headers = axum::http::HeaderMap::new();
@@ -274,7 +386,7 @@ mod tests {
 
     #[test]
     fn test_verify_request_non_whitelisted() {
-        let store = NonceStore::new();
+        let store = NonceStore::new(Duration::from_secs(300));
         let wl = ValidatorWhitelist::new();
         let auth = AuthHeaders {
             hotkey: "5InvalidHotkey".to_string(),
@@ -287,7 +399,7 @@ mod tests {
 
     #[test]
     fn test_nonce_not_burned_on_invalid_signature() {
-        let store = NonceStore::new();
+        let store = NonceStore::new(Duration::from_secs(300));
         let wl = ValidatorWhitelist::new();
         wl.insert_for_test(TEST_SS58);
 
@@ -366,8 +478,121 @@ mod tests {
         let signature = keypair.sign(context.bytes(message.as_bytes()));
         let sig_hex = hex::encode(signature.to_bytes());
 
-        assert!(verify_sr25519_signature(&ss58, &message, &sig_hex));
-        assert!(!verify_sr25519_signature(&ss58, "wrong-message", &sig_hex));
+        let cache = PublicKeyCache::new(4);
+        let public_key = cache.get(&ss58).expect("valid hotkey");
+        assert_eq!(public_key, pub_key);
+        assert!(verify_sr25519_signature(
+            &public_key,
+            b"substrate",
+            true,
+            &message,
+            &sig_hex
+        ));
+        assert!(!verify_sr25519_signature(
+            &public_key,
+            b"substrate",
+            true,
+            "wrong-message",
+            &sig_hex
+        ));
+        assert!(!verify_sr25519_signature(
+            &public_key,
+            b"",
+            true,
+            &message,
+            &sig_hex
+        ));
+    }
+
+    #[test]
+    fn test_verify_sr25519_bytes_wrapped_and_custom_context() {
+        use schnorrkel::{Keypair, MiniSecretKey};
+
+        let keypair: Keypair =
+            MiniSecretKey::generate().expand_to_keypair(schnorrkel::ExpansionMode::Ed25519);
+        let message = format!("{}{}", TEST_SS58, "wrapped-nonce");
+
+        let sign = |context: &[u8], payload: &str| {
+            let context = schnorrkel::signing_context(context);
+            hex::encode(keypair.sign(context.bytes(payload.as_bytes())).to_bytes())
+        };
+
+        // polkadot-js style: the wallet wraps the payload before signing.
+        let wrapped = sign(b"substrate", &format!("<Bytes>{}</Bytes>", message));
+        assert!(verify_sr25519_signature(
+            &keypair.public,
+            b"substrate",
+            true,
+            &message,
+            &wrapped
+        ));
+        assert!(!verify_sr25519_signature(
+            &keypair.public,
+            b"substrate",
+            true,
+            "other-message",
+            &wrapped
+        ));
+        // Only when the wrapping is accepted.
+        assert!(!verify_sr25519_signature(
+            &keypair.public,
+            b"substrate",
+            false,
+            &message,
+            &wrapped
+        ));
+
+        // Empty and custom contexts only verify when configured.
+        for context in [&b""[..], &b"my-chain"[..]] {
+            let sig = sign(context, &message);
+            assert!(verify_sr25519_signature(
+                &keypair.public,
+                context,
+                true,
+                &message,
+                &sig
+            ));
+            assert!(!verify_sr25519_signature(
+                &keypair.public,
+                b"substrate",
+                true,
+                &message,
+                &sig
+            ));
+        }
+    }
+
+    #[test]
+    fn test_public_key_cache_is_bounded_lru() {
+        let cache = PublicKeyCache::new(2);
+        let hotkeys: Vec<String> = (0..3u8)
+            .map(|i| {
+                let mut raw = vec![42u8];
+                raw.extend_from_slice(
+                    &schnorrkel::MiniSecretKey::from_bytes(&[i + 1; 32])
+                        .unwrap()
+                        .expand_to_public(schnorrkel::ExpansionMode::Ed25519)
+                        .to_bytes(),
+                );
+                let checksum = ss58_checksum(&raw);
+                raw.extend_from_slice(&checksum);
+                bs58::encode(&raw).into_string()
+            })
+            .collect();
+
+        assert!(cache.get(&hotkeys[0]).is_some());
+        assert!(cache.get(&hotkeys[1]).is_some());
+        // Touch the first so the second becomes least recently used.
+        assert!(cache.get(&hotkeys[0]).is_some());
+        assert!(cache.get(&hotkeys[2]).is_some());
+        assert_eq!(cache.len(), 2);
+        let inner = cache.inner.lock();
+        assert!(inner.keys.contains_key(&hotkeys[0]));
+        assert!(!inner.keys.contains_key(&hotkeys[1]));
+        drop(inner);
+
+        assert!(cache.get("not-an-address").is_none());
+        assert_eq!(cache.len(), 2);
     }
 
     #[test]
@@ -1,6 +1,14 @@
 use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
 use tracing::{info, warn};
 
+use crate::metrics::Metrics;
+use crate::session::SessionManager;
+
+const INTEGRITY_INTERVAL: Duration = Duration::from_secs(300);
+
 /// Remove a session's work directory.
 pub async fn remove_work_dir(path: &Path) {
     if !path.exists() {
@@ -11,6 +19,176 @@ pub async fn remove_work_dir(path: &Path) {
     }
 }
 
+/// When to keep a task's work dir around for debugging instead of deleting it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeepWorkdir {
+    #[default]
+    Never,
+    OnFailure,
+    Always,
+}
+
+impl std::str::FromStr for KeepWorkdir {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "never" => Ok(KeepWorkdir::Never),
+            "on_failure" => Ok(KeepWorkdir::OnFailure),
+            "always" => Ok(KeepWorkdir::Always),
+            other => Err(format!("unknown KEEP_WORKDIR policy '{}'", other)),
+        }
+    }
+}
+
+/// Subdirs of the workspace base holding kept work dirs of failed and of
+/// other tasks. They are skipped by the orphan sweep and their entries are
+/// aged out by `reap_stale_sessions`.
+pub const KEPT_FAILED_DIR: &str = "failed";
+pub const KEPT_DIR: &str = "kept";
+
+/// Dispose of a finished task's work dir according to `policy`: delete it, or
+/// move it to `base/failed/{batch_id}/{dir_name}` (`base/kept/...` for tasks
+/// that passed). A previous kept dir for the same task is replaced.
+pub async fn finish_work_dir(
+    base: &Path,
+    work_dir: &Path,
+    batch_id: &str,
+    dir_name: &str,
+    failed: bool,
+    policy: KeepWorkdir,
+) {
+    let keep = match policy {
+        KeepWorkdir::Never => false,
+        KeepWorkdir::OnFailure => failed,
+        KeepWorkdir::Always => true,
+    };
+    if !keep || !work_dir.exists() {
+        remove_work_dir(work_dir).await;
+        return;
+    }
+
+    let kept_root = base
+        .join(if failed { KEPT_FAILED_DIR } else { KEPT_DIR })
+        .join(batch_id);
+    let dest = kept_root.join(dir_name);
+    remove_work_dir(&dest).await;
+    let moved = match tokio::fs::create_dir_all(&kept_root).await {
+        Ok(()) => tokio::fs::rename(work_dir, &dest).await,
+        Err(e) => Err(e),
+    };
+    match moved {
+        Ok(()) => {
+            // Age from now, not from when the task started writing.
+            let _ = std::fs::File::open(&dest)
+                .and_then(|f| f.set_modified(std::time::SystemTime::now()));
+            info!("Kept work dir {} at {}", dir_name, dest.display());
+        }
+        Err(e) => {
+            warn!("Failed to keep work dir {}: {}", work_dir.display(), e);
+            remove_work_dir(work_dir).await;
+        }
+    }
+}
+
+/// Total size in bytes of regular files under `path`. Symlinks are not
+/// followed and unreadable entries are skipped. Blocking; call from
+/// `spawn_blocking`.
+pub fn disk_usage(path: &Path) -> u64 {
+    let mut total = 0u64;
+    let mut stack = vec![path.to_path_buf()];
+    while let Some(dir) = stack.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.flatten() {
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            if meta.is_dir() {
+                stack.push(entry.path());
+            } else if meta.is_file() {
+                total += meta.len();
+            }
+        }
+    }
+    total
+}
+
+/// Remove batch work dirs (`base/{batch_id}`) whose batch `sessions` no
+/// longer knows. Each batch is looked up right before its dir would go, and
+/// dirs modified within `min_age` are left for the next pass, so a batch
+/// that is just being created is never swept. Directories starting with `_`
+/// or `.` (extraction temp dirs, the journal and the like) and any holding
+/// `keep` are left alone. Returns how many were removed.
+pub async fn remove_orphan_work_dirs(
+    base: &Path,
+    sessions: &SessionManager,
+    min_age: Duration,
+    keep: Option<&Path>,
+) -> u64 {
+    let mut entries = match tokio::fs::read_dir(base).await {
+        Ok(e) => e,
+        Err(_) => return 0,
+    };
+
+    let now = std::time::SystemTime::now();
+    let mut removed = 0;
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        let name = entry.file_name().to_string_lossy().to_string();
+        if !path.is_dir()
+            || name.starts_with('_')
+            || name.starts_with('.')
+            || name == KEPT_FAILED_DIR
+            || name == KEPT_DIR
+            || keep.is_some_and(|keep| keep.starts_with(&path))
+        {
+            continue;
+        }
+        let recent = entry
+            .metadata()
+            .await
+            .and_then(|m| m.modified())
+            .map_or(true, |modified| {
+                now.duration_since(modified).unwrap_or_default() < min_age
+            });
+        if recent || sessions.get(&name).is_some() {
+            continue;
+        }
+        info!("Removing orphaned work dir {}", path.display());
+        remove_work_dir(&path).await;
+        removed += 1;
+    }
+    removed
+}
+
+/// Periodically reconcile work dirs against the batches the session manager
+/// still knows about, independent of age-based reaping.
+pub async fn integrity_loop(
+    base: std::path::PathBuf,
+    shared_cache_dir: Option<std::path::PathBuf>,
+    sessions: Arc<SessionManager>,
+    metrics: Arc<Metrics>,
+) {
+    let mut interval = tokio::time::interval(INTEGRITY_INTERVAL);
+    loop {
+        interval.tick().await;
+        let removed = remove_orphan_work_dirs(
+            &base,
+            &sessions,
+            INTEGRITY_INTERVAL,
+            shared_cache_dir.as_deref(),
+        )
+        .await;
+        if removed > 0 {
+            metrics
+                .orphans_cleaned
+                .fetch_add(removed, Ordering::Relaxed);
+        }
+    }
+}
+
 /// Kill all processes in a process group (best-effort).
 #[allow(dead_code)]
 pub async fn kill_process_group(pgid: u32) {
@@ -35,6 +213,13 @@ pub async fn reap_stale_sessions(base: &Path, max_age_secs: u64) {
         if !path.is_dir() {
             continue;
         }
+        let name = entry.file_name();
+        if name == KEPT_FAILED_DIR || name == KEPT_DIR {
+            // Age out each batch's kept work dirs, not the directory holding
+            // them.
+            Box::pin(reap_stale_sessions(&path, max_age_secs)).await;
+            continue;
+        }
         let metadata = match tokio::fs::metadata(&path).await {
             Ok(m) => m,
             Err(_) => continue,
@@ -59,12 +244,124 @@ pub async fn reap_stale_sessions(base: &Path, max_age_secs: u64) {
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn test_integrity_pass_removes_orphans() {
+        let base = tempfile::tempdir().unwrap();
+        let sessions = SessionManager::new(60);
+        let batch = sessions.create_batch(1);
+
+        let dirs = [batch.id.as_str(), "orphan-batch", "_extract_abc", "cache"];
+        for dir in dirs {
+            std::fs::create_dir_all(base.path().join(dir).join("task-1")).unwrap();
+        }
+        let cache = base.path().join("cache/pip");
+
+        // Too new to tell apart from a batch still being set up.
+        let hour = Duration::from_secs(3600);
+        assert_eq!(
+            remove_orphan_work_dirs(base.path(), &sessions, hour, Some(&cache)).await,
+            0
+        );
+        for dir in dirs {
+            std::fs::File::open(base.path().join(dir))
+                .unwrap()
+                .set_modified(std::time::SystemTime::now() - 2 * hour)
+                .unwrap();
+        }
+        let removed = remove_orphan_work_dirs(base.path(), &sessions, hour, Some(&cache)).await;
+
+        assert_eq!(removed, 1);
+        assert!(base.path().join(&batch.id).join("task-1").exists());
+        assert!(!base.path().join("orphan-batch").exists());
+        assert!(base.path().join("_extract_abc").exists());
+        assert!(base.path().join("cache").exists());
+    }
+
+    #[tokio::test]
+    async fn test_keep_workdir_policy() {
+        let base = tempfile::tempdir().unwrap();
+        let work = |id: &str| {
+            let dir = base.path().join("b1").join(id);
+            std::fs::create_dir_all(dir.join("repo")).unwrap();
+            std::fs::write(dir.join("repo/out.log"), "trace").unwrap();
+            dir
+        };
+
+        let dir = work("t-pass");
+        finish_work_dir(
+            base.path(),
+            &dir,
+            "b1",
+            "t-pass",
+            false,
+            KeepWorkdir::OnFailure,
+        )
+        .await;
+        assert!(!dir.exists());
+        assert!(!base.path().join(KEPT_DIR).exists());
+
+        let dir = work("t-fail");
+        finish_work_dir(
+            base.path(),
+            &dir,
+            "b1",
+            "t-fail",
+            true,
+            KeepWorkdir::OnFailure,
+        )
+        .await;
+        assert!(!dir.exists());
+        let kept = base.path().join(KEPT_FAILED_DIR).join("b1").join("t-fail");
+        assert_eq!(
+            std::fs::read_to_string(kept.join("repo/out.log")).unwrap(),
+            "trace"
+        );
+
+        let dir = work("t-never");
+        finish_work_dir(base.path(), &dir, "b1", "t-never", true, KeepWorkdir::Never).await;
+        assert!(!dir.exists());
+        assert!(!base
+            .path()
+            .join(KEPT_FAILED_DIR)
+            .join("b1")
+            .join("t-never")
+            .exists());
+
+        // Kept dirs survive the orphan sweep but not age-based reaping.
+        std::fs::remove_dir(base.path().join("b1")).unwrap();
+        assert_eq!(
+            remove_orphan_work_dirs(base.path(), &SessionManager::new(60), Duration::ZERO, None)
+                .await,
+            0
+        );
+        assert!(kept.exists());
+        reap_stale_sessions(base.path(), 3600).await;
+        assert!(kept.exists());
+        std::fs::File::open(kept.parent().unwrap())
+            .unwrap()
+            .set_modified(std::time::SystemTime::now() - Duration::from_secs(7200))
+            .unwrap();
+        reap_stale_sessions(base.path(), 3600).await;
+        assert!(!kept.exists());
+        assert!(base.path().join(KEPT_FAILED_DIR).exists());
+    }
+
     #[tokio::test]
     async fn test_remove_work_dir_nonexistent() {
         remove_work_dir(Path::new("/tmp/nonexistent_test_dir_xyz")).await;
         // should not panic
     }
 
+    #[test]
+    fn test_disk_usage() {
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::create_dir_all(tmp.path().join("a/b")).unwrap();
+        std::fs::write(tmp.path().join("top.txt"), vec![0u8; 100]).unwrap();
+        std::fs::write(tmp.path().join("a/b/deep.txt"), vec![0u8; 50]).unwrap();
+        assert_eq!(disk_usage(tmp.path()), 150);
+        assert_eq!(disk_usage(Path::new("/tmp/nonexistent_test_dir_xyz")), 0);
+    }
+
     #[tokio::test]
     async fn test_remove_work_dir() {
         let tmp = tempfile::tempdir().unwrap();
@@ -4,37 +4,183 @@ const DEFAULT_PORT: u16 = 8080;
 const DEFAULT_SESSION_TTL: u64 = 7200;
 const DEFAULT_MAX_CONCURRENT: usize = 6;
 const DEFAULT_CLONE_TIMEOUT: u64 = 600;
+const DEFAULT_INSTALL_TIMEOUT: u64 = 1800;
 const DEFAULT_AGENT_TIMEOUT: u64 = 600;
 const DEFAULT_TEST_TIMEOUT: u64 = 300;
+const DEFAULT_BATCH_TIMEOUT: u64 = 0;
+const DEFAULT_MAX_TASK_TIMEOUT: u64 = 1800;
+const DEFAULT_MIN_AGENT_RUNTIME_MS: u64 = 0;
+const DEFAULT_DRAIN_TIMEOUT_SECS: u64 = 600;
+const DEFAULT_TASK_DISK_QUOTA_MB: u64 = 0;
 const DEFAULT_MAX_ARCHIVE_BYTES: usize = 500 * 1024 * 1024;
+const DEFAULT_MAX_TASK_OUTPUT_BYTES: usize = 4 * 1024 * 1024;
+const DEFAULT_MAX_TASKS_PER_BATCH: usize = 1000;
 const DEFAULT_WORKSPACE_BASE: &str = "/home/agent/sessions";
 const DEFAULT_MAX_PENDING_CONSENSUS: usize = 100;
 const DEFAULT_BITTENSOR_NETUID: u16 = 100;
 const DEFAULT_MIN_VALIDATOR_STAKE_TAO: f64 = 10_000.0;
 const DEFAULT_VALIDATOR_REFRESH_SECS: u64 = 300;
+const DEFAULT_WHITELIST_FAILURE_THRESHOLD: u32 = 3;
 const DEFAULT_CONSENSUS_THRESHOLD: f64 = 0.5;
 const DEFAULT_CONSENSUS_TTL_SECS: u64 = 60;
+const DEFAULT_CONSENSUS_REAP_INTERVAL_SECS: u64 = 30;
+const DEFAULT_NONCE_TTL_SECS: u64 = 300;
+const DEFAULT_NONCE_REAP_INTERVAL_SECS: u64 = 60;
+const DEFAULT_SUBMIT_RATE_PER_MIN: usize = 30;
+#[cfg(feature = "nats")]
+const DEFAULT_EVENT_SUBJECT_PREFIX: &str = "term_executor.events";
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub port: u16,
     pub session_ttl_secs: u64,
     pub max_concurrent_tasks: usize,
+    /// Batches allowed to run at once. Their tasks share the
+    /// `max_concurrent_tasks` slots.
+    pub max_concurrent_batches: usize,
+    /// Consensus-reached batches held in FIFO order while
+    /// `max_concurrent_batches` are running; 0 rejects them with `busy`.
+    pub batch_queue_depth: usize,
+    /// Concurrency used when a reached consensus carries no agreed value.
+    /// Always clamped to `max_concurrent_tasks`.
+    pub default_consensus_concurrency: usize,
     pub clone_timeout_secs: u64,
+    /// Timeout for the `git ls-remote` reachability check before cloning;
+    /// 0 skips the check.
+    pub repo_precheck_secs: u64,
+    /// Per-command timeout for install steps, separate from the clone timeout.
+    pub install_timeout_secs: u64,
+    /// Shell commands run once per batch before its tasks start, each under
+    /// `install_timeout_secs`.
+    pub warmup_commands: Vec<String>,
+    /// Persistent dir for pip/go/npm caches shared by all tasks; unset keeps
+    /// each tool's default.
+    pub shared_cache_dir: Option<PathBuf>,
+    /// Synthesize a test command from the repo's project files when a task
+    /// ships no test scripts.
+    pub auto_test: bool,
+    /// Network access for test scripts; install stages always have network.
+    pub test_network: crate::sandbox::NetworkMode,
+    /// `ulimit -v` in MiB for the agent and test scripts; 0 disables it.
+    pub sandbox_memory_mb: u64,
+    /// Niceness for the agent and test scripts; 0 leaves it unchanged.
+    pub sandbox_nice: i32,
+    /// `ulimit -t` for test scripts; 0 disables it.
+    pub test_cpu_time_secs: u64,
+    /// `ulimit -u` for test scripts; 0 disables it.
+    pub test_max_processes: u64,
+    /// Seconds a timed-out agent or test script gets between SIGTERM and
+    /// SIGKILL; 0 kills it outright.
+    pub kill_grace_secs: u64,
+    /// Test scripts of one task run at the same time; 0 or 1 runs them in
+    /// order.
+    pub test_script_concurrency: usize,
+    /// Unprivileged user the agent and tests run as; `None` when unset or
+    /// when the executor isn't root.
+    pub run_as_user: Option<crate::sandbox::RunAsUser>,
     pub agent_timeout_secs: u64,
+    /// Run the agent attached to a pseudo-terminal instead of pipes.
+    pub agent_pty: bool,
+    /// How the prompt is handed to the agent (`file`, `stdin` or `arg`).
+    pub agent_prompt_delivery: crate::executor::PromptDelivery,
+    /// Interpreters agents are started with (`AGENT_RUNNER_<LANGUAGE>`).
+    pub agent_runners: crate::executor::AgentRunners,
+    pub reward_aggregation: crate::session::RewardAggregation,
+    /// Order of the `tasks` array in finished batches.
+    pub task_order: crate::session::TaskOrder,
+    pub duplicate_tasks: crate::task::DuplicateTaskPolicy,
+    /// Journal finished tasks to disk so a batch interrupted by a restart can
+    /// be resumed.
+    pub batch_journal: bool,
+    /// Fail a task as soon as an install command fails instead of running
+    /// the agent against a half-installed repo.
+    pub abort_on_install_failure: bool,
+    /// Keep task work dirs under `failed/` (or `kept/`) for debugging.
+    pub keep_workdir: crate::cleanup::KeepWorkdir,
     pub test_timeout_secs: u64,
+    /// Upper bound for `test_timeout_secs` / `agent_timeout_secs` overrides
+    /// in `workspace.yaml`.
+    pub max_task_timeout_secs: u64,
+    /// Wall-clock ceiling for a whole batch; 0 disables it.
+    pub batch_timeout_secs: u64,
+    /// Fail a batch when no task completes for this long; 0 disables it.
+    pub batch_stall_secs: u64,
+    /// Retries (e.g. of a failed clone) a whole batch may spend; 0 disables retries.
+    pub batch_retry_budget: u32,
+    /// Agents finishing faster than this are flagged `suspicious_agent`; 0 disables it.
+    pub min_agent_runtime_ms: u64,
+    /// How long shutdown waits for the running batch before cancelling it.
+    pub drain_timeout_secs: u64,
+    /// Per-task work dir size limit; 0 disables it.
+    pub task_disk_quota_mb: u64,
     pub max_archive_bytes: usize,
+    /// Test output kept per task across all its scripts (0 = unlimited).
+    pub max_task_output_bytes: usize,
+    /// Keep each batch's agent code for `GET /batch/{id}/agent`; otherwise
+    /// only its hash and language are kept.
+    pub retain_batch_agent_code: bool,
+    /// Deadline for fetching an archive submitted to `/submit/url`.
+    pub archive_download_timeout_secs: u64,
+    /// Task directories allowed in one archive; 0 disables the limit.
+    pub max_tasks_per_batch: usize,
+    /// Directory levels searched below the extraction dir for `tasks/`/`agent_code/`.
+    pub archive_root_depth: usize,
+    /// Agent language used when `agent_code/` neither declares one nor has
+    /// a recognisable file extension.
+    pub default_agent_language: String,
     pub workspace_base: PathBuf,
     pub bittensor_netuid: u16,
     pub min_validator_stake_tao: f64,
     pub validator_refresh_secs: u64,
+    /// Consecutive failed whitelist refreshes before the executor logs an
+    /// error and reports itself not ready. 0 disables the breaker.
+    pub whitelist_failure_threshold: u32,
     pub consensus_threshold: f64,
+    pub consensus_mode: crate::consensus::ConsensusMode,
+    /// Votes required when `consensus_mode` is absolute.
+    pub consensus_absolute: usize,
     pub consensus_ttl_secs: u64,
+    /// How often expired consensus entries are swept.
+    pub consensus_reap_interval_secs: u64,
+    /// How long a request nonce stays burned against replay.
+    pub nonce_ttl_secs: u64,
+    /// How often expired nonces are swept.
+    pub nonce_reap_interval_secs: u64,
+    /// sr25519 signing context validators sign `hotkey + nonce` under. May
+    /// be empty for wallets that sign without one.
+    pub signing_context: String,
+    /// Also accept signatures over the polkadot-js `<Bytes>...</Bytes>`
+    /// wrapping of the message.
+    pub signing_accept_bytes_wrapped: bool,
     pub max_pending_consensus: usize,
+    /// Per-hotkey `/submit` calls allowed per minute; 0 disables the limit.
+    pub submit_rate_per_min: usize,
     pub sudo_password: Option<String>,
+    /// Bearer token for `/admin/*`; the routes answer 404 when unset.
+    pub admin_token: Option<String>,
     pub trusted_validators: Vec<String>,
+    pub allowed_git_hosts: Vec<String>,
+    /// Token sent when cloning https repos on hosts in `allowed_git_hosts`.
+    pub git_token: Option<String>,
+    pub denied_repos: Vec<String>,
+    /// Only run `checks.txt` lines whose programs are in `checks_allowlist`.
+    pub checks_restricted: bool,
+    pub checks_allowlist: Vec<String>,
+    /// When false, endpoints that load tasks from HuggingFace are refused.
+    pub allow_huggingface_source: bool,
+    /// Attempts per HuggingFace dataset-viewer page.
+    pub hf_fetch_max_attempts: u32,
     pub basilica_api_token: Option<String>,
     pub basilica_ssh_key: Option<String>,
+    /// NATS server to publish batch events to (requires the `nats` feature).
+    pub nats_url: Option<String>,
+    /// Receives a POST when a batch starts from consensus and when it
+    /// completes.
+    pub consensus_webhook_url: Option<String>,
+    #[cfg(feature = "nats")]
+    pub event_subject_prefix: String,
+    /// Hex sr25519 seed used to sign batch results and submit acks.
+    pub response_signing_key: Option<String>,
 }
 
 impl Config {
@@ -49,20 +195,122 @@ impl Config {
             ));
         }
 
+        let consensus_mode = env_parse("CONSENSUS_MODE", crate::consensus::ConsensusMode::Fraction);
+        let consensus_absolute: usize = env_parse("CONSENSUS_ABSOLUTE", 1);
+        if consensus_mode == crate::consensus::ConsensusMode::Absolute && consensus_absolute == 0 {
+            return Err("CONSENSUS_ABSOLUTE must be at least 1".to_string());
+        }
+
+        let agent_pty = env_parse("AGENT_PTY", false);
+        let agent_prompt_delivery = env_parse(
+            "AGENT_PROMPT_DELIVERY",
+            crate::executor::PromptDelivery::File,
+        );
+        if agent_pty && agent_prompt_delivery == crate::executor::PromptDelivery::Stdin {
+            return Err("AGENT_PROMPT_DELIVERY=stdin cannot be used with AGENT_PTY".to_string());
+        }
+
+        let default_agent_language = match std::env::var("DEFAULT_AGENT_LANGUAGE") {
+            Ok(v) if !v.trim().is_empty() => crate::task::normalize_agent_language(&v)
+                .ok_or_else(|| format!("DEFAULT_AGENT_LANGUAGE: unknown language '{}'", v))?,
+            _ => crate::task::DEFAULT_AGENT_LANGUAGE,
+        }
+        .to_string();
+
+        let run_as_user =
+            crate::sandbox::RunAsUser::resolve(&std::env::var("RUN_AS_USER").unwrap_or_default())?;
+
+        let workspace_base = PathBuf::from(
+            std::env::var("WORKSPACE_BASE").unwrap_or_else(|_| DEFAULT_WORKSPACE_BASE.into()),
+        );
+        let shared_cache_dir = std::env::var("SHARED_CACHE_DIR")
+            .ok()
+            .filter(|s| !s.is_empty())
+            .map(PathBuf::from);
+        // Work dirs under the workspace are reaped and handed to RUN_AS_USER;
+        // the cache must outlive them and stay out of the agent's reach.
+        if let Some(ref dir) = shared_cache_dir {
+            if dir.starts_with(&workspace_base) {
+                return Err(format!(
+                    "SHARED_CACHE_DIR {} must not be inside WORKSPACE_BASE {}",
+                    dir.display(),
+                    workspace_base.display()
+                ));
+            }
+        }
+
+        let max_concurrent_tasks = std::env::var("CONCURRENTLY_TASKS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or_else(|| env_parse("MAX_CONCURRENT_TASKS", DEFAULT_MAX_CONCURRENT));
+
         Ok(Self {
             port: env_parse("PORT", DEFAULT_PORT),
             session_ttl_secs: env_parse("SESSION_TTL_SECS", DEFAULT_SESSION_TTL),
-            max_concurrent_tasks: std::env::var("CONCURRENTLY_TASKS")
-                .ok()
-                .and_then(|v| v.parse().ok())
-                .unwrap_or_else(|| env_parse("MAX_CONCURRENT_TASKS", DEFAULT_MAX_CONCURRENT)),
+            max_concurrent_tasks,
+            max_concurrent_batches: env_parse("MAX_CONCURRENT_BATCHES", 1usize).max(1),
+            batch_queue_depth: env_parse("BATCH_QUEUE_DEPTH", 0usize),
+            default_consensus_concurrency: env_parse(
+                "DEFAULT_CONSENSUS_CONCURRENCY",
+                max_concurrent_tasks,
+            ),
             clone_timeout_secs: env_parse("CLONE_TIMEOUT_SECS", DEFAULT_CLONE_TIMEOUT),
+            repo_precheck_secs: env_parse("REPO_PRECHECK_SECS", 0),
+            install_timeout_secs: env_parse("INSTALL_TIMEOUT_SECS", DEFAULT_INSTALL_TIMEOUT),
+            warmup_commands: std::env::var("BATCH_WARMUP_COMMANDS")
+                .unwrap_or_default()
+                .lines()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            shared_cache_dir,
+            auto_test: env_parse("AUTO_TEST", false),
+            test_network: env_parse("TEST_NETWORK", crate::sandbox::NetworkMode::Allow),
+            sandbox_memory_mb: env_parse("SANDBOX_MEMORY_MB", 0),
+            sandbox_nice: env_parse("SANDBOX_NICE", 0),
+            test_cpu_time_secs: env_parse("TEST_CPU_TIME_SECS", 0),
+            test_max_processes: env_parse("TEST_MAX_PROCESSES", 0),
+            kill_grace_secs: env_parse("KILL_GRACE_SECS", 5),
+            test_script_concurrency: env_parse("TEST_SCRIPT_CONCURRENCY", 1),
+            run_as_user,
             agent_timeout_secs: env_parse("AGENT_TIMEOUT_SECS", DEFAULT_AGENT_TIMEOUT),
+            agent_pty,
+            agent_prompt_delivery,
+            agent_runners: crate::executor::AgentRunners::from_env(),
+            reward_aggregation: env_parse(
+                "REWARD_AGGREGATION",
+                crate::session::RewardAggregation::Mean,
+            ),
+            task_order: env_parse("TASK_ORDER", crate::session::TaskOrder::Archive),
+            duplicate_tasks: env_parse(
+                "DUPLICATE_TASKS",
+                crate::task::DuplicateTaskPolicy::Coalesce,
+            ),
+            batch_journal: env_parse("BATCH_JOURNAL", false),
+            abort_on_install_failure: env_parse("ABORT_ON_INSTALL_FAILURE", false),
+            keep_workdir: env_parse("KEEP_WORKDIR", crate::cleanup::KeepWorkdir::Never),
             test_timeout_secs: env_parse("TEST_TIMEOUT_SECS", DEFAULT_TEST_TIMEOUT),
+            max_task_timeout_secs: env_parse("MAX_TASK_TIMEOUT_SECS", DEFAULT_MAX_TASK_TIMEOUT),
+            batch_timeout_secs: env_parse("BATCH_TIMEOUT_SECS", DEFAULT_BATCH_TIMEOUT),
+            batch_stall_secs: env_parse("BATCH_STALL_SECS", 0),
+            batch_retry_budget: env_parse("BATCH_RETRY_BUDGET", 0),
+            min_agent_runtime_ms: env_parse("MIN_AGENT_RUNTIME_MS", DEFAULT_MIN_AGENT_RUNTIME_MS),
+            drain_timeout_secs: env_parse("DRAIN_TIMEOUT_SECS", DEFAULT_DRAIN_TIMEOUT_SECS),
+            task_disk_quota_mb: env_parse("TASK_DISK_QUOTA_MB", DEFAULT_TASK_DISK_QUOTA_MB),
             max_archive_bytes: env_parse("MAX_ARCHIVE_BYTES", DEFAULT_MAX_ARCHIVE_BYTES),
-            workspace_base: PathBuf::from(
-                std::env::var("WORKSPACE_BASE").unwrap_or_else(|_| DEFAULT_WORKSPACE_BASE.into()),
+            max_task_output_bytes: env_parse(
+                "MAX_TASK_OUTPUT_BYTES",
+                DEFAULT_MAX_TASK_OUTPUT_BYTES,
+            ),
+            retain_batch_agent_code: env_parse("RETAIN_BATCH_AGENT_CODE", false),
+            archive_download_timeout_secs: env_parse("ARCHIVE_DOWNLOAD_TIMEOUT_SECS", 120),
+            max_tasks_per_batch: env_parse("MAX_TASKS_PER_BATCH", DEFAULT_MAX_TASKS_PER_BATCH),
+            archive_root_depth: env_parse(
+                "ARCHIVE_ROOT_DEPTH",
+                crate::task::DEFAULT_ARCHIVE_ROOT_DEPTH,
             ),
+            default_agent_language,
+            workspace_base,
             bittensor_netuid: env_parse("BITTENSOR_NETUID", DEFAULT_BITTENSOR_NETUID),
             min_validator_stake_tao: env_parse(
                 "MIN_VALIDATOR_STAKE_TAO",
@@ -72,15 +320,39 @@ impl Config {
                 "VALIDATOR_REFRESH_SECS",
                 DEFAULT_VALIDATOR_REFRESH_SECS,
             ),
+            whitelist_failure_threshold: env_parse(
+                "WHITELIST_FAILURE_THRESHOLD",
+                DEFAULT_WHITELIST_FAILURE_THRESHOLD,
+            ),
             consensus_threshold,
+            consensus_mode,
+            consensus_absolute,
             consensus_ttl_secs: env_parse("CONSENSUS_TTL_SECS", DEFAULT_CONSENSUS_TTL_SECS),
+            consensus_reap_interval_secs: env_parse(
+                "CONSENSUS_REAP_INTERVAL_SECS",
+                DEFAULT_CONSENSUS_REAP_INTERVAL_SECS,
+            )
+            .max(1),
+            nonce_ttl_secs: env_parse("NONCE_TTL_SECS", DEFAULT_NONCE_TTL_SECS).max(1),
+            nonce_reap_interval_secs: env_parse(
+                "NONCE_REAP_INTERVAL_SECS",
+                DEFAULT_NONCE_REAP_INTERVAL_SECS,
+            )
+            .max(1),
+            signing_context: env_parse(
+                "SIGNING_CONTEXT",
+                crate::auth::DEFAULT_SIGNING_CONTEXT.to_string(),
+            ),
+            signing_accept_bytes_wrapped: env_parse("SIGNING_ACCEPT_BYTES_WRAPPED", true),
             max_pending_consensus: env_parse(
                 "MAX_PENDING_CONSENSUS",
                 DEFAULT_MAX_PENDING_CONSENSUS,
             ),
+            submit_rate_per_min: env_parse("SUBMIT_RATE_PER_MIN", DEFAULT_SUBMIT_RATE_PER_MIN),
             sudo_password: std::env::var("SUDO_PASSWORD")
                 .ok()
                 .filter(|s| !s.is_empty()),
+            admin_token: std::env::var("ADMIN_TOKEN").ok().filter(|s| !s.is_empty()),
             basilica_api_token: std::env::var("BASILICA_API_TOKEN")
                 .ok()
                 .filter(|s| !s.is_empty()),
@@ -93,9 +365,124 @@ impl Config {
                 .map(|s| s.trim().to_string())
                 .filter(|s| !s.is_empty())
                 .collect(),
+            allowed_git_hosts: std::env::var("ALLOWED_GIT_HOSTS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            git_token: std::env::var("GIT_TOKEN").ok().filter(|s| !s.is_empty()),
+            denied_repos: std::env::var("DENIED_REPOS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+                .collect(),
+            checks_restricted: env_parse("CHECKS_RESTRICTED", false),
+            checks_allowlist: checks_allowlist(),
+            allow_huggingface_source: env_parse("ALLOW_HUGGINGFACE_SOURCE", true),
+            hf_fetch_max_attempts: env_parse(
+                "HF_FETCH_MAX_ATTEMPTS",
+                crate::swe_forge::client::DEFAULT_MAX_ATTEMPTS,
+            ),
+            nats_url: std::env::var("NATS_URL").ok().filter(|s| !s.is_empty()),
+            consensus_webhook_url: std::env::var("CONSENSUS_WEBHOOK_URL")
+                .ok()
+                .filter(|s| !s.is_empty()),
+            #[cfg(feature = "nats")]
+            event_subject_prefix: std::env::var("EVENT_SUBJECT_PREFIX")
+                .unwrap_or_else(|_| DEFAULT_EVENT_SUBJECT_PREFIX.into()),
+            response_signing_key: std::env::var("RESPONSE_SIGNING_KEY")
+                .ok()
+                .filter(|s| !s.is_empty()),
         })
     }
 
+    /// Built-in defaults, independent of the process environment.
+    #[cfg(test)]
+    pub fn test_default() -> Self {
+        Self {
+            port: DEFAULT_PORT,
+            session_ttl_secs: DEFAULT_SESSION_TTL,
+            max_concurrent_tasks: DEFAULT_MAX_CONCURRENT,
+            max_concurrent_batches: 1,
+            batch_queue_depth: 0,
+            default_consensus_concurrency: DEFAULT_MAX_CONCURRENT,
+            clone_timeout_secs: DEFAULT_CLONE_TIMEOUT,
+            repo_precheck_secs: 0,
+            install_timeout_secs: DEFAULT_INSTALL_TIMEOUT,
+            warmup_commands: Vec::new(),
+            shared_cache_dir: None,
+            auto_test: false,
+            test_network: crate::sandbox::NetworkMode::Allow,
+            sandbox_memory_mb: 0,
+            sandbox_nice: 0,
+            test_cpu_time_secs: 0,
+            test_max_processes: 0,
+            kill_grace_secs: 5,
+            test_script_concurrency: 1,
+            run_as_user: None,
+            agent_timeout_secs: DEFAULT_AGENT_TIMEOUT,
+            agent_pty: false,
+            agent_prompt_delivery: crate::executor::PromptDelivery::File,
+            agent_runners: crate::executor::AgentRunners::default(),
+            reward_aggregation: crate::session::RewardAggregation::Mean,
+            task_order: crate::session::TaskOrder::Archive,
+            duplicate_tasks: crate::task::DuplicateTaskPolicy::Coalesce,
+            batch_journal: false,
+            abort_on_install_failure: false,
+            keep_workdir: crate::cleanup::KeepWorkdir::Never,
+            test_timeout_secs: DEFAULT_TEST_TIMEOUT,
+            max_task_timeout_secs: DEFAULT_MAX_TASK_TIMEOUT,
+            batch_timeout_secs: DEFAULT_BATCH_TIMEOUT,
+            batch_stall_secs: 0,
+            batch_retry_budget: 0,
+            min_agent_runtime_ms: DEFAULT_MIN_AGENT_RUNTIME_MS,
+            drain_timeout_secs: DEFAULT_DRAIN_TIMEOUT_SECS,
+            task_disk_quota_mb: DEFAULT_TASK_DISK_QUOTA_MB,
+            max_archive_bytes: DEFAULT_MAX_ARCHIVE_BYTES,
+            max_task_output_bytes: DEFAULT_MAX_TASK_OUTPUT_BYTES,
+            retain_batch_agent_code: false,
+            archive_download_timeout_secs: 120,
+            max_tasks_per_batch: DEFAULT_MAX_TASKS_PER_BATCH,
+            archive_root_depth: crate::task::DEFAULT_ARCHIVE_ROOT_DEPTH,
+            default_agent_language: crate::task::DEFAULT_AGENT_LANGUAGE.to_string(),
+            workspace_base: PathBuf::from(DEFAULT_WORKSPACE_BASE),
+            bittensor_netuid: DEFAULT_BITTENSOR_NETUID,
+            min_validator_stake_tao: DEFAULT_MIN_VALIDATOR_STAKE_TAO,
+            validator_refresh_secs: DEFAULT_VALIDATOR_REFRESH_SECS,
+            whitelist_failure_threshold: DEFAULT_WHITELIST_FAILURE_THRESHOLD,
+            consensus_threshold: DEFAULT_CONSENSUS_THRESHOLD,
+            consensus_mode: crate::consensus::ConsensusMode::Fraction,
+            consensus_absolute: 1,
+            consensus_ttl_secs: DEFAULT_CONSENSUS_TTL_SECS,
+            consensus_reap_interval_secs: DEFAULT_CONSENSUS_REAP_INTERVAL_SECS,
+            nonce_ttl_secs: DEFAULT_NONCE_TTL_SECS,
+            nonce_reap_interval_secs: DEFAULT_NONCE_REAP_INTERVAL_SECS,
+            signing_context: crate::auth::DEFAULT_SIGNING_CONTEXT.to_string(),
+            signing_accept_bytes_wrapped: true,
+            max_pending_consensus: DEFAULT_MAX_PENDING_CONSENSUS,
+            submit_rate_per_min: DEFAULT_SUBMIT_RATE_PER_MIN,
+            sudo_password: None,
+            admin_token: None,
+            trusted_validators: Vec::new(),
+            basilica_api_token: None,
+            basilica_ssh_key: None,
+            allowed_git_hosts: Vec::new(),
+            git_token: None,
+            checks_restricted: false,
+            checks_allowlist: checks_allowlist(),
+            denied_repos: Vec::new(),
+            allow_huggingface_source: true,
+            hf_fetch_max_attempts: crate::swe_forge::client::DEFAULT_MAX_ATTEMPTS,
+            nats_url: None,
+            consensus_webhook_url: None,
+            #[cfg(feature = "nats")]
+            event_subject_prefix: DEFAULT_EVENT_SUBJECT_PREFIX.to_string(),
+            response_signing_key: None,
+        }
+    }
+
     pub fn print_banner(&self) {
         tracing::info!("╔══════════════════════════════════════════════════╗");
         tracing::info!(
@@ -113,13 +500,23 @@ impl Config {
             "║  Whitelist refresh: {:<25}s ║",
             self.validator_refresh_secs
         );
-        tracing::info!("║  Consensus thresh:  {:<28}║", self.consensus_threshold);
+        match self.consensus_mode {
+            crate::consensus::ConsensusMode::Fraction => {
+                tracing::info!("║  Consensus thresh:  {:<28}║", self.consensus_threshold)
+            }
+            crate::consensus::ConsensusMode::Absolute => {
+                tracing::info!("║  Consensus votes:   {:<28}║", self.consensus_absolute)
+            }
+        }
         tracing::info!("║  Consensus TTL:     {:<25}s ║", self.consensus_ttl_secs);
         tracing::info!("║  Max concurrent:    {:<28}║", self.max_concurrent_tasks);
+        tracing::info!("║  Max batches:       {:<28}║", self.max_concurrent_batches);
         tracing::info!("║  Session TTL:       {:<25}s ║", self.session_ttl_secs);
         tracing::info!("║  Clone timeout:     {:<25}s ║", self.clone_timeout_secs);
+        tracing::info!("║  Install timeout:   {:<25}s ║", self.install_timeout_secs);
         tracing::info!("║  Agent timeout:     {:<25}s ║", self.agent_timeout_secs);
         tracing::info!("║  Test timeout:      {:<25}s ║", self.test_timeout_secs);
+        tracing::info!("║  Batch timeout:     {:<25}s ║", self.batch_timeout_secs);
         tracing::info!(
             "║  Workspace:         {:<28}║",
             self.workspace_base.display()
@@ -128,6 +525,24 @@ impl Config {
     }
 }
 
+/// `CHECKS_ALLOWLIST` (comma-separated), or the built-in list when unset.
+fn checks_allowlist() -> Vec<String> {
+    let list: Vec<String> = std::env::var("CHECKS_ALLOWLIST")
+        .unwrap_or_default()
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .collect();
+    if list.is_empty() {
+        crate::task::checks::DEFAULT_CHECKS_ALLOWLIST
+            .iter()
+            .map(|s| s.to_string())
+            .collect()
+    } else {
+        list
+    }
+}
+
 fn env_parse<T: std::str::FromStr>(key: &str, default: T) -> T {
     std::env::var(key)
         .ok()
@@ -148,8 +563,12 @@ mod tests {
         let cfg = Config::from_env().expect("default config should be valid");
         assert_eq!(cfg.port, DEFAULT_PORT);
         assert_eq!(cfg.max_concurrent_tasks, 6);
+        assert_eq!(cfg.default_consensus_concurrency, 6);
         assert_eq!(cfg.bittensor_netuid, 100);
         assert!((cfg.consensus_threshold - 0.5).abs() < f64::EPSILON);
+        assert!(cfg.denied_repos.is_empty());
+        assert_eq!(cfg.batch_timeout_secs, 0);
+        assert!(cfg.allow_huggingface_source);
     }
 
     #[test]
@@ -157,6 +576,39 @@ mod tests {
         assert_eq!(env_parse::<u16>("NONEXISTENT_VAR_XYZ", 42), 42);
     }
 
+    #[test]
+    fn test_config_rejects_zero_absolute_consensus() {
+        let _lock = ENV_LOCK.lock().unwrap();
+        std::env::set_var("CONSENSUS_MODE", "absolute");
+        std::env::set_var("CONSENSUS_ABSOLUTE", "0");
+        let result = Config::from_env();
+        std::env::set_var("CONSENSUS_ABSOLUTE", "3");
+        let cfg = Config::from_env();
+        std::env::remove_var("CONSENSUS_MODE");
+        std::env::remove_var("CONSENSUS_ABSOLUTE");
+        assert!(result.is_err());
+        let cfg = cfg.unwrap();
+        assert_eq!(
+            cfg.consensus_mode,
+            crate::consensus::ConsensusMode::Absolute
+        );
+        assert_eq!(cfg.consensus_absolute, 3);
+    }
+
+    #[test]
+    fn test_config_rejects_shared_cache_in_workspace() {
+        let _lock = ENV_LOCK.lock().unwrap();
+        std::env::set_var("WORKSPACE_BASE", "/tmp/ws-test");
+        std::env::set_var("SHARED_CACHE_DIR", "/tmp/ws-test/cache");
+        let inside = Config::from_env();
+        std::env::set_var("SHARED_CACHE_DIR", "/tmp/ws-test-cache");
+        let outside = Config::from_env();
+        std::env::remove_var("WORKSPACE_BASE");
+        std::env::remove_var("SHARED_CACHE_DIR");
+        assert!(inside.is_err());
+        assert!(outside.is_ok());
+    }
+
     #[test]
     fn test_config_rejects_zero_threshold() {
         let _lock = ENV_LOCK.lock().unwrap();
@@ -1,16 +1,78 @@
 use dashmap::mapref::entry::Entry;
 use dashmap::DashMap;
 use std::collections::HashSet;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use tracing::{debug, info};
 
-const REAPER_INTERVAL_SECS: u64 = 30;
+use crate::metrics::Metrics;
 
 struct PendingConsensus {
     voters: HashSet<String>,
     created_at: Instant,
     concurrent_tasks: Option<usize>,
+    archive: Option<ArchiveMeta>,
+}
+
+/// Lightweight description of a pending archive, kept instead of its bytes.
+#[derive(Debug, Clone)]
+struct ArchiveMeta {
+    size_bytes: usize,
+    blake2b_256: String,
+    task_count: Option<usize>,
+}
+
+/// What `GET /consensus/{archive_hash}` reports about a pending entry.
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PendingEntryInfo {
+    pub archive_hash: String,
+    pub size_bytes: Option<usize>,
+    pub blake2b_256: Option<String>,
+    pub task_count: Option<usize>,
+    pub votes: usize,
+    pub age_secs: u64,
+}
+
+/// How many votes an archive needs before it runs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConsensusMode {
+    /// `ceil(total_validators * CONSENSUS_THRESHOLD)`.
+    #[default]
+    Fraction,
+    /// `CONSENSUS_ABSOLUTE` votes, whatever the size of the validator set.
+    Absolute,
+}
+
+impl std::str::FromStr for ConsensusMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "fraction" => Ok(ConsensusMode::Fraction),
+            "absolute" => Ok(ConsensusMode::Absolute),
+            other => Err(format!("unknown consensus mode '{}'", other)),
+        }
+    }
+}
+
+/// Votes required with `total_validators` in the whitelist, clamped to
+/// `1..=total_validators`.
+pub fn required_votes(
+    mode: ConsensusMode,
+    threshold: f64,
+    absolute: usize,
+    total_validators: usize,
+) -> usize {
+    let required = match mode {
+        ConsensusMode::Fraction => {
+            let required_f = (total_validators as f64 * threshold).ceil();
+            required_f.min(usize::MAX as f64) as usize
+        }
+        ConsensusMode::Absolute => absolute,
+    };
+    required.min(total_validators).max(1)
 }
 
 pub enum ConsensusStatus {
@@ -29,21 +91,29 @@ pub enum ConsensusStatus {
         required: usize,
         total_validators: usize,
     },
+    /// The vote would have completed consensus but there was no room to
+    /// start the batch. It was not recorded and the entry is left as is.
+    Busy,
 }
 
 pub struct ConsensusManager {
     pending: DashMap<String, PendingConsensus>,
     max_pending: usize,
+    metrics: Arc<Metrics>,
 }
 
 impl ConsensusManager {
-    pub fn new(max_pending: usize) -> Arc<Self> {
+    pub fn new(max_pending: usize, metrics: Arc<Metrics>) -> Arc<Self> {
         Arc::new(Self {
             pending: DashMap::new(),
             max_pending,
+            metrics,
         })
     }
 
+    /// Count `hotkey`'s vote for `archive_hash`. With `can_start` false
+    /// (nowhere to run or queue the batch) a vote that would complete
+    /// consensus is refused as `Busy` instead.
     pub fn record_vote(
         &self,
         archive_hash: &str,
@@ -51,6 +121,50 @@ impl ConsensusManager {
         concurrent_tasks: Option<usize>,
         required: usize,
         total_validators: usize,
+        can_start: bool,
+    ) -> ConsensusStatus {
+        let status = self.tally_vote(
+            archive_hash,
+            hotkey,
+            concurrent_tasks,
+            required,
+            total_validators,
+            can_start,
+        );
+        match status {
+            ConsensusStatus::AlreadyVoted { .. } | ConsensusStatus::Busy => {}
+            ConsensusStatus::Pending { .. } => {
+                self.metrics
+                    .consensus_votes_total
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+            ConsensusStatus::Reached { .. } => {
+                self.metrics
+                    .consensus_votes_total
+                    .fetch_add(1, Ordering::Relaxed);
+                self.metrics
+                    .consensus_reached_total
+                    .fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.update_pending_gauge();
+        status
+    }
+
+    fn update_pending_gauge(&self) {
+        self.metrics
+            .consensus_pending
+            .store(self.pending.len() as u64, Ordering::Relaxed);
+    }
+
+    fn tally_vote(
+        &self,
+        archive_hash: &str,
+        hotkey: &str,
+        concurrent_tasks: Option<usize>,
+        required: usize,
+        total_validators: usize,
+        can_start: bool,
     ) -> ConsensusStatus {
         match self.pending.entry(archive_hash.to_string()) {
             Entry::Occupied(mut entry) => {
@@ -63,6 +177,9 @@ impl ConsensusManager {
                         total_validators,
                     };
                 }
+                if !can_start && pending.voters.len() + 1 >= required {
+                    return ConsensusStatus::Busy;
+                }
 
                 pending.voters.insert(hotkey.to_string());
                 let votes = pending.voters.len();
@@ -84,6 +201,9 @@ impl ConsensusManager {
                 }
             }
             Entry::Vacant(entry) => {
+                if !can_start && required <= 1 {
+                    return ConsensusStatus::Busy;
+                }
                 info!(archive_hash, "New consensus entry created");
                 let mut voters = HashSet::new();
                 voters.insert(hotkey.to_string());
@@ -101,6 +221,7 @@ impl ConsensusManager {
                         voters,
                         created_at: Instant::now(),
                         concurrent_tasks,
+                        archive: None,
                     });
                     ConsensusStatus::Pending {
                         votes,
@@ -112,6 +233,44 @@ impl ConsensusManager {
         }
     }
 
+    /// Record the archive's size and BLAKE2b-256 digest, computed by the
+    /// caller while the upload streamed in. Only the first upload is kept,
+    /// and nothing is recorded if the entry is gone (e.g. consensus was
+    /// already reached).
+    pub fn attach_archive_meta(&self, archive_hash: &str, size_bytes: usize, blake2b_256: String) {
+        if let Some(mut entry) = self.pending.get_mut(archive_hash) {
+            if entry.archive.is_none() {
+                entry.archive = Some(ArchiveMeta {
+                    size_bytes,
+                    blake2b_256,
+                    task_count: None,
+                });
+            }
+        }
+    }
+
+    /// Record how many tasks the archive holds, as counted by the precheck.
+    pub fn set_task_count(&self, archive_hash: &str, task_count: usize) {
+        if let Some(mut entry) = self.pending.get_mut(archive_hash) {
+            if let Some(ref mut meta) = entry.archive {
+                meta.task_count = Some(task_count);
+            }
+        }
+    }
+
+    pub fn entry_info(&self, archive_hash: &str) -> Option<PendingEntryInfo> {
+        let entry = self.pending.get(archive_hash)?;
+        let meta = entry.archive.as_ref();
+        Some(PendingEntryInfo {
+            archive_hash: archive_hash.to_string(),
+            size_bytes: meta.map(|m| m.size_bytes),
+            blake2b_256: meta.map(|m| m.blake2b_256.clone()),
+            task_count: meta.and_then(|m| m.task_count),
+            votes: entry.voters.len(),
+            age_secs: entry.created_at.elapsed().as_secs(),
+        })
+    }
+
     #[cfg(test)]
     fn pending_count(&self) -> usize {
         self.pending.len()
@@ -121,28 +280,38 @@ impl ConsensusManager {
         self.pending.len() >= self.max_pending
     }
 
-    pub async fn reaper_loop(self: Arc<Self>, ttl_secs: u64) {
-        let mut interval = tokio::time::interval(Duration::from_secs(REAPER_INTERVAL_SECS));
+    pub async fn reaper_loop(self: Arc<Self>, ttl_secs: u64, interval_secs: u64) {
+        let mut interval = tokio::time::interval(Duration::from_secs(interval_secs));
         loop {
             interval.tick().await;
-            let cutoff = Instant::now() - Duration::from_secs(ttl_secs);
-            let before = self.pending.len();
-            self.pending.retain(|hash, entry| {
-                let keep = entry.created_at > cutoff;
-                if !keep {
-                    debug!(archive_hash = %hash, "Expired pending consensus entry");
-                }
-                keep
-            });
-            let removed = before.saturating_sub(self.pending.len());
-            if removed > 0 {
-                info!(
-                    removed,
-                    remaining = self.pending.len(),
-                    "Reaped expired consensus entries"
-                );
+            self.reap_expired(Duration::from_secs(ttl_secs));
+        }
+    }
+
+    /// Drop pending entries older than `ttl` and return how many were removed.
+    fn reap_expired(&self, ttl: Duration) -> usize {
+        let cutoff = Instant::now() - ttl;
+        let before = self.pending.len();
+        self.pending.retain(|hash, entry| {
+            let keep = entry.created_at > cutoff;
+            if !keep {
+                debug!(archive_hash = %hash, "Expired pending consensus entry");
             }
+            keep
+        });
+        let removed = before.saturating_sub(self.pending.len());
+        if removed > 0 {
+            info!(
+                removed,
+                remaining = self.pending.len(),
+                "Reaped expired consensus entries"
+            );
+            self.metrics
+                .consensus_expired_total
+                .fetch_add(removed as u64, Ordering::Relaxed);
         }
+        self.update_pending_gauge();
+        removed
     }
 }
 
@@ -150,10 +319,22 @@ impl ConsensusManager {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_required_votes() {
+        assert_eq!(required_votes(ConsensusMode::Fraction, 0.5, 3, 5), 3);
+        assert_eq!(required_votes(ConsensusMode::Fraction, 0.5, 3, 4), 2);
+        assert_eq!(required_votes(ConsensusMode::Fraction, 0.5, 3, 0), 1);
+        // Absolute stays put as the set changes, until the set is too small.
+        assert_eq!(required_votes(ConsensusMode::Absolute, 0.5, 3, 10), 3);
+        assert_eq!(required_votes(ConsensusMode::Absolute, 0.5, 3, 4), 3);
+        assert_eq!(required_votes(ConsensusMode::Absolute, 0.5, 3, 2), 2);
+        assert_eq!(required_votes(ConsensusMode::Absolute, 0.5, 0, 2), 1);
+    }
+
     #[test]
     fn test_single_vote_does_not_trigger() {
-        let mgr = ConsensusManager::new(100);
-        let status = mgr.record_vote("abc123", "hotkey1", Some(8), 2, 3);
+        let mgr = ConsensusManager::new(100, Metrics::new());
+        let status = mgr.record_vote("abc123", "hotkey1", Some(8), 2, 3, true);
         assert!(matches!(
             status,
             ConsensusStatus::Pending {
@@ -166,17 +347,34 @@ mod tests {
 
     #[test]
     fn test_reaching_threshold_triggers() {
-        let mgr = ConsensusManager::new(100);
-        mgr.record_vote("abc123", "hotkey1", Some(8), 2, 3);
-        let status = mgr.record_vote("abc123", "hotkey2", Some(8), 2, 3);
+        let mgr = ConsensusManager::new(100, Metrics::new());
+        mgr.record_vote("abc123", "hotkey1", Some(8), 2, 3, true);
+        let status = mgr.record_vote("abc123", "hotkey2", Some(8), 2, 3, true);
         assert!(matches!(status, ConsensusStatus::Reached { votes: 2, .. }));
     }
 
+    #[test]
+    fn test_busy_vote_does_not_complete_consensus() {
+        let mgr = ConsensusManager::new(100, Metrics::new());
+        mgr.record_vote("abc123", "hotkey1", Some(8), 2, 3, false);
+        let status = mgr.record_vote("abc123", "hotkey2", Some(8), 2, 3, false);
+        assert!(matches!(status, ConsensusStatus::Busy));
+        assert_eq!(mgr.pending_count(), 1);
+
+        let status = mgr.record_vote("abc123", "hotkey2", Some(8), 2, 3, true);
+        assert!(matches!(status, ConsensusStatus::Reached { votes: 2, .. }));
+        assert!(matches!(
+            mgr.record_vote("solo", "hotkey1", None, 1, 1, false),
+            ConsensusStatus::Busy
+        ));
+        assert_eq!(mgr.pending_count(), 0);
+    }
+
     #[test]
     fn test_duplicate_votes_no_double_count() {
-        let mgr = ConsensusManager::new(100);
-        mgr.record_vote("abc123", "hotkey1", Some(8), 3, 5);
-        let status = mgr.record_vote("abc123", "hotkey1", Some(8), 3, 5);
+        let mgr = ConsensusManager::new(100, Metrics::new());
+        mgr.record_vote("abc123", "hotkey1", Some(8), 3, 5, true);
+        let status = mgr.record_vote("abc123", "hotkey1", Some(8), 3, 5, true);
         assert!(matches!(
             status,
             ConsensusStatus::AlreadyVoted { votes: 1, .. }
@@ -185,21 +383,22 @@ mod tests {
 
     #[test]
     fn test_different_hashes_independent() {
-        let mgr = ConsensusManager::new(100);
-        mgr.record_vote("hash1", "hotkey1", Some(8), 2, 3);
-        mgr.record_vote("hash2", "hotkey1", Some(8), 2, 3);
+        let mgr = ConsensusManager::new(100, Metrics::new());
+        mgr.record_vote("hash1", "hotkey1", Some(8), 2, 3, true);
+        mgr.record_vote("hash2", "hotkey1", Some(8), 2, 3, true);
         assert_eq!(mgr.pending_count(), 2);
     }
 
     #[test]
     fn test_ttl_expiration() {
-        let mgr = ConsensusManager::new(100);
+        let mgr = ConsensusManager::new(100, Metrics::new());
         mgr.pending.insert(
             "old_hash".to_string(),
             PendingConsensus {
                 voters: HashSet::from(["hotkey1".to_string()]),
                 created_at: Instant::now() - Duration::from_secs(120),
                 concurrent_tasks: None,
+                archive: None,
             },
         );
         mgr.pending.insert(
@@ -208,6 +407,7 @@ mod tests {
                 voters: HashSet::from(["hotkey2".to_string()]),
                 created_at: Instant::now(),
                 concurrent_tasks: None,
+                archive: None,
             },
         );
 
@@ -221,7 +421,7 @@ mod tests {
 
     #[test]
     fn test_capacity_check() {
-        let mgr = ConsensusManager::new(2);
+        let mgr = ConsensusManager::new(2, Metrics::new());
         assert!(!mgr.is_at_capacity());
         mgr.pending.insert(
             "h1".to_string(),
@@ -229,6 +429,7 @@ mod tests {
                 voters: HashSet::new(),
                 created_at: Instant::now(),
                 concurrent_tasks: None,
+                archive: None,
             },
         );
         mgr.pending.insert(
@@ -237,6 +438,7 @@ mod tests {
                 voters: HashSet::new(),
                 created_at: Instant::now(),
                 concurrent_tasks: None,
+                archive: None,
             },
         );
         assert!(mgr.is_at_capacity());
@@ -244,17 +446,61 @@ mod tests {
 
     #[test]
     fn test_single_validator_consensus() {
-        let mgr = ConsensusManager::new(100);
-        let status = mgr.record_vote("hash1", "hotkey1", Some(4), 1, 1);
+        let mgr = ConsensusManager::new(100, Metrics::new());
+        let status = mgr.record_vote("hash1", "hotkey1", Some(4), 1, 1, true);
         assert!(matches!(status, ConsensusStatus::Reached { votes: 1, .. }));
         assert_eq!(mgr.pending_count(), 0);
     }
 
     #[test]
     fn test_entry_removed_after_consensus() {
-        let mgr = ConsensusManager::new(100);
-        mgr.record_vote("hash1", "hotkey1", Some(8), 2, 3);
-        mgr.record_vote("hash1", "hotkey2", Some(8), 2, 3);
+        let mgr = ConsensusManager::new(100, Metrics::new());
+        mgr.record_vote("hash1", "hotkey1", Some(8), 2, 3, true);
+        mgr.record_vote("hash1", "hotkey2", Some(8), 2, 3, true);
         assert_eq!(mgr.pending_count(), 0);
     }
+
+    #[test]
+    fn test_consensus_metrics() {
+        let metrics = Metrics::new();
+        let mgr = ConsensusManager::new(100, metrics.clone());
+
+        mgr.record_vote("hash1", "hotkey1", None, 2, 3, true);
+        mgr.record_vote("hash1", "hotkey1", None, 2, 3, true);
+        mgr.record_vote("hash2", "hotkey1", None, 2, 3, true);
+        assert_eq!(metrics.consensus_pending.load(Ordering::Relaxed), 2);
+        assert_eq!(metrics.consensus_votes_total.load(Ordering::Relaxed), 2);
+
+        mgr.record_vote("hash1", "hotkey2", None, 2, 3, true);
+        assert_eq!(metrics.consensus_reached_total.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.consensus_votes_total.load(Ordering::Relaxed), 3);
+        assert_eq!(metrics.consensus_pending.load(Ordering::Relaxed), 1);
+
+        assert_eq!(mgr.reap_expired(Duration::ZERO), 1);
+        assert_eq!(metrics.consensus_expired_total.load(Ordering::Relaxed), 1);
+        assert_eq!(metrics.consensus_pending.load(Ordering::Relaxed), 0);
+
+        let out = metrics.render_prometheus();
+        assert!(out.contains("term_executor_consensus_reached_total 1"));
+        assert!(out.contains("term_executor_consensus_expired_total 1"));
+    }
+
+    #[test]
+    fn test_entry_info_reports_metadata() {
+        let mgr = ConsensusManager::new(100, Metrics::new());
+        mgr.record_vote("hash1", "hotkey1", None, 3, 3, true);
+        mgr.attach_archive_meta("hash1", 4096, "ab".repeat(32));
+        mgr.record_vote("hash1", "hotkey2", None, 3, 3, true);
+        mgr.attach_archive_meta("hash1", 7, "ignored".into());
+
+        let info = mgr.entry_info("hash1").unwrap();
+        assert_eq!(info.size_bytes, Some(4096));
+        assert_eq!(info.votes, 2);
+        assert_eq!(info.blake2b_256.as_deref().map(str::len), Some(64));
+        assert!(info.task_count.is_none());
+        mgr.set_task_count("hash1", 5);
+        assert_eq!(mgr.entry_info("hash1").unwrap().task_count, Some(5));
+
+        assert!(mgr.entry_info("missing").is_none());
+    }
 }
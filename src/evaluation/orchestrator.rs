@@ -1,9 +1,31 @@
 use platform_challenge_sdk::error::ChallengeError;
 use platform_challenge_sdk::server::{EvaluationRequest, EvaluationResponse, ServerChallenge};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 use tracing::{error, info, warn};
 
+/// Time allowed for a request: the orchestrator's timeout, capped by the
+/// request's absolute `deadline` (unix seconds). `None` if the deadline has
+/// already passed.
+fn effective_timeout(timeout_secs: u64, deadline: Option<i64>, now_ms: i64) -> Option<Duration> {
+    let configured = Duration::from_secs(timeout_secs);
+    let Some(deadline) = deadline else {
+        return Some(configured);
+    };
+    let remaining_ms = deadline.saturating_mul(1000).saturating_sub(now_ms);
+    if remaining_ms <= 0 {
+        return None;
+    }
+    Some(configured.min(Duration::from_millis(remaining_ms as u64)))
+}
+
+fn deadline_passed(request_id: &str) -> ChallengeError {
+    ChallengeError::Timeout(format!(
+        "Evaluation {} deadline has already passed",
+        request_id
+    ))
+}
+
 pub struct Orchestrator<C: ServerChallenge> {
     challenge: Arc<C>,
     max_concurrent: usize,
@@ -56,7 +78,14 @@ impl<C: ServerChallenge + 'static> Orchestrator<C> {
             "Starting evaluation"
         );
 
-        let deadline = std::time::Duration::from_secs(self.timeout_secs);
+        let Some(deadline) = effective_timeout(
+            self.timeout_secs,
+            request.deadline,
+            chrono::Utc::now().timestamp_millis(),
+        ) else {
+            warn!(request_id = %request_id, "Evaluation deadline already passed");
+            return Err(deadline_passed(&request_id));
+        };
         let challenge = Arc::clone(&self.challenge);
 
         let result = tokio::time::timeout(deadline, challenge.evaluate(request)).await;
@@ -89,12 +118,13 @@ impl<C: ServerChallenge + 'static> Orchestrator<C> {
                 warn!(
                     challenge_id = %self.challenge.challenge_id(),
                     request_id = %request_id,
-                    timeout_secs = %self.timeout_secs,
+                    timeout_secs = %deadline.as_secs_f64(),
                     "Evaluation timed out"
                 );
                 Err(ChallengeError::Timeout(format!(
-                    "Evaluation {} timed out after {}s",
-                    request_id, self.timeout_secs
+                    "Evaluation {} timed out after {:.1}s",
+                    request_id,
+                    deadline.as_secs_f64()
                 )))
             }
         }
@@ -119,7 +149,13 @@ impl<C: ServerChallenge + 'static> Orchestrator<C> {
                     .map_err(|_| ChallengeError::Internal("Semaphore closed".to_string()))?;
 
                 let request_id = request.request_id.clone();
-                let deadline = std::time::Duration::from_secs(timeout_secs);
+                let Some(deadline) = effective_timeout(
+                    timeout_secs,
+                    request.deadline,
+                    chrono::Utc::now().timestamp_millis(),
+                ) else {
+                    return Err(deadline_passed(&request_id));
+                };
                 let start = Instant::now();
 
                 let result = tokio::time::timeout(deadline, challenge.evaluate(request)).await;
@@ -132,8 +168,9 @@ impl<C: ServerChallenge + 'static> Orchestrator<C> {
                     }
                     Ok(Err(e)) => Err(e),
                     Err(_) => Err(ChallengeError::Timeout(format!(
-                        "Evaluation {} timed out after {}s",
-                        request_id, timeout_secs
+                        "Evaluation {} timed out after {:.1}s",
+                        request_id,
+                        deadline.as_secs_f64()
                     ))),
                 }
             });
@@ -288,6 +325,50 @@ mod tests {
         assert!(matches!(result.unwrap_err(), ChallengeError::Timeout(_)));
     }
 
+    #[tokio::test]
+    async fn test_request_deadline_tighter_than_timeout() {
+        let orch = Orchestrator::new(MockChallenge::slow(5000)).with_timeout(600);
+        let mut req = test_request("req-deadline");
+        req.deadline = Some(chrono::Utc::now().timestamp() + 1);
+
+        let start = Instant::now();
+        let result = orch.evaluate(req).await;
+
+        assert!(matches!(result.unwrap_err(), ChallengeError::Timeout(_)));
+        assert!(start.elapsed() < Duration::from_secs(3));
+
+        let now_ms = 1_700_000_000_000;
+        assert_eq!(
+            effective_timeout(600, Some(1_700_000_010), now_ms),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            effective_timeout(5, Some(1_700_000_010), now_ms),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            effective_timeout(5, None, now_ms),
+            Some(Duration::from_secs(5))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_past_deadline_fails_fast() {
+        let orch = Orchestrator::new(MockChallenge::slow(5000));
+        let mut req = test_request("req-past");
+        req.deadline = Some(chrono::Utc::now().timestamp() - 10);
+
+        let start = Instant::now();
+        let result = orch.evaluate(req).await;
+        assert!(matches!(result.unwrap_err(), ChallengeError::Timeout(_)));
+        assert!(start.elapsed() < Duration::from_millis(500));
+
+        let mut req = test_request("req-past-batch");
+        req.deadline = Some(chrono::Utc::now().timestamp() - 10);
+        let results = orch.evaluate_batch(vec![req]).await;
+        assert!(matches!(results[0], Err(ChallengeError::Timeout(_))));
+    }
+
     #[tokio::test]
     async fn test_evaluate_batch_all_pass() {
         let orch = Orchestrator::new(MockChallenge::passing());
@@ -0,0 +1,177 @@
+use async_trait::async_trait;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::debug;
+
+use crate::metrics::Metrics;
+use crate::session::Batch;
+
+const PUBLISH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// External destination for batch/task events, in addition to WebSocket.
+#[async_trait]
+pub trait EventSink: Send + Sync {
+    async fn publish(&self, subject: String, payload: Vec<u8>) -> anyhow::Result<()>;
+}
+
+/// Copies every `WsEvent` of a batch to an `EventSink` on `{prefix}.{batch_id}`.
+/// Runs in its own task so a slow or unreachable broker never blocks the
+/// batch; events that can't be delivered are dropped and counted.
+pub struct EventForwarder {
+    sink: Arc<dyn EventSink>,
+    subject_prefix: String,
+    metrics: Arc<Metrics>,
+}
+
+impl EventForwarder {
+    pub fn new(sink: Arc<dyn EventSink>, subject_prefix: &str, metrics: Arc<Metrics>) -> Self {
+        Self {
+            sink,
+            subject_prefix: subject_prefix.trim_end_matches('.').to_string(),
+            metrics,
+        }
+    }
+
+    /// Subscribe to `batch` now and forward its events until the batch
+    /// completes or its channel closes.
+    pub fn forward(&self, batch: &Batch) -> tokio::task::JoinHandle<()> {
+        let mut rx = batch.events_tx.subscribe();
+        let subject = format!("{}.{}", self.subject_prefix, batch.id);
+        let sink = self.sink.clone();
+        let metrics = self.metrics.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let event = match rx.recv().await {
+                    Ok(event) => event,
+                    Err(RecvError::Lagged(n)) => {
+                        metrics.events_dropped.fetch_add(n, Ordering::Relaxed);
+                        continue;
+                    }
+                    Err(RecvError::Closed) => break,
+                };
+                let last = event.event == "batch_complete";
+
+                let delivered = match serde_json::to_vec(&event) {
+                    Ok(payload) => matches!(
+                        tokio::time::timeout(
+                            PUBLISH_TIMEOUT,
+                            sink.publish(subject.clone(), payload)
+                        )
+                        .await,
+                        Ok(Ok(()))
+                    ),
+                    Err(_) => false,
+                };
+                if !delivered {
+                    debug!(subject = %subject, event = %event.event, "Dropping event, sink unavailable");
+                    metrics.events_dropped.fetch_add(1, Ordering::Relaxed);
+                }
+
+                if last {
+                    break;
+                }
+            }
+        })
+    }
+}
+
+#[cfg(feature = "nats")]
+pub mod nats {
+    use super::*;
+
+    pub struct NatsSink {
+        client: async_nats::Client,
+    }
+
+    impl NatsSink {
+        /// Connect lazily: the client keeps retrying in the background, so a
+        /// broker that is down at startup doesn't prevent the executor from
+        /// running.
+        pub async fn connect(url: &str) -> anyhow::Result<Self> {
+            let client = async_nats::ConnectOptions::new()
+                .retry_on_initial_connect()
+                .connect(url)
+                .await?;
+            Ok(Self { client })
+        }
+    }
+
+    #[async_trait]
+    impl EventSink for NatsSink {
+        async fn publish(&self, subject: String, payload: Vec<u8>) -> anyhow::Result<()> {
+            self.client.publish(subject, payload.into()).await?;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::SessionManager;
+
+    #[derive(Default)]
+    struct MockBroker {
+        published: parking_lot::Mutex<Vec<(String, serde_json::Value)>>,
+        down: bool,
+    }
+
+    #[async_trait]
+    impl EventSink for MockBroker {
+        async fn publish(&self, subject: String, payload: Vec<u8>) -> anyhow::Result<()> {
+            if self.down {
+                anyhow::bail!("broker unavailable");
+            }
+            let value = serde_json::from_slice(&payload)?;
+            self.published.lock().push((subject, value));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_batch_events_published_to_subject() {
+        let broker = Arc::new(MockBroker::default());
+        let metrics = Metrics::new();
+        let forwarder = EventForwarder::new(broker.clone(), "term.events.", metrics.clone());
+        let batch = SessionManager::new(60).create_batch(1);
+
+        let handle = forwarder.forward(&batch);
+        batch
+            .emit_event("task_started", Some("t1"), serde_json::json!({}))
+            .await;
+        batch
+            .emit_event("batch_complete", None, serde_json::json!({"passed": 1}))
+            .await;
+        handle.await.unwrap();
+
+        let published = broker.published.lock();
+        assert_eq!(published.len(), 2);
+        let subject = format!("term.events.{}", batch.id);
+        assert!(published.iter().all(|(s, _)| *s == subject));
+        assert_eq!(published[0].1["event"], "task_started");
+        assert_eq!(published[1].1["data"]["passed"], 1);
+        assert_eq!(metrics.events_dropped.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_broker_outage_drops_and_counts() {
+        let broker = Arc::new(MockBroker {
+            down: true,
+            ..Default::default()
+        });
+        let metrics = Metrics::new();
+        let forwarder = EventForwarder::new(broker, "term.events", metrics.clone());
+        let batch = SessionManager::new(60).create_batch(1);
+
+        let handle = forwarder.forward(&batch);
+        batch
+            .emit_event("batch_complete", None, serde_json::json!({}))
+            .await;
+        handle.await.unwrap();
+
+        assert_eq!(metrics.events_dropped.load(Ordering::Relaxed), 1);
+    }
+}
@@ -1,5 +1,5 @@
 use axum::{
-    extract::{Multipart, State},
+    extract::{DefaultBodyLimit, Multipart, State},
     http::{header, StatusCode},
     response::{Html, IntoResponse, Json, Response},
     routing::{get, post},
@@ -19,10 +19,12 @@ use crate::basilica::client::BasilicaClient;
 use crate::config::Config;
 use crate::executor::Executor;
 use crate::metrics::Metrics;
-use crate::session::SessionManager;
+use crate::rate_limit::SubmitRateLimiter;
+use crate::session::{Batch, BatchRemoval, BatchStatus, SessionManager, TaskResult, TaskStatus};
 use crate::ws;
 
 use crate::consensus::{ConsensusManager, ConsensusStatus};
+use crate::signing::{ResponseSigner, SIGNATURE_HEADER};
 use crate::validator_whitelist::ValidatorWhitelist;
 use sha2::{Digest, Sha256};
 
@@ -32,35 +34,70 @@ pub struct AppState {
     pub metrics: Arc<Metrics>,
     pub executor: Arc<Executor>,
     pub nonce_store: Arc<NonceStore>,
+    pub submit_limiter: Arc<SubmitRateLimiter>,
     pub started_at: chrono::DateTime<Utc>,
     pub validator_whitelist: Arc<ValidatorWhitelist>,
     pub consensus_manager: Arc<ConsensusManager>,
     pub agent_archive: Arc<RwLock<Option<Vec<u8>>>>,
     pub agent_env: Arc<RwLock<HashMap<String, String>>>,
     pub basilica_client: Option<Arc<BasilicaClient>>,
+    pub response_signer: Option<Arc<ResponseSigner>>,
+    /// Notified when a batch starts from consensus and when it completes.
+    pub consensus_webhook: Option<Arc<crate::webhook::ConsensusWebhook>>,
 }
 
+/// Optional client-chosen key; resubmitting with the same key returns the
+/// batch it already started.
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 128;
+/// Most batch ids accepted by one `POST /batches/query`.
+const MAX_BATCH_QUERY_IDS: usize = 100;
+/// `archive` fields accepted in one `/submit`; their tasks run as one batch.
+const MAX_ARCHIVES_PER_SUBMIT: usize = 16;
+
+/// Room for multipart boundaries and the other form fields on top of the
+/// archive itself.
+const MULTIPART_OVERHEAD_BYTES: usize = 64 * 1024;
+
 pub fn router(state: Arc<AppState>) -> Router {
+    // Reject oversized uploads before handler code runs.
+    let upload_limit =
+        DefaultBodyLimit::max(state.config.max_archive_bytes + MULTIPART_OVERHEAD_BYTES);
     Router::new()
         .route("/", get(upload_frontend))
         .route("/health", get(health))
+        .route("/ready", get(ready))
+        .route("/version", get(version))
         .route("/status", get(status))
         .route("/metrics", get(metrics))
         .route("/upload-agent", post(upload_agent))
         .route("/upload-agent-json", post(upload_agent_json))
         .route("/agent-code", get(get_agent_code))
         .route("/code-hash", get(get_code_hash))
-        .route("/submit", post(submit_batch))
-        .route("/batch/:id", get(get_batch))
+        .route("/submit", post(submit_batch).layer(upload_limit))
+        .route("/submit/url", post(submit_url))
+        .route("/batch/:id", get(get_batch).delete(delete_batch))
+        .route("/batch/:id/resume", post(resume_batch))
         .route("/batch/:id/tasks", get(get_batch_tasks))
+        .route("/batch/:id/agent", get(get_batch_agent))
+        .route("/batch/:id/rescore", post(rescore_batch))
         .route("/batch/:id/task/:task_id", get(get_task))
-        .route("/batches", get(list_batches))
+        .route("/batch/:id/task/:task_id/plan", get(get_task_plan))
+        .route("/batches", get(list_batches).delete(prune_batches))
+        .route("/batches/query", post(query_batches))
         .route("/verify/:batch_id", get(verify_batch))
+        .route("/consensus/:archive_hash", get(get_consensus_entry))
         .route("/instance", get(instance_info))
+        .route("/public-key", get(public_key))
+        .route("/admin/quiesce", post(admin_quiesce))
+        .route("/admin/resume", post(admin_resume))
         .route("/dataset", get(fetch_dataset))
-        .route("/submit_tasks", post(submit_tasks))
-        .route("/evaluate", post(evaluate_with_stored_agent))
-        .route("/ws", get(ws::ws_handler))
+        .route("/submit_tasks", post(submit_tasks).layer(upload_limit))
+        .route("/tasks/schema", get(tasks_schema))
+        .route(
+            "/evaluate",
+            post(evaluate_with_stored_agent).layer(upload_limit),
+        )
         .route("/basilica/containers", post(basilica_create_container))
         .route("/basilica/containers", get(basilica_list_containers))
         .route(
@@ -76,13 +113,78 @@ pub fn router(state: Arc<AppState>) -> Router {
         .route("/basilica/balance", get(basilica_get_balance))
         .route("/basilica/ssh-keys", post(basilica_register_ssh_key))
         .route("/basilica/ssh-keys", get(basilica_get_ssh_key))
+        .layer(compression())
+        // Added after the compression layer so upgrades pass through untouched.
+        .route("/ws", get(ws::ws_handler))
         .with_state(state)
 }
 
+/// gzip/br/deflate for clients that send `Accept-Encoding`. Small bodies,
+/// images and event streams are left alone by the default predicate.
+fn compression() -> tower_http::compression::CompressionLayer {
+    tower_http::compression::CompressionLayer::new()
+}
+
 async fn health() -> impl IntoResponse {
     Json(serde_json::json!({ "status": "ok" }))
 }
 
+/// 503 while the executor should not take new work: draining for shutdown,
+/// quiesced by an operator, or the validator whitelist refresh has tripped
+/// its failure threshold.
+async fn ready(State(state): State<Arc<AppState>>) -> Response {
+    let mut reasons = Vec::new();
+    if state.sessions.is_draining() {
+        reasons.push("draining");
+    }
+    if state.sessions.is_quiesced() {
+        reasons.push("quiesced");
+    }
+    if state
+        .validator_whitelist
+        .is_degraded(state.config.whitelist_failure_threshold)
+    {
+        reasons.push("whitelist_refresh_failing");
+    }
+    let status = if reasons.is_empty() {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    };
+    (
+        status,
+        Json(serde_json::json!({
+            "ready": reasons.is_empty(),
+            "reasons": reasons,
+        })),
+    )
+        .into_response()
+}
+
+/// Build metadata captured by `build.rs`.
+#[derive(Serialize)]
+struct VersionInfo {
+    version: &'static str,
+    git_sha: &'static str,
+    build_timestamp: String,
+    rustc_version: &'static str,
+}
+
+/// Unauthenticated and independent of runtime state; the Basilica
+/// enrollment check polls it. YAML on request, like `/status`.
+async fn version(headers: axum::http::HeaderMap) -> Response {
+    let epoch: i64 = env!("TERM_EXECUTOR_BUILD_EPOCH").parse().unwrap_or(0);
+    let body = VersionInfo {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("TERM_EXECUTOR_GIT_SHA"),
+        build_timestamp: chrono::DateTime::from_timestamp(epoch, 0)
+            .unwrap_or_default()
+            .to_rfc3339(),
+        rustc_version: env!("TERM_EXECUTOR_RUSTC_VERSION"),
+    };
+    negotiated(&headers, &body)
+}
+
 fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
     if a.len() != b.len() {
         return false;
@@ -498,11 +600,112 @@ struct StatusResponse {
     tasks_failed: u64,
     max_concurrent_tasks: usize,
     has_active_batch: bool,
+    active_batch_id: Option<String>,
+    /// Completed/total tasks of the active batch.
+    active_batch_progress: Option<f64>,
+    /// Consensus-reached batches waiting for a free batch slot.
+    queued_batches: usize,
+    whitelist_consecutive_failures: u32,
+    whitelist_last_refresh: Option<chrono::DateTime<Utc>>,
+    whitelist_degraded: bool,
+}
+
+fn wants_yaml(headers: &axum::http::HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .map(|accept| {
+            accept.split(',').any(|media| {
+                let media = media.split(';').next().unwrap_or("").trim();
+                ["application/yaml", "application/x-yaml", "text/yaml"]
+                    .iter()
+                    .any(|yaml| media.eq_ignore_ascii_case(yaml))
+            })
+        })
+        .unwrap_or(false)
+}
+
+/// Serialize `body` as YAML when the client's `Accept` header asks for it,
+/// JSON otherwise.
+fn negotiated<T: Serialize>(headers: &axum::http::HeaderMap, body: &T) -> Response {
+    if wants_yaml(headers) {
+        match serde_yaml::to_string(body) {
+            Ok(yaml) => {
+                return (
+                    StatusCode::OK,
+                    [(header::CONTENT_TYPE, "application/yaml")],
+                    yaml,
+                )
+                    .into_response()
+            }
+            Err(e) => warn!(error = %e, "Failed to serialize YAML response, falling back to JSON"),
+        }
+    }
+    Json(body).into_response()
+}
+
+/// JSON response whose exact body bytes are signed when a signer is
+/// configured, with the signature in `X-Executor-Signature`.
+fn signed_json<T: Serialize>(
+    signer: Option<&ResponseSigner>,
+    status: StatusCode,
+    body: &T,
+) -> Response {
+    let bytes = match serde_json::to_vec(body) {
+        Ok(b) => b,
+        Err(e) => {
+            warn!(error = %e, "Failed to serialize response");
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let mut response = (
+        status,
+        [(header::CONTENT_TYPE, "application/json")],
+        bytes.clone(),
+    )
+        .into_response();
+    if let Some(signer) = signer {
+        if let Ok(value) = signer.sign(&bytes).parse() {
+            response.headers_mut().insert(SIGNATURE_HEADER, value);
+        }
+    }
+    response
+}
+
+/// Expected archive layout and `workspace.yaml` JSON Schema, for validating
+/// archives before upload.
+async fn tasks_schema(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    Json(crate::task::archive_schema(
+        state.config.max_tasks_per_batch,
+        state.config.archive_root_depth,
+        &state.config.default_agent_language,
+    ))
+}
+
+async fn public_key(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    let signer = state.response_signer.as_ref().ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({
+                "error": "signing_disabled",
+                "message": "Response signing is not configured on this executor"
+            })),
+        )
+    })?;
+    Ok(Json(serde_json::json!({
+        "scheme": "sr25519",
+        "public_key": signer.public_key_hex(),
+        "signing_context": String::from_utf8_lossy(crate::signing::RESPONSE_SIGNING_CONTEXT),
+        "header": SIGNATURE_HEADER,
+    })))
 }
 
-async fn status(State(state): State<Arc<AppState>>) -> Json<StatusResponse> {
+async fn status(State(state): State<Arc<AppState>>, headers: axum::http::HeaderMap) -> Response {
     let uptime = (Utc::now() - state.started_at).num_seconds();
-    Json(StatusResponse {
+    let (active_batch_id, active_batch_progress) = state.sessions.active_batch_progress().unzip();
+    let body = StatusResponse {
         version: env!("CARGO_PKG_VERSION").to_string(),
         uptime_secs: uptime,
         active_batches: state.metrics.batches_active.load(Ordering::Relaxed),
@@ -512,11 +715,21 @@ async fn status(State(state): State<Arc<AppState>>) -> Json<StatusResponse> {
         tasks_failed: state.metrics.tasks_failed.load(Ordering::Relaxed),
         max_concurrent_tasks: state.config.max_concurrent_tasks,
         has_active_batch: state.sessions.has_active_batch(),
-    })
+        active_batch_id,
+        active_batch_progress,
+        queued_batches: state.executor.queued_batches(),
+        whitelist_consecutive_failures: state.validator_whitelist.consecutive_failures(),
+        whitelist_last_refresh: state.validator_whitelist.last_success(),
+        whitelist_degraded: state
+            .validator_whitelist
+            .is_degraded(state.config.whitelist_failure_threshold),
+    };
+    negotiated(&headers, &body)
 }
 
 async fn metrics(State(state): State<Arc<AppState>>) -> Response {
-    let body = state.metrics.render_prometheus();
+    let mut body = state.metrics.render_prometheus();
+    body.push_str(&state.validator_whitelist.render_prometheus());
     (
         StatusCode::OK,
         [("content-type", "text/plain; version=0.0.4; charset=utf-8")],
@@ -529,184 +742,931 @@ async fn metrics(State(state): State<Arc<AppState>>) -> Response {
 struct SubmitQuery {
     #[serde(default)]
     concurrent_tasks: Option<usize>,
+    /// Comma-separated task ids; only these tasks of the archive run.
+    #[serde(default)]
+    tasks: Option<String>,
 }
 
-async fn submit_batch(
-    State(state): State<Arc<AppState>>,
-    headers: axum::http::HeaderMap,
-    query: axum::extract::Query<SubmitQuery>,
-    mut multipart: Multipart,
-) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
-    let auth_headers = auth::extract_auth_headers(&headers).ok_or_else(|| {
+impl SubmitQuery {
+    /// The requested task ids, sorted and deduplicated so every validator
+    /// naming the same set lands on the same consensus key.
+    fn task_subset(&self) -> Option<Vec<String>> {
+        let mut ids: Vec<String> = self
+            .tasks
+            .as_deref()?
+            .split(',')
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(str::to_string)
+            .collect();
+        ids.sort_unstable();
+        ids.dedup();
+        (!ids.is_empty()).then_some(ids)
+    }
+}
+
+/// Consensus key of a submission restricted to `task_ids`: votes for
+/// different subsets of the same archive must not count together.
+fn subset_consensus_key(archive_hash: &str, task_ids: &[String]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(archive_hash.as_bytes());
+    for id in task_ids {
+        hasher.update(b"\n");
+        hasher.update(id.as_bytes());
+    }
+    hex::encode(hasher.finalize())
+}
+
+/// Concurrency for a batch whose consensus was reached: the agreed value if
+/// any, otherwise the configured default, never above the global max.
+fn consensus_concurrency(config: &Config, agreed: Option<usize>) -> usize {
+    agreed
+        .unwrap_or(config.default_consensus_concurrency)
+        .min(config.max_concurrent_tasks)
+}
+
+/// Create a uniquely named extraction directory under the workspace so
+/// concurrent submissions never share one. It is removed when the guard drops,
+/// including on early returns.
+fn extraction_dir(
+    config: &Config,
+    label: &str,
+) -> Result<tempfile::TempDir, (StatusCode, Json<serde_json::Value>)> {
+    tempfile::Builder::new()
+        .prefix(&format!("_extract_{}_", label))
+        .tempdir_in(&config.workspace_base)
+        .map_err(|e| {
+            warn!(error = %e, "Failed to create extraction directory");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({
+                    "error": "extraction_failed",
+                    "message": "Failed to create extraction directory"
+                })),
+            )
+        })
+}
+
+/// The 400 for archive contents the executor refuses to run, as opposed to
+/// archives it can't read.
+fn archive_rejection(e: &anyhow::Error) -> Option<(StatusCode, Json<serde_json::Value>)> {
+    let code = if let Some(e) = e.downcast_ref::<crate::task::AgentCodeError>() {
+        e.code()
+    } else if e.downcast_ref::<crate::task::TooManyTasks>().is_some() {
+        "too_many_tasks"
+    } else {
+        return None;
+    };
+    Some((
+        StatusCode::BAD_REQUEST,
+        Json(serde_json::json!({
+            "error": code,
+            "message": e.to_string(),
+        })),
+    ))
+}
+
+fn archive_too_large(max_bytes: usize, bytes_seen: usize) -> (StatusCode, Json<serde_json::Value>) {
+    (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        Json(serde_json::json!({
+            "error": "archive_too_large",
+            "message": format!("Archive exceeds maximum size of {} bytes", max_bytes),
+            "max_bytes": max_bytes,
+            "bytes_seen": bytes_seen,
+        })),
+    )
+}
+
+fn upload_error(
+    e: axum::extract::multipart::MultipartError,
+    max_bytes: usize,
+    seen: usize,
+) -> (StatusCode, Json<serde_json::Value>) {
+    if e.status() == StatusCode::PAYLOAD_TOO_LARGE {
+        return archive_too_large(max_bytes, seen);
+    }
+    warn!(error = %e, "Failed to read multipart chunk");
+    (
+        StatusCode::BAD_REQUEST,
+        Json(serde_json::json!({
+            "error": "upload_failed",
+            "message": "Failed to read uploaded archive"
+        })),
+    )
+}
+
+/// An uploaded archive spooled to a temp file under `workspace_base`, with
+/// digests computed as it streamed in. The file is removed on drop.
+#[derive(Debug)]
+struct SpooledArchive {
+    file: tempfile::NamedTempFile,
+    size: usize,
+    sha256: String,
+    blake2b_256: String,
+}
+
+impl SpooledArchive {
+    fn path(&self) -> &std::path::Path {
+        self.file.path()
+    }
+
+    /// Re-hash the spooled file and check it still matches the SHA-256 that
+    /// was voted on. Blocking; call from `spawn_blocking`.
+    fn verify(&self) -> anyhow::Result<()> {
+        let mut file = std::fs::File::open(self.path())?;
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut file, &mut hasher)?;
+        let actual = hex::encode(hasher.finalize());
+        if actual != self.sha256 {
+            anyhow::bail!(
+                "archive changed after hashing: expected sha256 {}, found {}",
+                self.sha256,
+                actual
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Stream every `archive` (or `file`) field to disk, never holding more than
+/// one chunk in memory. Fails with 413 as soon as the archives together
+/// exceed `max_bytes`, leaving the rest of the stream unread.
+async fn read_archive_uploads(
+    multipart: &mut Multipart,
+    max_bytes: usize,
+    spool_dir: &std::path::Path,
+) -> Result<Vec<SpooledArchive>, (StatusCode, Json<serde_json::Value>)> {
+    use blake2::{digest::consts::U32, Blake2b};
+    use futures::TryStreamExt;
+    use tokio::io::AsyncWriteExt;
+
+    let spool_error = |e: std::io::Error| {
+        warn!(error = %e, "Failed to spool uploaded archive");
         (
-            StatusCode::UNAUTHORIZED,
+            StatusCode::INTERNAL_SERVER_ERROR,
             Json(serde_json::json!({
-                "error": "missing_auth",
-                "message": "Missing required headers: X-Hotkey, X-Nonce, X-Signature"
+                "error": "upload_failed",
+                "message": "Failed to store uploaded archive"
             })),
         )
-    })?;
+    };
 
-    if state.validator_whitelist.validator_count() == 0 {
-        return Err((
-            StatusCode::SERVICE_UNAVAILABLE,
+    let mut archives = Vec::new();
+    let mut total = 0usize;
+    while let Some(mut field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| upload_error(e, max_bytes, total))?
+    {
+        let name = field.name().unwrap_or("").to_string();
+        if name != "archive" && name != "file" {
+            continue;
+        }
+        if archives.len() == MAX_ARCHIVES_PER_SUBMIT {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "error": "too_many_archives",
+                    "message": format!(
+                        "At most {} archives per submission",
+                        MAX_ARCHIVES_PER_SUBMIT
+                    ),
+                })),
+            ));
+        }
+        let file = tempfile::Builder::new()
+            .prefix("_upload_")
+            .tempfile_in(spool_dir)
+            .map_err(spool_error)?;
+        let mut writer = tokio::fs::File::from_std(file.reopen().map_err(spool_error)?);
+        let mut sha256 = Sha256::new();
+        let mut blake2b = Blake2b::<U32>::new();
+        let mut size = 0usize;
+        while let Some(chunk) = field
+            .try_next()
+            .await
+            .map_err(|e| upload_error(e, max_bytes, total + size))?
+        {
+            if total + size + chunk.len() > max_bytes {
+                return Err(archive_too_large(max_bytes, total + size + chunk.len()));
+            }
+            size += chunk.len();
+            sha256.update(&chunk);
+            blake2b.update(&chunk);
+            writer.write_all(&chunk).await.map_err(spool_error)?;
+        }
+        writer.flush().await.map_err(spool_error)?;
+        total += size;
+        archives.push(SpooledArchive {
+            file,
+            size,
+            sha256: hex::encode(sha256.finalize()),
+            blake2b_256: hex::encode(blake2b.finalize()),
+        });
+    }
+    Ok(archives)
+}
+
+/// Check every spooled archive against its recorded SHA-256, and the set
+/// against the consensus `archive_hash`, before anything is extracted.
+async fn verify_archives(
+    archives: Vec<SpooledArchive>,
+    archive_hash: &str,
+) -> Result<Vec<SpooledArchive>, (StatusCode, Json<serde_json::Value>)> {
+    let expected = archive_hash.to_string();
+    let checked = tokio::task::spawn_blocking(move || {
+        let sha256s: Vec<&str> = archives.iter().map(|a| a.sha256.as_str()).collect();
+        let combined = combined_digest::<Sha256>(&sha256s);
+        if combined != expected {
+            anyhow::bail!(
+                "archives hash to {}, not the consensus hash {}",
+                combined,
+                expected
+            );
+        }
+        for archive in &archives {
+            archive.verify()?;
+        }
+        Ok(archives)
+    })
+    .await
+    .map_err(|e| anyhow::anyhow!("archive verification panicked: {}", e))
+    .and_then(|r| r);
+    checked.map_err(|e| {
+        tracing::error!(archive_hash, error = %e, "Archive integrity check failed");
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
             Json(serde_json::json!({
-                "error": "whitelist_not_ready",
-                "message": "Validator whitelist not yet initialized. Please retry shortly."
+                "error": "archive_integrity_mismatch",
+                "message": e.to_string(),
             })),
-        ));
+        )
+    })
+}
+
+/// One digest for a set of archives: the archive's own digest when there is
+/// one, otherwise `D` over the sorted hex digests, one per line. Upload order
+/// therefore doesn't change the consensus key.
+fn combined_digest<D: Digest>(digests: &[&str]) -> String {
+    if let [single] = digests {
+        return single.to_string();
+    }
+    let mut sorted = digests.to_vec();
+    sorted.sort_unstable();
+    let mut hasher = D::new();
+    for digest in sorted {
+        hasher.update(digest.as_bytes());
+        hasher.update(b"\n");
     }
+    hex::encode(hasher.finalize())
+}
 
-    if let Err(e) = auth::verify_request(
+/// Check the validator auth headers (without consuming a submit slot).
+pub(crate) fn require_validator(
+    state: &AppState,
+    headers: &axum::http::HeaderMap,
+) -> Result<auth::AuthHeaders, (StatusCode, Json<serde_json::Value>)> {
+    let auth_headers = auth::extract_auth_headers(headers).ok_or_else(|| {
+        (
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({
+                "error": "missing_auth",
+                "message": "Missing required headers: X-Hotkey, X-Nonce, X-Signature"
+            })),
+        )
+    })?;
+    auth::verify_request(
         &auth_headers,
         &state.nonce_store,
         &state.validator_whitelist,
-    ) {
-        return Err((
+    )
+    .map_err(|e| {
+        (
             StatusCode::UNAUTHORIZED,
             Json(serde_json::json!({
                 "error": e.code(),
                 "message": e.message(),
             })),
-        ));
-    }
-
-    let max_bytes = state.config.max_archive_bytes;
-    let mut archive_data: Option<Vec<u8>> = None;
-
-    while let Ok(Some(field)) = multipart.next_field().await {
-        let name = field.name().unwrap_or("").to_string();
-        if name == "archive" || name == "file" {
-            let mut buf = Vec::new();
-            let mut stream = field;
-            use futures::TryStreamExt;
-            while let Some(chunk) = stream.try_next().await.map_err(|e| {
-                warn!(error = %e, "Failed to read multipart chunk");
-                (
-                    StatusCode::BAD_REQUEST,
-                    Json(serde_json::json!({
-                        "error": "upload_failed",
-                        "message": "Failed to read uploaded archive"
-                    })),
-                )
-            })? {
-                if buf.len() + chunk.len() > max_bytes {
-                    return Err((
-                        StatusCode::BAD_REQUEST,
-                        Json(serde_json::json!({
-                            "error": "archive_too_large",
-                            "message": format!(
-                                "Archive exceeds maximum size of {} bytes",
-                                max_bytes
-                            )
-                        })),
-                    ));
-                }
-                buf.extend_from_slice(&chunk);
-            }
-            archive_data = Some(buf);
-        }
-    }
+        )
+    })?;
+    Ok(auth_headers)
+}
 
-    let archive_bytes = archive_data.ok_or_else(|| {
+fn consensus_entry_response(
+    manager: &ConsensusManager,
+    archive_hash: &str,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    let info = manager.entry_info(archive_hash).ok_or_else(|| {
         (
-            StatusCode::BAD_REQUEST,
+            StatusCode::NOT_FOUND,
             Json(serde_json::json!({
-                "error": "missing_archive",
-                "message": "No archive file uploaded. Send a multipart form with field 'archive'."
+                "error": "not_found",
+                "message": "No pending consensus entry for this archive hash"
             })),
         )
     })?;
+    Ok(Json(serde_json::json!(info)))
+}
 
-    if state.consensus_manager.is_at_capacity() {
+/// Metadata about a pending consensus entry. Never returns archive bytes.
+async fn get_consensus_entry(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    axum::extract::Path(archive_hash): axum::extract::Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    require_validator(&state, &headers)?;
+    consensus_entry_response(&state.consensus_manager, &archive_hash)
+}
+
+/// Refuse new batches once a shutdown drain has started or while an operator
+/// has quiesced the executor.
+fn reject_if_draining(state: &AppState) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    if state.sessions.is_draining() {
         return Err((
             StatusCode::SERVICE_UNAVAILABLE,
             Json(serde_json::json!({
-                "error": "too_many_pending",
-                "message": "Too many pending consensus entries. Please retry later."
+                "error": "draining",
+                "message": "Executor is shutting down and no longer accepts batches."
+            })),
+        ));
+    }
+    if state.sessions.is_quiesced() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({
+                "error": "quiesced",
+                "message": "Executor is quiesced for maintenance and not accepting batches."
             })),
         ));
     }
+    Ok(())
+}
 
-    let archive_hash = {
-        let mut hasher = Sha256::new();
-        hasher.update(&archive_bytes);
-        hex::encode(hasher.finalize())
+/// `reject_if_draining` again once `batch` is registered, right before it is
+/// started. A drain or quiesce that began after admission is either seen here
+/// or its `cancel_all` already reached `batch`. A rejected batch is failed.
+async fn reject_new_batch_if_draining(
+    state: &AppState,
+    batch: &Batch,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    let Err(rejection) = reject_if_draining(state) else {
+        return Ok(());
     };
+    let mut res = batch.result.lock().await;
+    res.status = BatchStatus::Failed;
+    res.error = Some("executor stopped accepting batches before this one started".to_string());
+    state.sessions.mark_failed();
+    Err(rejection)
+}
 
-    let total_validators = state.validator_whitelist.validator_count();
-    let required_f = (total_validators as f64 * state.config.consensus_threshold).ceil();
-    let required = (required_f.min(usize::MAX as f64) as usize).max(1);
-
-    let concurrent = query
-        .concurrent_tasks
-        .unwrap_or(state.config.max_concurrent_tasks)
-        .min(state.config.max_concurrent_tasks);
+/// `Authorization: Bearer <ADMIN_TOKEN>`. The admin routes don't exist
+/// (404) when no token is configured.
+fn check_admin_token(
+    config: &Config,
+    headers: &axum::http::HeaderMap,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    let Some(ref expected) = config.admin_token else {
+        return Err((
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({
+                "error": "admin_disabled",
+                "message": "ADMIN_TOKEN is not configured on this executor"
+            })),
+        ));
+    };
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .unwrap_or("");
+    if !constant_time_eq(presented.as_bytes(), expected.as_bytes()) {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({
+                "error": "invalid_admin_token",
+                "message": "Missing or invalid admin token"
+            })),
+        ));
+    }
+    Ok(())
+}
 
-    let status = state.consensus_manager.record_vote(
-        &archive_hash,
-        &auth_headers.hotkey,
-        Some(concurrent),
-        required,
-        total_validators,
+/// Refuse new batches and cancel every running one. Unlike a shutdown the
+/// process stays up, so status, metrics and results remain queryable.
+async fn admin_quiesce(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    check_admin_token(&state.config, &headers)?;
+    let was_quiesced = state.sessions.set_quiesced(true);
+    let active_batches = state.metrics.batches_active.load(Ordering::Relaxed);
+    state.sessions.cancel_all();
+    warn!(
+        active_batches,
+        "Executor quiesced, running batches cancelled"
     );
+    Ok(Json(serde_json::json!({
+        "quiesced": true,
+        "was_quiesced": was_quiesced,
+        "cancelled_batches": active_batches,
+    })))
+}
 
-    match status {
-        ConsensusStatus::Pending {
-            votes,
-            required,
-            total_validators,
-        } => Ok((
-            StatusCode::ACCEPTED,
+/// Accept batches again after `/admin/quiesce`.
+async fn admin_resume(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    check_admin_token(&state.config, &headers)?;
+    let was_quiesced = state.sessions.set_quiesced(false);
+    state.executor.start_queued().await;
+    tracing::info!("Executor resumed");
+    Ok(Json(serde_json::json!({
+        "quiesced": false,
+        "was_quiesced": was_quiesced,
+    })))
+}
+
+/// Remove a finished batch and its journal ahead of `SESSION_TTL_SECS`.
+async fn delete_batch(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    check_admin_token(&state.config, &headers)?;
+    match state.sessions.remove_batch(&id).await {
+        BatchRemoval::Removed(status) => {
+            remove_batch_journal(&state.config, &id).await;
+            Ok(Json(serde_json::json!({
+                "batch_id": id,
+                "status": status,
+                "deleted": true,
+            })))
+        }
+        BatchRemoval::NotFound => Err((
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({
+                "error": "not_found",
+                "message": "No batch with this id"
+            })),
+        )),
+        BatchRemoval::NotTerminal(status) => Err((
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({
+                "error": "batch_active",
+                "message": format!("Batch is {:?}, only completed or failed batches can be deleted", status)
+            })),
+        )),
+    }
+}
+
+#[derive(serde::Deserialize, Default)]
+struct PruneQuery {
+    #[serde(default)]
+    status: Option<BatchStatus>,
+}
+
+/// Remove every completed or failed batch, or only those in `?status=`.
+async fn prune_batches(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    query: Result<axum::extract::Query<PruneQuery>, axum::extract::rejection::QueryRejection>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    check_admin_token(&state.config, &headers)?;
+    let status = match query {
+        Ok(axum::extract::Query(q)) if q.status.as_ref().is_none_or(|s| s.is_terminal()) => {
+            q.status
+        }
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "error": "invalid_status",
+                    "message": "status must be completed or failed"
+                })),
+            ))
+        }
+    };
+
+    let mut pruned = Vec::new();
+    for id in state.sessions.terminal_batch_ids(status.as_ref()) {
+        if let BatchRemoval::Removed(_) = state.sessions.remove_batch(&id).await {
+            remove_batch_journal(&state.config, &id).await;
+            pruned.push(id);
+        }
+    }
+    Ok(Json(serde_json::json!({
+        "pruned": pruned.len(),
+        "batch_ids": pruned,
+    })))
+}
+
+/// Delete what `BATCH_JOURNAL` kept of a batch, so a pruned interrupted
+/// batch isn't restored on the next start.
+async fn remove_batch_journal(config: &Config, batch_id: &str) {
+    if config.batch_journal {
+        let dir = config.workspace_base.join(crate::journal::JOURNAL_DIR);
+        crate::journal::BatchJournal::open(&dir, batch_id)
+            .remove()
+            .await;
+    }
+}
+
+/// The checks every archive submission passes before its archive is read:
+/// auth, idempotent replay and the per-hotkey rate limit. `Err` is the
+/// response to send instead (an error or a replayed batch).
+async fn admit_submission(
+    state: &AppState,
+    headers: &axum::http::HeaderMap,
+) -> Result<(auth::AuthHeaders, Option<String>), Response> {
+    reject_if_draining(state).map_err(IntoResponse::into_response)?;
+
+    let auth_headers = auth::extract_auth_headers(headers).ok_or_else(|| {
+        (
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({
+                "error": "missing_auth",
+                "message": "Missing required headers: X-Hotkey, X-Nonce, X-Signature"
+            })),
+        )
+            .into_response()
+    })?;
+
+    if state.validator_whitelist.validator_count() == 0 {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({
+                "error": "whitelist_not_ready",
+                "message": "Validator whitelist not yet initialized. Please retry shortly."
+            })),
+        )
+            .into_response());
+    }
+
+    if let Err(e) = auth::verify_request(
+        &auth_headers,
+        &state.nonce_store,
+        &state.validator_whitelist,
+    ) {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            Json(serde_json::json!({
+                "error": e.code(),
+                "message": e.message(),
+            })),
+        )
+            .into_response());
+    }
+
+    // Keys are scoped per hotkey so validators can't collide with each other.
+    let idempotency_key = headers
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|k| !k.is_empty() && k.len() <= MAX_IDEMPOTENCY_KEY_LEN)
+        .map(|k| format!("{}:{}", auth_headers.hotkey, k));
+    if let Some(ref key) = idempotency_key {
+        if let Some(batch) = state.sessions.batch_for_idempotency_key(key) {
+            let total_tasks = batch.result.lock().await.total_tasks;
+            tracing::info!(batch_id = %batch.id, "Idempotent submit, returning existing batch");
+            return Err(signed_json(
+                state.response_signer.as_deref(),
+                StatusCode::ACCEPTED,
+                &serde_json::json!({
+                    "batch_id": batch.id,
+                    "total_tasks": total_tasks,
+                    "ws_url": format!("/ws?batch_id={}", batch.id),
+                    "idempotent_replay": true,
+                }),
+            ));
+        }
+    }
+
+    if let Err(wait) = state.submit_limiter.try_acquire(&auth_headers.hotkey) {
+        let retry_after = wait.as_secs().max(1);
+        warn!(hotkey = %auth_headers.hotkey, retry_after, "Submit rate limit exceeded");
+        return Err((
+            StatusCode::TOO_MANY_REQUESTS,
+            [(header::RETRY_AFTER, retry_after.to_string())],
+            Json(serde_json::json!({
+                "error": "rate_limited",
+                "message": format!(
+                    "Too many submissions from this hotkey. Retry in {}s.",
+                    retry_after
+                ),
+            })),
+        )
+            .into_response());
+    }
+
+    Ok((auth_headers, idempotency_key))
+}
+
+async fn submit_batch(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    query: axum::extract::Query<SubmitQuery>,
+    mut multipart: Multipart,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    let (auth_headers, idempotency_key) = match admit_submission(&state, &headers).await {
+        Ok(admitted) => admitted,
+        Err(response) => return Ok(response),
+    };
+
+    let max_bytes = state.config.max_archive_bytes;
+    let archives =
+        read_archive_uploads(&mut multipart, max_bytes, &state.config.workspace_base).await?;
+
+    if archives.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": "missing_archive",
+                "message": "No archive file uploaded. Send a multipart form with field 'archive'."
+            })),
+        ));
+    }
+
+    submit_archive(
+        &state,
+        auth_headers,
+        idempotency_key,
+        query.concurrent_tasks,
+        query.task_subset(),
+        archives,
+    )
+    .await
+}
+
+#[derive(serde::Deserialize)]
+struct SubmitUrlRequest {
+    archive_url: String,
+}
+
+/// Like `/submit`, but the executor fetches the archive from `archive_url`
+/// (S3, IPFS gateway, ...) instead of receiving it as an upload. The URL
+/// must pass the `ALLOWED_GIT_HOSTS` rules, as must every redirect.
+async fn submit_url(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    query: axum::extract::Query<SubmitQuery>,
+    Json(req): Json<SubmitUrlRequest>,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    let (auth_headers, idempotency_key) = match admit_submission(&state, &headers).await {
+        Ok(admitted) => admitted,
+        Err(response) => return Ok(response),
+    };
+
+    let allowed_hosts = state.config.allowed_git_hosts.clone();
+    let check_url =
+        move |url: &str| crate::executor::check_archive_url_allowed(url, &allowed_hosts);
+    if let Err(e) = check_url(&req.archive_url) {
+        return Err((
+            StatusCode::BAD_REQUEST,
             Json(serde_json::json!({
+                "error": "archive_url_not_allowed",
+                "message": e.to_string(),
+            })),
+        ));
+    }
+
+    let file = tempfile::Builder::new()
+        .prefix("_download_")
+        .tempfile_in(&state.config.workspace_base)
+        .map_err(|e| {
+            warn!(error = %e, "Failed to create archive download file");
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Json(serde_json::json!({
+                    "error": "download_failed",
+                    "message": "Failed to store downloaded archive"
+                })),
+            )
+        })?;
+    let max_bytes = state.config.max_archive_bytes;
+    let downloaded = crate::task::download_archive(
+        &req.archive_url,
+        file.path(),
+        max_bytes,
+        state.config.archive_download_timeout_secs,
+        check_url,
+    )
+    .await
+    .map_err(|e| {
+        if let Some(too_large) = e.downcast_ref::<crate::task::ArchiveTooLarge>() {
+            return archive_too_large(max_bytes, too_large.bytes_seen);
+        }
+        warn!(error = %e, url = %req.archive_url, "Archive download failed");
+        (
+            StatusCode::BAD_GATEWAY,
+            Json(serde_json::json!({
+                "error": "download_failed",
+                "message": format!("{:#}", e),
+            })),
+        )
+    })?;
+    let archive = SpooledArchive {
+        file,
+        size: downloaded.size,
+        sha256: downloaded.sha256,
+        blake2b_256: downloaded.blake2b_256,
+    };
+
+    submit_archive(
+        &state,
+        auth_headers,
+        idempotency_key,
+        query.concurrent_tasks,
+        query.task_subset(),
+        vec![archive],
+    )
+    .await
+}
+
+/// Catch agents that could never run, and oversized batches, before
+/// validators spend a consensus round on them. Returns the total task count,
+/// or `None` if an archive couldn't be listed.
+async fn precheck_archives(
+    config: &Config,
+    archives: &[SpooledArchive],
+) -> Result<Option<usize>, (StatusCode, Json<serde_json::Value>)> {
+    let max_tasks = config.max_tasks_per_batch;
+    let root_depth = config.archive_root_depth;
+    let mut task_count = Some(0);
+    for archive in archives {
+        let archive_path = archive.path().to_path_buf();
+        let count = match tokio::task::spawn_blocking(move || {
+            crate::task::precheck_archive(&archive_path, max_tasks, root_depth)
+        })
+        .await
+        {
+            Ok(Ok(count)) => count,
+            Ok(Err(e)) => match archive_rejection(&e) {
+                Some(rejection) => return Err(rejection),
+                None => None,
+            },
+            Err(_) => None,
+        };
+        task_count = task_count.zip(count).map(|(total, n)| total + n);
+    }
+    Ok(task_count)
+}
+
+/// Count an admitted submission's vote for `archives` and, once consensus is
+/// reached, extract them and start one batch over their merged tasks.
+async fn submit_archive(
+    state: &AppState,
+    auth_headers: auth::AuthHeaders,
+    idempotency_key: Option<String>,
+    concurrent_tasks: Option<usize>,
+    task_subset: Option<Vec<String>>,
+    archives: Vec<SpooledArchive>,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    if state.consensus_manager.is_at_capacity() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            Json(serde_json::json!({
+                "error": "too_many_pending",
+                "message": "Too many pending consensus entries. Please retry later."
+            })),
+        ));
+    }
+
+    let sha256s: Vec<&str> = archives.iter().map(|a| a.sha256.as_str()).collect();
+    let archive_hash = combined_digest::<Sha256>(&sha256s);
+    let consensus_key = match task_subset {
+        Some(ref ids) => subset_consensus_key(&archive_hash, ids),
+        None => archive_hash.clone(),
+    };
+
+    // Every validator uploads the same archive, so a round that already has
+    // the task count was prechecked by an earlier vote.
+    let known_count = state
+        .consensus_manager
+        .entry_info(&consensus_key)
+        .and_then(|info| info.task_count);
+    let task_count = match known_count {
+        Some(count) => Some(count),
+        None => precheck_archives(&state.config, &archives).await?,
+    };
+
+    let total_validators = state.validator_whitelist.validator_count();
+    let required = crate::consensus::required_votes(
+        state.config.consensus_mode,
+        state.config.consensus_threshold,
+        state.config.consensus_absolute,
+        total_validators,
+    );
+    let consensus_rule = serde_json::json!({
+        "mode": state.config.consensus_mode,
+        "threshold": match state.config.consensus_mode {
+            crate::consensus::ConsensusMode::Fraction => serde_json::json!(state.config.consensus_threshold),
+            crate::consensus::ConsensusMode::Absolute => serde_json::json!(state.config.consensus_absolute),
+        },
+    });
+
+    let concurrent = concurrent_tasks.map(|c| c.min(state.config.max_concurrent_tasks));
+
+    // Reserved before voting so that a vote completing consensus while
+    // there is nowhere to run the batch leaves the round open.
+    let admission = state.executor.try_admit();
+    let status = state.consensus_manager.record_vote(
+        &consensus_key,
+        &auth_headers.hotkey,
+        concurrent,
+        required,
+        total_validators,
+        admission.is_some(),
+    );
+    if matches!(status, ConsensusStatus::Pending { .. }) {
+        let blake2b_256s: Vec<&str> = archives.iter().map(|a| a.blake2b_256.as_str()).collect();
+        state.consensus_manager.attach_archive_meta(
+            &consensus_key,
+            archives.iter().map(|a| a.size).sum(),
+            combined_digest::<blake2::Blake2b<blake2::digest::consts::U32>>(&blake2b_256s),
+        );
+        if let Some(count) = task_count {
+            state
+                .consensus_manager
+                .set_task_count(&consensus_key, count);
+        }
+    }
+
+    match status {
+        ConsensusStatus::Busy => Err(batches_busy(&state.config)),
+        ConsensusStatus::Pending {
+            votes,
+            required,
+            total_validators,
+        } => Ok(signed_json(
+            state.response_signer.as_deref(),
+            StatusCode::ACCEPTED,
+            &serde_json::json!({
                 "status": "pending_consensus",
                 "archive_hash": archive_hash,
+                "consensus_key": consensus_key,
+                "tasks": task_subset,
                 "votes": votes,
                 "required": required,
                 "total_validators": total_validators,
-            })),
+                "consensus": consensus_rule,
+            }),
         )),
         ConsensusStatus::AlreadyVoted {
             votes,
             required,
             total_validators,
-        } => Ok((
+        } => Ok(signed_json(
+            state.response_signer.as_deref(),
             StatusCode::ACCEPTED,
-            Json(serde_json::json!({
+            &serde_json::json!({
                 "status": "pending_consensus",
                 "archive_hash": archive_hash,
+                "consensus_key": consensus_key,
+                "tasks": task_subset,
                 "votes": votes,
                 "required": required,
                 "total_validators": total_validators,
+                "consensus": consensus_rule,
                 "note": "Your vote was already recorded",
-            })),
+            }),
         )),
         ConsensusStatus::Reached {
             concurrent_tasks,
             votes,
             required,
         } => {
-            let effective_concurrent = concurrent_tasks
-                .unwrap_or(state.config.max_concurrent_tasks)
-                .min(state.config.max_concurrent_tasks);
+            // The vote that completes a round should not eat into the
+            // submitter's budget.
+            state.submit_limiter.refund(&auth_headers.hotkey);
 
-            if state.sessions.has_active_batch() {
-                return Err((
-                    StatusCode::SERVICE_UNAVAILABLE,
-                    Json(serde_json::json!({
-                        "error": "busy",
-                        "message": "A batch is already running. Wait for it to complete."
-                    })),
-                ));
-            }
+            let effective_concurrent = consensus_concurrency(&state.config, concurrent_tasks);
 
-            let extract_dir = state.config.workspace_base.join("_extract_tmp");
-            let _ = tokio::fs::remove_dir_all(&extract_dir).await;
+            // Never `None` here: a vote without room comes back `Busy`.
+            let Some(admission) = admission else {
+                return Err(batches_busy(&state.config));
+            };
 
-            let extracted = crate::task::extract_uploaded_archive(&archive_bytes, &extract_dir)
+            // The votes were counted against digests taken while the archives
+            // streamed in; make sure the bytes about to run are those bytes.
+            let archives = verify_archives(archives, &archive_hash).await?;
+
+            let mut parts = Vec::with_capacity(archives.len());
+            for archive in &archives {
+                let extract_dir = extraction_dir(&state.config, &archive.sha256[..12])?;
+                let part = crate::task::extract_uploaded_archive_file(
+                    archive.path(),
+                    extract_dir.path(),
+                    state.config.archive_root_depth,
+                    state.config.max_tasks_per_batch,
+                    &state.config.default_agent_language,
+                )
                 .await
                 .map_err(|e| {
                     warn!(error = %e, "Failed to extract uploaded archive");
+                    if let Some(rejection) = archive_rejection(&e) {
+                        return rejection;
+                    }
                     (
                         StatusCode::BAD_REQUEST,
                         Json(serde_json::json!({
@@ -715,64 +1675,468 @@ async fn submit_batch(
                         })),
                     )
                 })?;
+                parts.push(part);
+            }
+
+            let mut extracted =
+                crate::task::merge_archives(parts, state.config.max_tasks_per_batch).map_err(
+                    |e| {
+                        archive_rejection(&e).unwrap_or_else(|| {
+                            (
+                                StatusCode::BAD_REQUEST,
+                                Json(serde_json::json!({
+                                    "error": "extraction_failed",
+                                    "message": e.to_string(),
+                                })),
+                            )
+                        })
+                    },
+                )?;
+            if let Some(ref ids) = task_subset {
+                crate::task::select_tasks(&mut extracted.tasks, ids).map_err(|e| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        Json(serde_json::json!({
+                            "error": "unknown_task_ids",
+                            "message": e.to_string(),
+                        })),
+                    )
+                })?;
+            }
+            dedupe_batch_tasks(&state.config, &mut extracted.tasks)?;
+            if state.config.checks_restricted {
+                crate::task::checks::restrict_checks(
+                    &extracted.tasks,
+                    &state.config.checks_allowlist,
+                )
+                .map_err(|e| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        Json(serde_json::json!({
+                            "error": "check_not_allowed",
+                            "message": e.to_string(),
+                        })),
+                    )
+                })?;
+            }
+            let total_tasks = extracted.tasks.len();
+            let batch = state.sessions.create_batch(total_tasks);
+            let batch_id = batch.id.clone();
+            if let Some(ref key) = idempotency_key {
+                state.sessions.remember_idempotency_key(key, &batch_id);
+            }
+
+            let env = state.agent_env.read().await.clone();
+            let mut body = serde_json::json!({
+                "batch_id": batch_id,
+                "total_tasks": total_tasks,
+                "concurrent_tasks": effective_concurrent,
+                "ws_url": format!("/ws?batch_id={}", batch_id),
+                "consensus_reached": true,
+                "votes": votes,
+                "required": required,
+            });
+            if let Some(ids) = task_subset {
+                body["tasks"] = serde_json::json!(ids);
+            }
+            reject_new_batch_if_draining(state, &batch).await?;
+            if let Some(ref webhook) = state.consensus_webhook {
+                webhook.batch_started(
+                    &batch,
+                    crate::webhook::ConsensusInfo {
+                        archive_hash: archive_hash.clone(),
+                        votes,
+                        required,
+                    },
+                );
+            }
+            if let Some(position) = state
+                .executor
+                .start_batch(batch, extracted, effective_concurrent, env, admission)
+                .await
+            {
+                body["status"] = serde_json::json!("queued");
+                body["queue_position"] = serde_json::json!(position);
+            }
+
+            Ok(signed_json(
+                state.response_signer.as_deref(),
+                StatusCode::ACCEPTED,
+                &body,
+            ))
+        }
+    }
+}
+
+async fn get_batch(
+    State(state): State<Arc<AppState>>,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Result<Response, StatusCode> {
+    let batch = state.sessions.get(&id).ok_or(StatusCode::NOT_FOUND)?;
+    let body = batch_summary(&*batch.result.lock().await);
+    Ok(signed_json(
+        state.response_signer.as_deref(),
+        StatusCode::OK,
+        &body,
+    ))
+}
+
+/// Summary shared by `GET /batch/{id}` and `POST /batches/query`.
+fn batch_summary(result: &crate::session::BatchResult) -> serde_json::Value {
+    let progress = if result.total_tasks == 0 {
+        0.0
+    } else {
+        result.completed_tasks as f64 / result.total_tasks as f64
+    };
+    serde_json::json!({
+        "batch_id": result.batch_id,
+        "status": result.status,
+        "total_tasks": result.total_tasks,
+        "completed_tasks": result.completed_tasks,
+        "passed_tasks": result.passed_tasks,
+        "failed_tasks": result.failed_tasks,
+        "progress": progress,
+        "aggregate_reward": result.aggregate_reward,
+        "reward_aggregation": result.reward_aggregation,
+        "error": result.error,
+        "duration_ms": result.duration_ms,
+        "retries_used": result.retries_used,
+    })
+}
+
+#[derive(serde::Deserialize)]
+struct BatchQueryRequest {
+    batch_ids: Vec<String>,
+}
+
+/// Summaries for several batches in one round-trip. Unknown ids map to `null`.
+async fn query_batches(
+    State(state): State<Arc<AppState>>,
+    Json(req): Json<BatchQueryRequest>,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    if req.batch_ids.len() > MAX_BATCH_QUERY_IDS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": "too_many_batch_ids",
+                "message": format!("At most {} batch ids per query", MAX_BATCH_QUERY_IDS),
+                "max": MAX_BATCH_QUERY_IDS,
+            })),
+        ));
+    }
+    let body = serde_json::json!({
+        "batches": batch_summaries(&state.sessions, &req.batch_ids).await,
+    });
+    Ok(signed_json(
+        state.response_signer.as_deref(),
+        StatusCode::OK,
+        &body,
+    ))
+}
+
+async fn batch_summaries(
+    sessions: &SessionManager,
+    batch_ids: &[String],
+) -> serde_json::Map<String, serde_json::Value> {
+    let mut summaries = serde_json::Map::new();
+    for id in batch_ids {
+        let summary = match sessions.get(id) {
+            Some(batch) => batch_summary(&*batch.result.lock().await),
+            None => serde_json::Value::Null,
+        };
+        summaries.insert(id.clone(), summary);
+    }
+    summaries
+}
 
-            let _ = tokio::fs::remove_dir_all(&extract_dir).await;
+/// 503 for a batch arriving while `MAX_CONCURRENT_BATCHES` are running
+/// and the `BATCH_QUEUE_DEPTH` queue, if any, is full.
+fn batches_busy(config: &Config) -> (StatusCode, Json<serde_json::Value>) {
+    let message = if config.batch_queue_depth > 0 {
+        format!(
+            "The batch queue is full ({} waiting). Try again later.",
+            config.batch_queue_depth
+        )
+    } else if config.max_concurrent_batches <= 1 {
+        "A batch is already running. Wait for it to complete.".to_string()
+    } else {
+        format!(
+            "{} batches are already running. Wait for one to complete.",
+            config.max_concurrent_batches
+        )
+    };
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        Json(serde_json::json!({
+            "error": "busy",
+            "message": message,
+        })),
+    )
+}
+
+/// Re-run the unfinished tasks of a batch interrupted by a restart. Needs
+/// `BATCH_JOURNAL`; tasks that finished before the restart keep their results.
+async fn resume_batch(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    axum::extract::Path(id): axum::extract::Path<String>,
+) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    require_validator(&state, &headers)?;
+    reject_if_draining(&state)?;
+
+    let not_found = || {
+        (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({
+                "error": "not_found",
+                "message": "No resumable batch with this id"
+            })),
+        )
+    };
+    if !state.config.batch_journal {
+        return Err(not_found());
+    }
+    let batch = state.sessions.get(&id).ok_or_else(not_found)?;
+    let slot = state
+        .sessions
+        .try_reserve_batch_slot(state.config.max_concurrent_batches)
+        .ok_or_else(|| batches_busy(&state.config))?;
+    let env = state.agent_env.read().await.clone();
+    // The batch is registered, so a quiesce after this check cancels it.
+    reject_if_draining(&state)?;
+
+    // Claim the batch: of two concurrent resumes only one sees `Failed`.
+    let total_tasks = {
+        let mut result = batch.result.lock().await;
+        if result.status != BatchStatus::Failed {
+            return Err((
+                StatusCode::CONFLICT,
+                Json(serde_json::json!({
+                    "error": "not_interrupted",
+                    "message": format!("Batch is {:?}, only interrupted batches can be resumed", result.status)
+                })),
+            ));
+        }
+        result.status = BatchStatus::Pending;
+        result.total_tasks
+    };
+
+    let remaining = match state.executor.resume_batch(batch.clone(), env, slot).await {
+        Ok(remaining) => remaining,
+        Err(e) => {
+            warn!(batch_id = %id, "Cannot resume batch: {:#}", e);
+            batch.result.lock().await.status = BatchStatus::Failed;
+            return Err(not_found());
+        }
+    };
+
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(serde_json::json!({
+            "batch_id": id,
+            "total_tasks": total_tasks,
+            "remaining_tasks": remaining,
+            "ws_url": format!("/ws?batch_id={}", id),
+        })),
+    ))
+}
+
+#[derive(serde::Deserialize, Default)]
+struct TaskFilterQuery {
+    #[serde(default)]
+    status: Option<TaskStatus>,
+    #[serde(default)]
+    passed: Option<bool>,
+    #[serde(default)]
+    offset: Option<usize>,
+    #[serde(default)]
+    limit: Option<usize>,
+}
+
+impl TaskFilterQuery {
+    fn matches(&self, t: &TaskResult) -> bool {
+        if let Some(ref status) = self.status {
+            if &t.status != status {
+                return false;
+            }
+        }
+        if let Some(passed) = self.passed {
+            // Tasks that failed before producing a verdict count as not passed.
+            let verdict = t.passed.or(matches!(
+                t.status,
+                TaskStatus::Failed | TaskStatus::DiskQuotaExceeded
+            )
+            .then_some(false));
+            if verdict != Some(passed) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Matching tasks after `offset`/`limit`, plus the number of matches overall.
+    fn apply<'a>(&self, tasks: &'a [TaskResult]) -> (Vec<&'a TaskResult>, usize) {
+        let matching: Vec<&TaskResult> = tasks.iter().filter(|t| self.matches(t)).collect();
+        let total = matching.len();
+        let page = matching
+            .into_iter()
+            .skip(self.offset.unwrap_or(0))
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect();
+        (page, total)
+    }
+}
+
+#[derive(serde::Deserialize)]
+struct RescoreRequest {
+    #[serde(default)]
+    mode: crate::session::ScoringMode,
+    /// Defaults to the strategy the batch was scored with.
+    aggregation: Option<crate::session::RewardAggregation>,
+    /// Overrides the weights the tasks declared.
+    #[serde(default)]
+    weights: HashMap<String, f64>,
+    /// Store the new rewards on the batch instead of only returning them.
+    /// Needs the admin token.
+    #[serde(default)]
+    apply: bool,
+}
+
+/// Recompute a finished batch's rewards from its stored test, stage and lint
+/// results under a different scoring mode, aggregation or weights. Nothing
+/// is re-run.
+async fn rescore_batch(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    axum::extract::Path(id): axum::extract::Path<String>,
+    Json(req): Json<RescoreRequest>,
+) -> Result<Response, (StatusCode, Json<serde_json::Value>)> {
+    require_validator(&state, &headers)?;
+    if req.apply {
+        check_admin_token(&state.config, &headers)?;
+    }
+    if let Some((task_id, _)) = req
+        .weights
+        .iter()
+        .find(|(_, w)| !w.is_finite() || **w < 0.0)
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": "invalid_weight",
+                "message": format!("Weight for task '{}' must be a non-negative number", task_id),
+            })),
+        ));
+    }
+    let batch = state.sessions.get(&id).ok_or_else(|| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "not_found", "message": "Batch not found"})),
+        )
+    })?;
 
-            let total_tasks = extracted.tasks.len();
-            let batch = state.sessions.create_batch(total_tasks);
-            let batch_id = batch.id.clone();
+    let mut result = batch.result.lock().await;
+    ensure_rescorable(&result.status)?;
+    let aggregation = req.aggregation.unwrap_or(result.reward_aggregation);
+    // A batch restored from its journal has no plans until it is resumed;
+    // its results then weigh 1 like the tasks it never got to.
+    let declared: Vec<(String, f64)> = match batch.plans.get() {
+        Some(plans) => plans
+            .iter()
+            .map(|p| (p.task_id.clone(), p.weight))
+            .collect(),
+        None => result
+            .tasks
+            .iter()
+            .map(|t| (t.task_id.clone(), 1.0))
+            .collect(),
+    };
+    let task_weights: Vec<(String, f64)> = declared
+        .into_iter()
+        .map(|(id, weight)| {
+            let weight = req.weights.get(&id).copied().unwrap_or(weight);
+            (id, weight)
+        })
+        .collect();
+    let (rewards, aggregate_reward) = result.rescore(req.mode, aggregation, &task_weights);
 
-            let env = state.agent_env.read().await.clone();
-            state
-                .executor
-                .spawn_batch(batch, extracted, effective_concurrent, env);
+    let tasks: Vec<serde_json::Value> = result
+        .tasks
+        .iter()
+        .zip(&rewards)
+        .map(|(task, (task_id, reward))| {
+            serde_json::json!({
+                "task_id": task_id,
+                "reward": reward,
+                "previous_reward": task.reward,
+            })
+        })
+        .collect();
+    let body = serde_json::json!({
+        "batch_id": result.batch_id,
+        "mode": req.mode,
+        "reward_aggregation": aggregation,
+        "aggregate_reward": aggregate_reward,
+        "previous_aggregate_reward": result.aggregate_reward,
+        "applied": req.apply,
+        "tasks": tasks,
+    });
 
-            Ok((
-                StatusCode::ACCEPTED,
-                Json(serde_json::json!({
-                    "batch_id": batch_id,
-                    "total_tasks": total_tasks,
-                    "concurrent_tasks": effective_concurrent,
-                    "ws_url": format!("/ws?batch_id={}", batch_id),
-                    "consensus_reached": true,
-                    "votes": votes,
-                    "required": required,
-                })),
-            ))
+    if req.apply {
+        for (task, (_, reward)) in result.tasks.iter_mut().zip(&rewards) {
+            task.reward = *reward;
         }
+        result.aggregate_reward = aggregate_reward;
+        result.reward_aggregation = aggregation;
     }
+
+    Ok(signed_json(
+        state.response_signer.as_deref(),
+        StatusCode::OK,
+        &body,
+    ))
 }
 
-async fn get_batch(
+/// The agent a batch evaluated: language and code hash, plus the code when
+/// `RETAIN_BATCH_AGENT_CODE` is on. Validators only, since it's the miner's
+/// submission.
+async fn get_batch_agent(
     State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
     axum::extract::Path(id): axum::extract::Path<String>,
-) -> Result<Json<serde_json::Value>, StatusCode> {
-    let batch = state.sessions.get(&id).ok_or(StatusCode::NOT_FOUND)?;
-    let result = batch.result.lock().await;
-
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    require_validator(&state, &headers)?;
+    let not_found = |message: &str| {
+        (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "not_found", "message": message})),
+        )
+    };
+    let batch = state
+        .sessions
+        .get(&id)
+        .ok_or_else(|| not_found("Batch not found"))?;
+    let agent = batch
+        .agent
+        .get()
+        .ok_or_else(|| not_found("No agent recorded for this batch"))?;
     Ok(Json(serde_json::json!({
-        "batch_id": result.batch_id,
-        "status": result.status,
-        "total_tasks": result.total_tasks,
-        "completed_tasks": result.completed_tasks,
-        "passed_tasks": result.passed_tasks,
-        "failed_tasks": result.failed_tasks,
-        "aggregate_reward": result.aggregate_reward,
-        "error": result.error,
-        "duration_ms": result.duration_ms,
+        "batch_id": batch.id,
+        "agent": agent,
     })))
 }
 
 async fn get_batch_tasks(
     State(state): State<Arc<AppState>>,
     axum::extract::Path(id): axum::extract::Path<String>,
+    axum::extract::Query(filter): axum::extract::Query<TaskFilterQuery>,
 ) -> Result<Json<serde_json::Value>, StatusCode> {
     let batch = state.sessions.get(&id).ok_or(StatusCode::NOT_FOUND)?;
     let result = batch.result.lock().await;
 
-    let tasks: Vec<serde_json::Value> = result
-        .tasks
-        .iter()
+    let (matching, total) = filter.apply(&result.tasks);
+    let tasks: Vec<serde_json::Value> = matching
+        .into_iter()
         .map(|t| {
             serde_json::json!({
                 "task_id": t.task_id,
@@ -782,18 +2146,78 @@ async fn get_batch_tasks(
                 "test_output": t.test_output,
                 "agent_output": t.agent_output,
                 "agent_patch": t.agent_patch,
+                "suspicious_agent": t.suspicious_agent,
                 "error": t.error,
                 "duration_ms": t.duration_ms,
+                "queue_ms": t.queue_ms,
+                "exec_ms": t.exec_ms,
             })
         })
         .collect();
 
     Ok(Json(serde_json::json!({
         "batch_id": result.batch_id,
+        "total": total,
         "tasks": tasks,
     })))
 }
 
+/// Apply `DUPLICATE_TASKS` to a batch before it is created.
+fn dedupe_batch_tasks(
+    config: &Config,
+    tasks: &mut Vec<crate::task::SweForgeTask>,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    let dropped = crate::task::dedupe_tasks(tasks, config.duplicate_tasks).map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({
+                "error": "duplicate_tasks",
+                "message": e.to_string(),
+            })),
+        )
+    })?;
+    if !dropped.is_empty() {
+        warn!(
+            "Coalesced {} duplicate task(s): {}",
+            dropped.len(),
+            dropped.join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[derive(serde::Deserialize)]
+struct PlanQuery {
+    #[serde(default)]
+    include_scripts: bool,
+}
+
+/// What a task will run (repo, commit, install commands, test script names),
+/// available as soon as its batch starts. Validators only, since the agent
+/// can reach this API. `?include_scripts=true` adds the script contents
+/// once the task has finished.
+async fn get_task_plan(
+    State(state): State<Arc<AppState>>,
+    headers: axum::http::HeaderMap,
+    axum::extract::Path((batch_id, task_id)): axum::extract::Path<(String, String)>,
+    query: axum::extract::Query<PlanQuery>,
+) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
+    require_validator(&state, &headers)?;
+    let not_found = || {
+        (
+            StatusCode::NOT_FOUND,
+            Json(serde_json::json!({"error": "not_found", "message": "Unknown batch or task"})),
+        )
+    };
+    let batch = state.sessions.get(&batch_id).ok_or_else(not_found)?;
+    let plan = batch
+        .plans
+        .get()
+        .and_then(|plans| plans.iter().find(|p| p.task_id == task_id))
+        .ok_or_else(not_found)?;
+    Ok(Json(plan.to_json(query.include_scripts)))
+}
+
 async fn get_task(
     State(state): State<Arc<AppState>>,
     axum::extract::Path((batch_id, task_id)): axum::extract::Path<(String, String)>,
@@ -801,11 +2225,12 @@ async fn get_task(
     let batch = state.sessions.get(&batch_id).ok_or(StatusCode::NOT_FOUND)?;
     let result = batch.result.lock().await;
 
-    let task = result
-        .tasks
-        .iter()
-        .find(|t| t.task_id == task_id)
-        .ok_or(StatusCode::NOT_FOUND)?;
+    let mut matches = result.tasks.iter().filter(|t| t.task_id == task_id);
+    let task = matches.next().ok_or(StatusCode::NOT_FOUND)?;
+    // Batches are deduplicated on submit; never pick one of two silently.
+    if matches.next().is_some() {
+        return Err(StatusCode::CONFLICT);
+    }
 
     Ok(Json(serde_json::json!({
         "task_id": task.task_id,
@@ -816,8 +2241,20 @@ async fn get_task(
         "test_output": task.test_output,
         "agent_output": task.agent_output,
         "agent_patch": task.agent_patch,
+        "agent_patch_hash": task.agent_patch_hash,
+        "agent_output_changed": task.agent_output_changed,
+        "agent_report": task.agent_report,
+        "suspicious_agent": task.suspicious_agent,
         "error": task.error,
+        "failure_kind": task.failure_kind,
+        "phase_errors": task.phase_errors,
+        "artifacts": task.artifacts,
+        "stages": task.stages,
+        "lint": task.lint,
+        "peak_memory_kb": task.peak_memory_kb,
         "duration_ms": task.duration_ms,
+        "queue_ms": task.queue_ms,
+        "exec_ms": task.exec_ms,
     })))
 }
 
@@ -852,6 +2289,7 @@ struct ExecutionProof {
     passed_tasks: usize,
     failed_tasks: usize,
     aggregate_reward: f64,
+    reward_aggregation: crate::session::RewardAggregation,
     /// SHA256 hash of: batch_id + task results (task_id, passed, reward) sorted
     results_hash: String,
     /// Per-task summary
@@ -919,6 +2357,7 @@ async fn verify_batch(
         passed_tasks: result.passed_tasks,
         failed_tasks: result.failed_tasks,
         aggregate_reward: result.aggregate_reward,
+        reward_aggregation: result.reward_aggregation,
         results_hash,
         task_summaries,
         executor_version: env!("CARGO_PKG_VERSION").to_string(),
@@ -962,12 +2401,53 @@ async fn instance_info(State(state): State<Arc<AppState>>) -> Json<InstanceInfo>
     })
 }
 
+/// HuggingFace client honouring `HF_FETCH_MAX_ATTEMPTS`.
+fn hf_client(config: &Config) -> anyhow::Result<crate::swe_forge::client::HuggingFaceClient> {
+    Ok(crate::swe_forge::client::HuggingFaceClient::new()?
+        .with_max_attempts(config.hf_fetch_max_attempts))
+}
+
+/// Only a finished batch has results to rescore; a queued one hasn't run yet.
+fn ensure_rescorable(status: &BatchStatus) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    if status.is_terminal() {
+        return Ok(());
+    }
+    Err((
+        StatusCode::CONFLICT,
+        Json(serde_json::json!({
+            "error": "batch_running",
+            "message": "Only finished batches can be rescored",
+        })),
+    ))
+}
+
+/// Reject requests that would load tasks from HuggingFace when the operator
+/// has disabled that source.
+fn ensure_huggingface_allowed(
+    config: &Config,
+) -> Result<(), (StatusCode, Json<serde_json::Value>)> {
+    if config.allow_huggingface_source {
+        return Ok(());
+    }
+    Err((
+        StatusCode::FORBIDDEN,
+        Json(serde_json::json!({
+            "error": "source_disabled",
+            "message": "HuggingFace-sourced tasks are disabled on this executor. Submit an archive instead."
+        })),
+    ))
+}
+
 /// Fetch SWE-bench tasks from HuggingFace CortexLM/swe-forge dataset.
-/// Query params: ?split=test&limit=10&offset=0&difficulty=hard
+/// Query params: ?split=test&limit=10&offset=0&difficulty=hard, or
+/// ?instance_ids=a,b to fetch named instances.
 async fn fetch_dataset(
+    State(state): State<Arc<AppState>>,
     axum::extract::Query(query): axum::extract::Query<DatasetQuery>,
 ) -> Result<Json<serde_json::Value>, (StatusCode, Json<serde_json::Value>)> {
-    let client = crate::swe_forge::client::HuggingFaceClient::new().map_err(|e| {
+    ensure_huggingface_allowed(&state.config)?;
+
+    let client = hf_client(&state.config).map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(serde_json::json!({"error": format!("Failed to create HF client: {}", e)})),
@@ -983,6 +2463,15 @@ async fn fetch_dataset(
         split: split.clone(),
         limit,
         offset,
+        instance_ids: query
+            .instance_ids
+            .as_deref()
+            .unwrap_or_default()
+            .split(',')
+            .map(|id| id.trim().to_string())
+            .filter(|id| !id.is_empty())
+            .take(100)
+            .collect(),
     };
 
     let dataset = client.fetch_dataset(&config).await.map_err(|e| {
@@ -1036,6 +2525,8 @@ struct DatasetQuery {
     limit: Option<usize>,
     offset: Option<usize>,
     difficulty: Option<String>,
+    /// Comma-separated instance ids to look up instead of an offset range.
+    instance_ids: Option<String>,
 }
 
 /// Request body for /submit_tasks: validators provide task IDs to execute.
@@ -1061,6 +2552,8 @@ async fn submit_tasks(
     headers: axum::http::HeaderMap,
     mut multipart: Multipart,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    reject_if_draining(&state)?;
+
     // Auth check
     let auth_headers = auth::extract_auth_headers(&headers).ok_or_else(|| {
         (
@@ -1096,6 +2589,8 @@ async fn submit_tasks(
         ));
     }
 
+    ensure_huggingface_allowed(&state.config)?;
+
     // Parse multipart: expect "task_ids" (JSON) and "archive" (file)
     let mut task_ids: Option<Vec<String>> = None;
     let mut split = "train".to_string();
@@ -1163,7 +2658,7 @@ async fn submit_tasks(
     }
 
     // Fetch full dataset from HuggingFace to find matching tasks
-    let hf_client = crate::swe_forge::client::HuggingFaceClient::new().map_err(|e| {
+    let hf_client = hf_client(&state.config).map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(serde_json::json!({"error": format!("HF client error: {}", e)})),
@@ -1173,8 +2668,9 @@ async fn submit_tasks(
     let config = crate::swe_forge::types::DatasetConfig {
         dataset_id: "CortexLM/swe-forge".to_string(),
         split,
-        limit: 100, // fetch all (dataset has 66 rows currently)
+        limit: 100,
         offset: 0,
+        instance_ids: task_ids.clone(),
     };
 
     let dataset = hf_client.fetch_dataset(&config).await.map_err(|e| {
@@ -1202,7 +2698,7 @@ async fn submit_tasks(
             Json(serde_json::json!({
                 "error": "No matching tasks found in dataset",
                 "requested": task_ids,
-                "available_count": dataset.entries.len(),
+                "available_count": dataset.total_count,
             })),
         ));
     }
@@ -1223,22 +2719,26 @@ async fn submit_tasks(
     })?;
 
     // Extract agent code from uploaded archive
-    let extract_dir = state.config.workspace_base.join("_extract_submit_tasks");
-    let _ = tokio::fs::remove_dir_all(&extract_dir).await;
-    let extracted = crate::task::extract_uploaded_archive(&archive_bytes, &extract_dir)
-        .await
-        .map_err(|e| {
-            (
-                StatusCode::BAD_REQUEST,
-                Json(
-                    serde_json::json!({"error": format!("Failed to extract agent archive: {}", e)}),
-                ),
-            )
-        })?;
-    let _ = tokio::fs::remove_dir_all(&extract_dir).await;
+    let extract_dir = extraction_dir(&state.config, "submit_tasks")?;
+    let extracted = crate::task::extract_uploaded_archive(
+        &archive_bytes,
+        extract_dir.path(),
+        state.config.archive_root_depth,
+        state.config.max_tasks_per_batch,
+        &state.config.default_agent_language,
+    )
+    .await
+    .map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": format!("Failed to extract agent archive: {}", e)})),
+        )
+    })?;
+    drop(extract_dir);
 
     // Replace the tasks from archive with the HF tasks, but keep the agent code
-    let hf_tasks: Vec<crate::task::SweForgeTask> = registry.get_tasks().to_vec();
+    let mut hf_tasks: Vec<crate::task::SweForgeTask> = registry.get_tasks().to_vec();
+    dedupe_batch_tasks(&state.config, &mut hf_tasks)?;
     let final_archive = crate::task::ExtractedArchive {
         tasks: hf_tasks,
         agent_code: extracted.agent_code,
@@ -1246,12 +2746,15 @@ async fn submit_tasks(
         agent_archive: extracted.agent_archive,
     };
 
-    if state.sessions.has_active_batch() {
-        return Err((
-            StatusCode::SERVICE_UNAVAILABLE,
-            Json(serde_json::json!({"error": "A batch is already running"})),
-        ));
-    }
+    let slot = state
+        .sessions
+        .try_reserve_batch_slot(state.config.max_concurrent_batches)
+        .ok_or_else(|| {
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(serde_json::json!({"error": "Too many batches are already running"})),
+            )
+        })?;
 
     let total_tasks = final_archive.tasks.len();
     let batch = state.sessions.create_batch(total_tasks);
@@ -1259,9 +2762,10 @@ async fn submit_tasks(
     let concurrent = state.config.max_concurrent_tasks;
 
     let env = state.agent_env.read().await.clone();
+    reject_new_batch_if_draining(&state, &batch).await?;
     state
         .executor
-        .spawn_batch(batch, final_archive, concurrent, env);
+        .spawn_batch(batch, final_archive, concurrent, env, slot);
 
     Ok((
         StatusCode::ACCEPTED,
@@ -1283,6 +2787,8 @@ async fn evaluate_with_stored_agent(
     headers: axum::http::HeaderMap,
     Json(body): Json<serde_json::Value>,
 ) -> Result<impl IntoResponse, (StatusCode, Json<serde_json::Value>)> {
+    reject_if_draining(&state)?;
+
     // Auth: try validator hotkey first, then sudo password
     let mut authed = false;
 
@@ -1323,6 +2829,8 @@ async fn evaluate_with_stored_agent(
         ));
     }
 
+    ensure_huggingface_allowed(&state.config)?;
+
     // Parse task_ids
     let task_ids: Vec<String> = body
         .get("task_ids")
@@ -1353,7 +2861,7 @@ async fn evaluate_with_stored_agent(
     };
 
     // Download all task files from HF repo via snapshot (bulk git clone)
-    let hf_client = crate::swe_forge::client::HuggingFaceClient::new().map_err(|e| {
+    let hf_client = hf_client(&state.config).map_err(|e| {
         (
             StatusCode::INTERNAL_SERVER_ERROR,
             Json(serde_json::json!({"error": format!("HF client error: {}", e)})),
@@ -1386,6 +2894,10 @@ async fn evaluate_with_stored_agent(
 
     for task_id in &task_ids {
         // task_id format: "org/repo-number" → HF path: tasks/org/repo-number/
+        if let Err(e) = crate::task::task_dir_name(task_id) {
+            errors.push(e.to_string());
+            continue;
+        }
         let task_path = tasks_dir.join(task_id);
 
         if !task_path.exists() || !task_path.is_dir() {
@@ -1408,14 +2920,7 @@ async fn evaluate_with_stored_agent(
     // Fallback: if snapshot produced no matches, try per-task download for missing ones
     if hf_tasks.is_empty() && !task_ids.is_empty() {
         tracing::info!("No tasks found in snapshot cache, falling back to per-task download");
-        let hf_client = std::sync::Arc::new(
-            crate::swe_forge::client::HuggingFaceClient::new().map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Json(serde_json::json!({"error": format!("HF client error: {}", e)})),
-                )
-            })?,
-        );
+        let hf_client = std::sync::Arc::new(hf_client);
         let tasks_base = state.config.workspace_base.join("_hf_tasks");
 
         use futures::stream::{self, StreamExt};
@@ -1471,19 +2976,22 @@ async fn evaluate_with_stored_agent(
     }
 
     // Extract agent code only (no tasks/ required - we use HF tasks)
-    let extract_dir = state.config.workspace_base.join("_extract_evaluate");
-    let _ = tokio::fs::remove_dir_all(&extract_dir).await;
-    let (agent_code, agent_language) =
-        crate::task::extract_agent_only(&archive_bytes, &extract_dir)
-            .await
-            .map_err(|e| {
-                (
-                    StatusCode::BAD_REQUEST,
-                    Json(serde_json::json!({"error": format!("Failed to extract agent: {}", e)})),
-                )
-            })?;
-    let _ = tokio::fs::remove_dir_all(&extract_dir).await;
+    let extract_dir = extraction_dir(&state.config, "evaluate")?;
+    let (agent_code, agent_language) = crate::task::extract_agent_only(
+        &archive_bytes,
+        extract_dir.path(),
+        &state.config.default_agent_language,
+    )
+    .await
+    .map_err(|e| {
+        (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": format!("Failed to extract agent: {}", e)})),
+        )
+    })?;
+    drop(extract_dir);
 
+    dedupe_batch_tasks(&state.config, &mut hf_tasks)?;
     let final_archive = crate::task::ExtractedArchive {
         tasks: hf_tasks,
         agent_code,
@@ -1491,12 +2999,17 @@ async fn evaluate_with_stored_agent(
         agent_archive: Some(archive_bytes),
     };
 
-    if state.sessions.has_active_batch() {
-        return Err((
-            StatusCode::SERVICE_UNAVAILABLE,
-            Json(serde_json::json!({"error": "A batch is already running. Try again later."})),
-        ));
-    }
+    let slot = state
+        .sessions
+        .try_reserve_batch_slot(state.config.max_concurrent_batches)
+        .ok_or_else(|| {
+            (
+                StatusCode::SERVICE_UNAVAILABLE,
+                Json(
+                    serde_json::json!({"error": "Too many batches are already running. Try again later."}),
+                ),
+            )
+        })?;
 
     let total_tasks = final_archive.tasks.len();
     let batch = state.sessions.create_batch(total_tasks);
@@ -1504,9 +3017,10 @@ async fn evaluate_with_stored_agent(
     let concurrent = state.config.max_concurrent_tasks;
 
     let env = state.agent_env.read().await.clone();
+    reject_new_batch_if_draining(&state, &batch).await?;
     state
         .executor
-        .spawn_batch(batch, final_archive, concurrent, env);
+        .spawn_batch(batch, final_archive, concurrent, env, slot);
 
     Ok((
         StatusCode::ACCEPTED,
@@ -1787,3 +3301,467 @@ async fn basilica_get_ssh_key(
         )),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_status() -> StatusResponse {
+        StatusResponse {
+            version: "1.0.0".to_string(),
+            uptime_secs: 42,
+            active_batches: 0,
+            total_batches: 3,
+            completed_batches: 3,
+            tasks_passed: 5,
+            tasks_failed: 1,
+            max_concurrent_tasks: 6,
+            has_active_batch: false,
+            active_batch_id: None,
+            active_batch_progress: None,
+            queued_batches: 0,
+            whitelist_consecutive_failures: 0,
+            whitelist_last_refresh: None,
+            whitelist_degraded: false,
+        }
+    }
+
+    fn task_with(id: &str, status: TaskStatus, passed: Option<bool>) -> TaskResult {
+        let mut t = TaskResult::new(id.to_string());
+        t.status = status;
+        t.passed = passed;
+        t
+    }
+
+    fn task_filter(query: &str) -> TaskFilterQuery {
+        let uri: axum::http::Uri = format!("/batch/x/tasks?{}", query).parse().unwrap();
+        axum::extract::Query::<TaskFilterQuery>::try_from_uri(&uri)
+            .unwrap()
+            .0
+    }
+
+    #[test]
+    fn test_task_filter_by_status_and_passed() {
+        let tasks = vec![
+            task_with("a", TaskStatus::Completed, Some(true)),
+            task_with("b", TaskStatus::Failed, Some(false)),
+            task_with("c", TaskStatus::RunningTests, None),
+            task_with("d", TaskStatus::Failed, None),
+        ];
+
+        let (page, total) = task_filter("status=failed").apply(&tasks);
+        let ids: Vec<&str> = page.iter().map(|t| t.task_id.as_str()).collect();
+        assert_eq!(ids, vec!["b", "d"]);
+        assert_eq!(total, 2);
+
+        let (page, total) = task_filter("passed=false&limit=1&offset=1").apply(&tasks);
+        assert_eq!(total, 2);
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].task_id, "d");
+
+        let (all, total) = TaskFilterQuery::default().apply(&tasks);
+        assert_eq!((all.len(), total), (4, 4));
+    }
+
+    #[test]
+    fn test_huggingface_kill_switch() {
+        let mut config = Config::test_default();
+        assert!(ensure_huggingface_allowed(&config).is_ok());
+
+        config.allow_huggingface_source = false;
+        let (status, Json(body)) = ensure_huggingface_allowed(&config).unwrap_err();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["error"], "source_disabled");
+    }
+
+    #[test]
+    fn test_only_finished_batches_rescorable() {
+        for status in [BatchStatus::Completed, BatchStatus::Failed] {
+            assert!(ensure_rescorable(&status).is_ok());
+        }
+        for status in [
+            BatchStatus::Queued,
+            BatchStatus::Pending,
+            BatchStatus::Running,
+        ] {
+            let (code, Json(body)) = ensure_rescorable(&status).unwrap_err();
+            assert_eq!(code, StatusCode::CONFLICT);
+            assert_eq!(body["error"], "batch_running");
+        }
+    }
+
+    #[test]
+    fn test_consensus_without_concurrency_uses_default() {
+        let mut config = Config::test_default();
+        config.max_concurrent_tasks = 8;
+        config.default_consensus_concurrency = 2;
+        assert_eq!(consensus_concurrency(&config, None), 2);
+        assert_eq!(consensus_concurrency(&config, Some(5)), 5);
+        assert_eq!(consensus_concurrency(&config, Some(20)), 8);
+
+        config.default_consensus_concurrency = 32;
+        assert_eq!(consensus_concurrency(&config, None), 8);
+    }
+
+    #[tokio::test]
+    async fn test_status_yaml_when_requested() {
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert(
+            header::ACCEPT,
+            "text/html, application/yaml;q=0.9".parse().unwrap(),
+        );
+        let resp = negotiated(&headers, &sample_status());
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/yaml");
+
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let parsed: serde_yaml::Value = serde_yaml::from_slice(&body).unwrap();
+        assert_eq!(parsed["max_concurrent_tasks"].as_u64(), Some(6));
+        assert_eq!(parsed["version"].as_str(), Some("1.0.0"));
+    }
+
+    #[tokio::test]
+    async fn test_status_json_by_default() {
+        let resp = negotiated(&axum::http::HeaderMap::new(), &sample_status());
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/json");
+
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let parsed: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(parsed["total_batches"], 3);
+    }
+
+    #[tokio::test]
+    async fn test_signed_result_verifies_against_public_key() {
+        let signer = ResponseSigner::from_seed_hex(&"42".repeat(32)).unwrap();
+        let body = serde_json::json!({"batch_id": "b1", "aggregate_reward": 0.8});
+        let resp = signed_json(Some(&signer), StatusCode::OK, &body);
+        let signature = resp.headers()[SIGNATURE_HEADER]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+
+        let public_key = signer.public_key_hex();
+        assert!(crate::signing::verify(&public_key, &bytes, &signature));
+
+        let tampered = String::from_utf8(bytes.to_vec())
+            .unwrap()
+            .replace("0.8", "1.0");
+        assert!(!crate::signing::verify(
+            &public_key,
+            tampered.as_bytes(),
+            &signature
+        ));
+
+        let unsigned = signed_json(None, StatusCode::OK, &body);
+        assert!(unsigned.headers().get(SIGNATURE_HEADER).is_none());
+    }
+
+    fn sample_archive() -> Vec<u8> {
+        let mut tar_gz = Vec::new();
+        {
+            let enc = flate2::write::GzEncoder::new(&mut tar_gz, flate2::Compression::fast());
+            let mut builder = tar::Builder::new(enc);
+            for (name, content) in [
+                (
+                    "tasks/t1/workspace.yaml",
+                    "repo: https://github.com/test/repo\n",
+                ),
+                ("tasks/t1/prompt.md", "Fix it"),
+                ("agent_code/agent.py", "print('hi')\n"),
+            ] {
+                let mut header = tar::Header::new_gnu();
+                header.set_size(content.len() as u64);
+                header.set_mode(0o644);
+                header.set_cksum();
+                builder
+                    .append_data(&mut header, name, content.as_bytes())
+                    .unwrap();
+            }
+            builder.into_inner().unwrap().finish().unwrap();
+        }
+        tar_gz
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_extractions_use_distinct_dirs() {
+        let workspace = tempfile::tempdir().unwrap();
+        let mut config = Config::test_default();
+        config.workspace_base = workspace.path().to_path_buf();
+        let data = sample_archive();
+
+        let a = extraction_dir(&config, "abc").unwrap();
+        let b = extraction_dir(&config, "abc").unwrap();
+        assert_ne!(a.path(), b.path());
+
+        let (ra, rb) = tokio::join!(
+            crate::task::extract_uploaded_archive(
+                &data,
+                a.path(),
+                2,
+                0,
+                crate::task::DEFAULT_AGENT_LANGUAGE
+            ),
+            crate::task::extract_uploaded_archive(
+                &data,
+                b.path(),
+                2,
+                0,
+                crate::task::DEFAULT_AGENT_LANGUAGE
+            ),
+        );
+        assert_eq!(ra.unwrap().tasks.len(), 1);
+        assert_eq!(rb.unwrap().tasks.len(), 1);
+
+        drop(a);
+        drop(b);
+        assert_eq!(std::fs::read_dir(workspace.path()).unwrap().count(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_archive_rejected_with_413() {
+        use tower::ServiceExt;
+
+        async fn upload(mut multipart: Multipart) -> Response {
+            let spool_dir = std::env::temp_dir();
+            match read_archive_uploads(&mut multipart, 1024, &spool_dir).await {
+                Ok(data) => {
+                    Json(serde_json::json!({"len": data.iter().map(|d| d.size).sum::<usize>()}))
+                        .into_response()
+                }
+                Err(e) => e.into_response(),
+            }
+        }
+        let app = Router::new().route(
+            "/submit",
+            post(upload).layer(DefaultBodyLimit::max(1024 + MULTIPART_OVERHEAD_BYTES)),
+        );
+        let request = |payload: Vec<u8>| {
+            let mut body = b"--XX\r\nContent-Disposition: form-data; name=\"archive\"; filename=\"a.tgz\"\r\n\r\n".to_vec();
+            body.extend(payload);
+            body.extend(b"\r\n--XX--\r\n");
+            axum::http::Request::post("/submit")
+                .header(header::CONTENT_TYPE, "multipart/form-data; boundary=XX")
+                .body(axum::body::Body::from(body))
+                .unwrap()
+        };
+
+        let resp = app.clone().oneshot(request(vec![0u8; 512])).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+
+        for size in [2048, 1024 + MULTIPART_OVERHEAD_BYTES + 1] {
+            let resp = app.clone().oneshot(request(vec![0u8; size])).await.unwrap();
+            assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+            let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            let parsed: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(parsed["error"], "archive_too_large");
+            assert_eq!(parsed["max_bytes"], 1024);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_responses_compressed_on_request() {
+        use tower::ServiceExt;
+
+        let app = Router::new()
+            .route(
+                "/tasks",
+                get(|| async { Json(serde_json::json!({ "test_output": "x".repeat(4096) })) }),
+            )
+            .route(
+                "/metrics",
+                get(|| async {
+                    (
+                        [("content-type", "text/plain; version=0.0.4; charset=utf-8")],
+                        "tasks_passed 1\n".repeat(100),
+                    )
+                }),
+            )
+            .layer(compression());
+        let request = |path: &str, encoding: Option<&str>| {
+            let mut req = axum::http::Request::get(path);
+            if let Some(encoding) = encoding {
+                req = req.header(header::ACCEPT_ENCODING, encoding);
+            }
+            req.body(axum::body::Body::empty()).unwrap()
+        };
+
+        let resp = app
+            .clone()
+            .oneshot(request("/tasks", Some("gzip")))
+            .await
+            .unwrap();
+        assert_eq!(resp.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/json");
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let mut json = String::new();
+        std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(&body[..]), &mut json)
+            .unwrap();
+        assert!(json.contains(&"x".repeat(4096)));
+
+        let resp = app
+            .clone()
+            .oneshot(request("/metrics", Some("br, gzip")))
+            .await
+            .unwrap();
+        assert_eq!(resp.headers()[header::CONTENT_ENCODING], "br");
+        assert_eq!(
+            resp.headers()[header::CONTENT_TYPE],
+            "text/plain; version=0.0.4; charset=utf-8"
+        );
+
+        let resp = app.oneshot(request("/tasks", None)).await.unwrap();
+        assert!(resp.headers().get(header::CONTENT_ENCODING).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_batch_summaries_mark_unknown_ids_null() {
+        let sessions = SessionManager::new(60);
+        let batch = sessions.create_batch(4);
+        batch.result.lock().await.completed_tasks = 1;
+
+        let ids = vec![batch.id.clone(), "missing".to_string()];
+        let summaries = batch_summaries(&sessions, &ids).await;
+        assert_eq!(summaries.len(), 2);
+        assert_eq!(summaries[&batch.id]["total_tasks"], 4);
+        assert_eq!(summaries[&batch.id]["progress"], 0.25);
+        assert!(summaries["missing"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_version_reports_build_metadata() {
+        let resp = version(axum::http::HeaderMap::new()).await;
+        let body = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let info: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+        assert!(!info["git_sha"].as_str().unwrap().is_empty());
+        let rustc = info["rustc_version"].as_str().unwrap();
+        assert!(rustc.starts_with("rustc") || rustc == "unknown");
+        assert!(
+            chrono::DateTime::parse_from_rfc3339(info["build_timestamp"].as_str().unwrap()).is_ok()
+        );
+
+        let mut headers = axum::http::HeaderMap::new();
+        headers.insert(header::ACCEPT, "application/yaml".parse().unwrap());
+        let resp = version(headers).await;
+        assert_eq!(resp.headers()[header::CONTENT_TYPE], "application/yaml");
+    }
+
+    #[tokio::test]
+    async fn test_upload_spooled_to_disk() {
+        use axum::extract::FromRequest;
+
+        let spool = tempfile::tempdir().unwrap();
+        let payload = sample_archive();
+        let mut body =
+            b"--XX\r\nContent-Disposition: form-data; name=\"archive\"; filename=\"a.tgz\"\r\n\r\n"
+                .to_vec();
+        body.extend(&payload);
+        body.extend(b"\r\n--XX--\r\n");
+        let request = axum::http::Request::post("/submit")
+            .header(header::CONTENT_TYPE, "multipart/form-data; boundary=XX")
+            .body(axum::body::Body::from(body))
+            .unwrap();
+        let mut multipart = Multipart::from_request(request, &()).await.unwrap();
+
+        let archive = read_archive_uploads(&mut multipart, 1 << 20, spool.path())
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_eq!(archive.size, payload.len());
+        assert_eq!(archive.sha256, hex::encode(Sha256::digest(&payload)));
+        assert_eq!(std::fs::read(archive.path()).unwrap(), payload);
+
+        let hash = archive.sha256.clone();
+        let archive = verify_archives(vec![archive], &hash)
+            .await
+            .unwrap()
+            .pop()
+            .unwrap();
+        let (status, _) = verify_archives(vec![], &hash).await.unwrap_err();
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+
+        // Bytes swapped on disk after the vote are caught before extraction.
+        std::fs::write(archive.path(), b"tampered").unwrap();
+        let (status, Json(body)) = verify_archives(vec![archive], &hash).await.unwrap_err();
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(body["error"], "archive_integrity_mismatch");
+        assert_eq!(std::fs::read_dir(spool.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_admin_token_required() {
+        let mut config = Config::test_default();
+        let mut headers = axum::http::HeaderMap::new();
+        let (status, Json(body)) = check_admin_token(&config, &headers).unwrap_err();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["error"], "admin_disabled");
+
+        config.admin_token = Some("s3cret".to_string());
+        let (status, _) = check_admin_token(&config, &headers).unwrap_err();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        headers.insert(header::AUTHORIZATION, "Bearer wrong".parse().unwrap());
+        assert!(check_admin_token(&config, &headers).is_err());
+        headers.insert(header::AUTHORIZATION, "s3cret".parse().unwrap());
+        assert!(check_admin_token(&config, &headers).is_err());
+        headers.insert(header::AUTHORIZATION, "Bearer s3cret".parse().unwrap());
+        assert!(check_admin_token(&config, &headers).is_ok());
+    }
+
+    #[test]
+    fn test_task_subset_consensus_key() {
+        let query = |tasks: Option<&str>| SubmitQuery {
+            concurrent_tasks: None,
+            tasks: tasks.map(str::to_string),
+        };
+        assert_eq!(query(None).task_subset(), None);
+        assert_eq!(query(Some(" , ")).task_subset(), None);
+        let subset = query(Some("b, a,b")).task_subset().unwrap();
+        assert_eq!(subset, ["a", "b"]);
+        assert_eq!(query(Some("a,b")).task_subset().unwrap(), subset);
+
+        let key = subset_consensus_key("hash", &subset);
+        assert_ne!(key, "hash");
+        assert_eq!(key, subset_consensus_key("hash", &subset));
+        assert_ne!(key, subset_consensus_key("hash", &["a".to_string()]));
+        assert_ne!(key, subset_consensus_key("other", &subset));
+    }
+
+    #[test]
+    fn test_combined_digest_ignores_upload_order() {
+        let (a, b) = ("aa".repeat(32), "bb".repeat(32));
+        assert_eq!(combined_digest::<Sha256>(&[&a]), a);
+        let ab = combined_digest::<Sha256>(&[&a, &b]);
+        assert_eq!(ab, combined_digest::<Sha256>(&[&b, &a]));
+        assert_ne!(ab, a);
+        assert_eq!(ab, hex::encode(Sha256::digest(format!("{}\n{}\n", a, b))));
+    }
+
+    #[test]
+    fn test_consensus_entry_metadata() {
+        let manager = ConsensusManager::new(10, Metrics::new());
+        manager.record_vote("abc", "hotkey1", None, 3, 3, true);
+        manager.attach_archive_meta("abc", 1500, "11".repeat(32));
+        manager.record_vote("abc", "hotkey2", None, 3, 3, true);
+
+        let Json(body) = consensus_entry_response(&manager, "abc").unwrap();
+        assert_eq!(body["size_bytes"], 1500);
+        assert_eq!(body["votes"], 2);
+        assert!(body.get("archive").is_none());
+
+        let (status, _) = consensus_entry_response(&manager, "nope").unwrap_err();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,280 @@
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tracing::warn;
+
+use crate::session::TaskResult;
+use crate::task::{ExtractedArchive, SweForgeTask};
+
+/// Directory under `workspace_base` holding batch journals. The leading
+/// underscore keeps the orphan sweep away from it.
+pub const JOURNAL_DIR: &str = "_journal";
+
+/// One line of `{batch_id}.ndjson`.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum JournalEntry {
+    Batch {
+        batch_id: String,
+        total_tasks: usize,
+        concurrent_limit: usize,
+    },
+    Task {
+        result: Box<TaskResult>,
+    },
+}
+
+/// What a batch was started with, minus the agent archive (stored raw
+/// alongside) and the agent env (taken from the executor on resume).
+#[derive(Serialize, Deserialize)]
+struct JournaledArchive {
+    tasks: Vec<SweForgeTask>,
+    agent_code: String,
+    agent_language: String,
+}
+
+/// A batch found on disk at startup that never finished.
+#[derive(Debug)]
+pub struct InterruptedBatch {
+    pub batch_id: String,
+    pub total_tasks: usize,
+    pub completed: Vec<TaskResult>,
+}
+
+/// Append-only record of a running batch's finished tasks.
+pub struct BatchJournal {
+    dir: PathBuf,
+    batch_id: String,
+    lock: tokio::sync::Mutex<()>,
+}
+
+impl BatchJournal {
+    /// Start a journal for a new batch, saving everything needed to run it
+    /// again.
+    pub async fn create(
+        dir: &Path,
+        batch_id: &str,
+        archive: &ExtractedArchive,
+        concurrent_limit: usize,
+    ) -> Result<Self> {
+        tokio::fs::create_dir_all(dir)
+            .await
+            .context("Failed to create journal directory")?;
+        let journal = Self::open(dir, batch_id);
+
+        let saved = JournaledArchive {
+            tasks: archive.tasks.clone(),
+            agent_code: archive.agent_code.clone(),
+            agent_language: archive.agent_language.clone(),
+        };
+        tokio::fs::write(journal.archive_path(), serde_json::to_vec(&saved)?).await?;
+        if let Some(ref bytes) = archive.agent_archive {
+            tokio::fs::write(journal.agent_path(), bytes).await?;
+        }
+        journal
+            .append(&JournalEntry::Batch {
+                batch_id: batch_id.to_string(),
+                total_tasks: archive.tasks.len(),
+                concurrent_limit,
+            })
+            .await?;
+        Ok(journal)
+    }
+
+    /// Reattach to the journal of an interrupted batch.
+    pub fn open(dir: &Path, batch_id: &str) -> Self {
+        Self {
+            dir: dir.to_path_buf(),
+            batch_id: batch_id.to_string(),
+            lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    fn entries_path(&self) -> PathBuf {
+        self.dir.join(format!("{}.ndjson", self.batch_id))
+    }
+
+    fn archive_path(&self) -> PathBuf {
+        self.dir.join(format!("{}.archive.json", self.batch_id))
+    }
+
+    fn agent_path(&self) -> PathBuf {
+        self.dir.join(format!("{}.agent", self.batch_id))
+    }
+
+    async fn append(&self, entry: &JournalEntry) -> Result<()> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        let _guard = self.lock.lock().await;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.entries_path())
+            .await
+            .context("Failed to open batch journal")?;
+        file.write_all(&line).await?;
+        file.sync_data().await?;
+        Ok(())
+    }
+
+    pub async fn record_task(&self, result: &TaskResult) {
+        let entry = JournalEntry::Task {
+            result: Box::new(result.clone()),
+        };
+        if let Err(e) = self.append(&entry).await {
+            warn!(batch_id = %self.batch_id, "Failed to journal task {}: {:#}", result.task_id, e);
+        }
+    }
+
+    /// The batch finished (either way); nothing is left to resume.
+    pub async fn remove(&self) {
+        for path in [self.entries_path(), self.archive_path(), self.agent_path()] {
+            let _ = tokio::fs::remove_file(path).await;
+        }
+    }
+
+    /// The concurrency the batch was originally started with.
+    pub async fn concurrent_limit(&self) -> Option<usize> {
+        let content = tokio::fs::read_to_string(self.entries_path()).await.ok()?;
+        match serde_json::from_str(content.lines().next()?) {
+            Ok(JournalEntry::Batch {
+                concurrent_limit, ..
+            }) => Some(concurrent_limit),
+            _ => None,
+        }
+    }
+
+    /// Rebuild the archive the batch was started with.
+    pub async fn load_archive(&self) -> Result<ExtractedArchive> {
+        let data = tokio::fs::read(self.archive_path())
+            .await
+            .context("Journaled archive missing")?;
+        let saved: JournaledArchive =
+            serde_json::from_slice(&data).context("Corrupt journaled archive")?;
+        let agent_archive = match tokio::fs::read(self.agent_path()).await {
+            Ok(bytes) => Some(bytes),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e).context("Failed to read journaled agent archive"),
+        };
+        let mut tasks = saved.tasks;
+        for task in &mut tasks {
+            task.workspace.refresh_runtime_install();
+        }
+        Ok(ExtractedArchive {
+            tasks,
+            agent_code: saved.agent_code,
+            agent_language: saved.agent_language,
+            agent_archive,
+        })
+    }
+}
+
+/// Read every journal in `dir`. A truncated final line (from a crash
+/// mid-write) is ignored.
+pub fn scan(dir: &Path) -> Vec<InterruptedBatch> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut batches = Vec::new();
+    for entry in entries.filter_map(|e| e.ok()) {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some("ndjson") {
+            continue;
+        }
+        let Ok(content) = std::fs::read_to_string(&path) else {
+            continue;
+        };
+        let mut lines = content.lines();
+        let Some(Ok(JournalEntry::Batch {
+            batch_id,
+            total_tasks,
+            ..
+        })) = lines.next().map(serde_json::from_str::<JournalEntry>)
+        else {
+            warn!("Ignoring journal without a header: {}", path.display());
+            continue;
+        };
+        let completed = lines
+            .filter_map(|l| match serde_json::from_str(l) {
+                Ok(JournalEntry::Task { result }) => Some(*result),
+                _ => None,
+            })
+            .collect();
+        batches.push(InterruptedBatch {
+            batch_id,
+            total_tasks,
+            completed,
+        });
+    }
+    batches
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::TaskStatus;
+
+    #[tokio::test]
+    async fn test_journal_round_trip() {
+        let tmp = tempfile::tempdir().unwrap();
+        let task_dir = tmp.path().join("tasks/t1");
+        std::fs::create_dir_all(&task_dir).unwrap();
+        std::fs::write(
+            task_dir.join("workspace.yaml"),
+            "repo: o/r\ninstall_config:\n  python: \"3.11\"\n",
+        )
+        .unwrap();
+        std::fs::write(task_dir.join("prompt.md"), "Fix it").unwrap();
+        let task = crate::task::parse_task(&task_dir).unwrap();
+        let archive = ExtractedArchive {
+            tasks: vec![
+                task.clone(),
+                SweForgeTask {
+                    id: "t2".into(),
+                    ..task
+                },
+            ],
+            agent_code: "print(1)".into(),
+            agent_language: "python".into(),
+            agent_archive: Some(vec![1, 2, 3]),
+        };
+
+        let dir = tmp.path().join(JOURNAL_DIR);
+        let journal = BatchJournal::create(&dir, "b1", &archive, 4).await.unwrap();
+        let mut done = TaskResult::new("t1".into());
+        done.status = TaskStatus::Completed;
+        done.passed = Some(true);
+        journal.record_task(&done).await;
+        // A crash mid-write leaves a partial line behind.
+        std::fs::OpenOptions::new()
+            .append(true)
+            .open(dir.join("b1.ndjson"))
+            .and_then(|mut f| std::io::Write::write_all(&mut f, b"{\"type\":\"ta"))
+            .unwrap();
+
+        let found = scan(&dir);
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].batch_id, "b1");
+        assert_eq!(found[0].total_tasks, 2);
+        assert_eq!(found[0].completed.len(), 1);
+        assert_eq!(found[0].completed[0].task_id, "t1");
+
+        let restored = BatchJournal::open(&dir, "b1").load_archive().await.unwrap();
+        assert_eq!(restored.tasks.len(), 2);
+        assert_eq!(restored.tasks[1].id, "t2");
+        assert_eq!(
+            restored.tasks[0].workspace.runtime_install,
+            archive.tasks[0].workspace.runtime_install
+        );
+        assert_eq!(restored.agent_archive, Some(vec![1, 2, 3]));
+        assert_eq!(
+            BatchJournal::open(&dir, "b1").concurrent_limit().await,
+            Some(4)
+        );
+
+        journal.remove().await;
+        assert!(scan(&dir).is_empty());
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+    }
+}
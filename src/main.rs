@@ -5,17 +5,24 @@ mod cleanup;
 mod config;
 mod consensus;
 mod evaluation;
+#[cfg(feature = "nats")]
+mod event_sink;
 mod executor;
 mod handlers;
+mod journal;
 mod metrics;
+mod rate_limit;
+mod sandbox;
 mod session;
+mod signing;
 mod swe_forge;
 mod task;
 mod validator_whitelist;
+mod webhook;
 mod ws;
 
 use std::sync::Arc;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 #[tokio::main]
 async fn main() {
@@ -37,15 +44,48 @@ async fn main() {
         }
     };
     config.print_banner();
+    for (key, program) in config.agent_runners.missing() {
+        tracing::warn!(
+            "Agent runner `{}` ({}) not found; agents in that language will fail",
+            program,
+            key
+        );
+    }
 
     if let Err(e) = tokio::fs::create_dir_all(&config.workspace_base).await {
         error!("Failed to create workspace directory: {}", e);
         std::process::exit(1);
     }
+    if let Err(e) = executor::prepare_shared_cache(&config).await {
+        warn!("Failed to prepare shared cache dir: {}", e);
+    }
 
     let sessions = Arc::new(session::SessionManager::new(config.session_ttl_secs));
+    if config.batch_journal {
+        let journal_dir = config.workspace_base.join(journal::JOURNAL_DIR);
+        for interrupted in journal::scan(&journal_dir) {
+            info!(
+                "Batch {} was interrupted with {}/{} tasks done; resumable",
+                interrupted.batch_id,
+                interrupted.completed.len(),
+                interrupted.total_tasks
+            );
+            sessions.restore_batch(
+                &interrupted.batch_id,
+                interrupted.total_tasks,
+                interrupted.completed,
+            );
+        }
+    }
     let metrics_store = metrics::Metrics::new();
-    let nonce_store = Arc::new(auth::NonceStore::new());
+    let nonce_store = Arc::new(
+        auth::NonceStore::new(std::time::Duration::from_secs(config.nonce_ttl_secs))
+            .with_signing_context(config.signing_context.as_bytes())
+            .with_bytes_wrapped(config.signing_accept_bytes_wrapped),
+    );
+    let submit_limiter = Arc::new(rate_limit::SubmitRateLimiter::new(
+        config.submit_rate_per_min,
+    ));
 
     let basilica_client = config.basilica_api_token.as_ref().and_then(|token| {
         match basilica::client::BasilicaClient::new(token) {
@@ -60,12 +100,70 @@ async fn main() {
         }
     });
 
-    let executor = Arc::new(executor::Executor::new(
+    let response_signer = match config.response_signing_key.as_deref() {
+        Some(seed) => match signing::ResponseSigner::from_seed_hex(seed) {
+            Ok(signer) => {
+                info!("Signing responses with key {}", signer.public_key_hex());
+                Some(Arc::new(signer))
+            }
+            Err(e) => {
+                error!("Invalid RESPONSE_SIGNING_KEY: {}", e);
+                std::process::exit(1);
+            }
+        },
+        None => None,
+    };
+
+    let consensus_webhook =
+        config.consensus_webhook_url.as_deref().and_then(
+            |url| match webhook::ConsensusWebhook::new(
+                url,
+                response_signer.clone(),
+                metrics_store.clone(),
+            ) {
+                Ok(hook) => {
+                    info!("Sending consensus notifications to {}", url);
+                    Some(Arc::new(hook))
+                }
+                Err(e) => {
+                    error!("Failed to set up consensus webhook: {}", e);
+                    None
+                }
+            },
+        );
+
+    let executor = executor::Executor::new(
         config.clone(),
         sessions.clone(),
         metrics_store.clone(),
         basilica_client.clone(),
-    ));
+    );
+    #[cfg(feature = "nats")]
+    let executor = match config.nats_url {
+        Some(ref nats_url) => match event_sink::nats::NatsSink::connect(nats_url).await {
+            Ok(sink) => {
+                info!("Publishing batch events to NATS at {}", nats_url);
+                executor.with_event_forwarder(Arc::new(event_sink::EventForwarder::new(
+                    Arc::new(sink),
+                    &config.event_subject_prefix,
+                    metrics_store.clone(),
+                )))
+            }
+            Err(e) => {
+                error!("Failed to set up NATS event sink: {}", e);
+                executor
+            }
+        },
+        None => executor,
+    };
+    #[cfg(not(feature = "nats"))]
+    if let Some(ref nats_url) = config.nats_url {
+        warn!(
+            "NATS_URL={} is set but term-executor was built without the `nats` feature",
+            nats_url
+        );
+    }
+    let executor = Arc::new(executor);
 
     let validator_whitelist = validator_whitelist::ValidatorWhitelist::new();
     if !config.trusted_validators.is_empty() {
@@ -75,20 +173,24 @@ async fn main() {
         );
         validator_whitelist.add_trusted(&config.trusted_validators);
     }
-    let consensus_manager = consensus::ConsensusManager::new(config.max_pending_consensus);
+    let consensus_manager =
+        consensus::ConsensusManager::new(config.max_pending_consensus, metrics_store.clone());
 
     let state = Arc::new(handlers::AppState {
         config: config.clone(),
         sessions: sessions.clone(),
-        metrics: metrics_store,
-        executor,
+        metrics: metrics_store.clone(),
+        executor: executor.clone(),
         nonce_store: nonce_store.clone(),
+        submit_limiter: submit_limiter.clone(),
         started_at: chrono::Utc::now(),
         validator_whitelist: validator_whitelist.clone(),
         consensus_manager: consensus_manager.clone(),
         agent_archive: Arc::new(tokio::sync::RwLock::new(None)),
         agent_env: Arc::new(tokio::sync::RwLock::new(std::collections::HashMap::new())),
         basilica_client,
+        response_signer,
+        consensus_webhook,
     });
 
     let app = handlers::router(state);
@@ -100,8 +202,13 @@ async fn main() {
     });
 
     let nonce_reaper = nonce_store.clone();
+    let nonce_reap_every = std::time::Duration::from_secs(config.nonce_reap_interval_secs);
     tokio::spawn(async move {
-        nonce_reaper.reaper_loop().await;
+        nonce_reaper.reaper_loop(nonce_reap_every).await;
+    });
+
+    tokio::spawn(async move {
+        submit_limiter.reaper_loop().await;
     });
 
     let workspace = config.workspace_base.clone();
@@ -114,18 +221,28 @@ async fn main() {
         }
     });
 
+    tokio::spawn(cleanup::integrity_loop(
+        config.workspace_base.clone(),
+        config.shared_cache_dir.clone(),
+        sessions.clone(),
+        metrics_store.clone(),
+    ));
+
     let wl = validator_whitelist.clone();
     let netuid = config.bittensor_netuid;
     let min_stake = config.min_validator_stake_tao;
     let refresh_secs = config.validator_refresh_secs;
+    let failure_threshold = config.whitelist_failure_threshold;
     tokio::spawn(async move {
-        wl.refresh_loop(netuid, min_stake, refresh_secs).await;
+        wl.refresh_loop(netuid, min_stake, refresh_secs, failure_threshold)
+            .await;
     });
 
     let cm = consensus_manager.clone();
     let consensus_ttl = config.consensus_ttl_secs;
+    let consensus_reap_every = config.consensus_reap_interval_secs;
     tokio::spawn(async move {
-        cm.reaper_loop(consensus_ttl).await;
+        cm.reaper_loop(consensus_ttl, consensus_reap_every).await;
     });
 
     info!("Listening on {}", addr);
@@ -137,12 +254,32 @@ async fn main() {
         }
     };
 
-    let shutdown = async {
-        if let Err(e) = tokio::signal::ctrl_c().await {
-            error!("Failed to install CTRL+C handler: {}", e);
-            return;
+    let drain_sessions = sessions.clone();
+    let drain_executor = executor.clone();
+    let drain_timeout_secs = config.drain_timeout_secs;
+    let shutdown = async move {
+        let signal = match shutdown_signal().await {
+            Ok(signal) => signal,
+            Err(e) => {
+                error!("Failed to install shutdown signal handler: {}", e);
+                return;
+            }
+        };
+        info!(
+            "{} received, draining (up to {}s)...",
+            signal, drain_timeout_secs
+        );
+        drain_sessions.begin_drain();
+        if !drain_sessions
+            .wait_until_idle(std::time::Duration::from_secs(drain_timeout_secs))
+            .await
+        {
+            tracing::warn!("Drain timeout reached, cancelling running batches");
+            drain_sessions.cancel_all();
+            if !drain_executor.wait_for_batches(CANCEL_GRACE).await {
+                tracing::warn!("Cancelled batches did not finish within {:?}", CANCEL_GRACE);
+            }
         }
-        info!("Shutdown signal received, draining...");
     };
 
     if let Err(e) = axum::serve(listener, app)
@@ -155,3 +292,25 @@ async fn main() {
 
     info!("Shutdown complete");
 }
+
+/// How long cancelled batches get to kill their commands and store their
+/// results once the drain timeout has passed.
+const CANCEL_GRACE: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Resolves with the name of the first shutdown signal received. Orchestrators
+/// stop containers with SIGTERM, so it must drain just like CTRL+C.
+#[cfg(unix)]
+async fn shutdown_signal() -> std::io::Result<&'static str> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate())?;
+    tokio::select! {
+        res = tokio::signal::ctrl_c() => res.map(|_| "SIGINT"),
+        _ = terminate.recv() => Ok("SIGTERM"),
+    }
+}
+
+#[cfg(not(unix))]
+async fn shutdown_signal() -> std::io::Result<&'static str> {
+    tokio::signal::ctrl_c().await.map(|_| "CTRL+C")
+}
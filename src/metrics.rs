@@ -1,6 +1,100 @@
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
 
+/// Upper bounds, in seconds, for duration histograms. SWE tasks range from
+/// instant failures to long test suites, so the buckets span 0.5s to 1h.
+const DURATION_BUCKETS_SECS: &[f64] = &[
+    0.5, 1.0, 5.0, 15.0, 30.0, 60.0, 120.0, 300.0, 600.0, 900.0, 1200.0, 1800.0, 3600.0,
+];
+
+/// Upper bounds, in bytes, for the task peak-memory histogram: 64 MiB to
+/// 32 GiB in powers of two.
+const MEMORY_BUCKETS_BYTES: &[f64] = &[
+    67108864.0,
+    134217728.0,
+    268435456.0,
+    536870912.0,
+    1073741824.0,
+    2147483648.0,
+    4294967296.0,
+    8589934592.0,
+    17179869184.0,
+    34359738368.0,
+];
+
+/// Cumulative Prometheus histogram. Observations are recorded as integers in
+/// a raw unit (milliseconds, KiB) and rendered as `raw * mul / div`.
+#[derive(Debug)]
+pub struct Histogram {
+    bounds: &'static [f64],
+    mul: f64,
+    div: f64,
+    buckets: Vec<AtomicU64>,
+    count: AtomicU64,
+    sum: AtomicU64,
+}
+
+impl Histogram {
+    fn new(bounds: &'static [f64], mul: f64, div: f64) -> Self {
+        Self {
+            bounds,
+            mul,
+            div,
+            buckets: bounds.iter().map(|_| AtomicU64::new(0)).collect(),
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0),
+        }
+    }
+
+    /// Millisecond durations, rendered in seconds.
+    fn durations() -> Self {
+        Self::new(DURATION_BUCKETS_SECS, 1.0, 1000.0)
+    }
+
+    /// KiB sizes, rendered in bytes.
+    fn memory() -> Self {
+        Self::new(MEMORY_BUCKETS_BYTES, 1024.0, 1.0)
+    }
+
+    pub fn observe(&self, raw: u64) {
+        let value = self.scaled(raw);
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            if value <= *bound {
+                bucket.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        self.count.fetch_add(1, Ordering::Relaxed);
+        self.sum.fetch_add(raw, Ordering::Relaxed);
+    }
+
+    fn scaled(&self, raw: u64) -> f64 {
+        raw as f64 * self.mul / self.div
+    }
+
+    pub fn observe_ms(&self, ms: u64) {
+        self.observe(ms);
+    }
+
+    fn render(&self, name: &str, help: &str) -> String {
+        let mut out = format!("# HELP {name} {help}\n# TYPE {name} histogram\n");
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            out.push_str(&format!(
+                "{name}_bucket{{le=\"{}\"}} {}\n",
+                bound,
+                bucket.load(Ordering::Relaxed)
+            ));
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        out.push_str(&format!("{name}_bucket{{le=\"+Inf\"}} {count}\n"));
+        out.push_str(&format!(
+            "{name}_sum {}\n",
+            self.scaled(self.sum.load(Ordering::Relaxed))
+        ));
+        out.push_str(&format!("{name}_count {count}\n"));
+        out
+    }
+}
+
 #[derive(Debug)]
 pub struct Metrics {
     pub batches_total: AtomicU64,
@@ -10,6 +104,18 @@ pub struct Metrics {
     pub tasks_passed: AtomicU64,
     pub tasks_failed: AtomicU64,
     pub duration_sum_ms: AtomicU64,
+    pub events_dropped: AtomicU64,
+    /// Webhook notifications given up on after every retry.
+    pub webhook_failures: AtomicU64,
+    pub consensus_pending: AtomicU64,
+    pub consensus_votes_total: AtomicU64,
+    pub consensus_reached_total: AtomicU64,
+    pub consensus_expired_total: AtomicU64,
+    pub orphans_cleaned: AtomicU64,
+    pub task_duration: Histogram,
+    pub batch_duration: Histogram,
+    /// Peak resident memory of finished tasks, observed in KiB.
+    pub task_peak_memory: Histogram,
 }
 
 impl Metrics {
@@ -22,6 +128,16 @@ impl Metrics {
             tasks_passed: AtomicU64::new(0),
             tasks_failed: AtomicU64::new(0),
             duration_sum_ms: AtomicU64::new(0),
+            events_dropped: AtomicU64::new(0),
+            webhook_failures: AtomicU64::new(0),
+            consensus_pending: AtomicU64::new(0),
+            consensus_votes_total: AtomicU64::new(0),
+            consensus_reached_total: AtomicU64::new(0),
+            consensus_expired_total: AtomicU64::new(0),
+            orphans_cleaned: AtomicU64::new(0),
+            task_duration: Histogram::durations(),
+            batch_duration: Histogram::durations(),
+            task_peak_memory: Histogram::memory(),
         })
     }
 
@@ -35,6 +151,7 @@ impl Metrics {
         self.batches_completed.fetch_add(1, Ordering::Relaxed);
         self.duration_sum_ms
             .fetch_add(duration_ms, Ordering::Relaxed);
+        self.batch_duration.observe_ms(duration_ms);
         if all_passed {
             self.tasks_passed.fetch_add(1, Ordering::Relaxed);
         }
@@ -58,8 +175,15 @@ impl Metrics {
         let tasks_passed = self.tasks_passed.load(Ordering::Relaxed);
         let tasks_failed = self.tasks_failed.load(Ordering::Relaxed);
         let dur_sum = self.duration_sum_ms.load(Ordering::Relaxed);
+        let events_dropped = self.events_dropped.load(Ordering::Relaxed);
+        let webhook_failures = self.webhook_failures.load(Ordering::Relaxed);
+        let consensus_pending = self.consensus_pending.load(Ordering::Relaxed);
+        let consensus_votes = self.consensus_votes_total.load(Ordering::Relaxed);
+        let consensus_reached = self.consensus_reached_total.load(Ordering::Relaxed);
+        let consensus_expired = self.consensus_expired_total.load(Ordering::Relaxed);
+        let orphans_cleaned = self.orphans_cleaned.load(Ordering::Relaxed);
 
-        format!(
+        let mut out = format!(
             "# HELP term_executor_batches_total Total batches submitted.\n\
              # TYPE term_executor_batches_total counter\n\
              term_executor_batches_total {}\n\
@@ -80,15 +204,56 @@ impl Metrics {
              term_executor_tasks_failed {}\n\
              # HELP term_executor_duration_ms_sum Sum of batch durations in ms.\n\
              # TYPE term_executor_duration_ms_sum counter\n\
-             term_executor_duration_ms_sum {}\n",
+             term_executor_duration_ms_sum {}\n\
+             # HELP term_executor_events_dropped Events the external event sink could not deliver.\n\
+             # TYPE term_executor_events_dropped counter\n\
+             term_executor_events_dropped {}\n\
+             # HELP term_executor_webhook_failures Consensus webhook notifications that could not be delivered.\n\
+             # TYPE term_executor_webhook_failures counter\n\
+             term_executor_webhook_failures {}\n\
+             # HELP term_executor_consensus_pending Archives waiting for enough validator votes.\n\
+             # TYPE term_executor_consensus_pending gauge\n\
+             term_executor_consensus_pending {}\n\
+             # HELP term_executor_consensus_votes_total Validator votes recorded.\n\
+             # TYPE term_executor_consensus_votes_total counter\n\
+             term_executor_consensus_votes_total {}\n\
+             # HELP term_executor_consensus_reached_total Archives that reached consensus.\n\
+             # TYPE term_executor_consensus_reached_total counter\n\
+             term_executor_consensus_reached_total {}\n\
+             # HELP term_executor_consensus_expired_total Pending consensus entries reaped by TTL.\n\
+             # TYPE term_executor_consensus_expired_total counter\n\
+             term_executor_consensus_expired_total {}\n\
+             # HELP term_executor_orphans_cleaned Work dirs removed because no retained batch owns them.\n\
+             # TYPE term_executor_orphans_cleaned counter\n\
+             term_executor_orphans_cleaned {}\n",
             batches_total,
             batches_active,
             batches_completed,
             tasks_total,
             tasks_passed,
             tasks_failed,
-            dur_sum
-        )
+            dur_sum,
+            events_dropped,
+            webhook_failures,
+            consensus_pending,
+            consensus_votes,
+            consensus_reached,
+            consensus_expired,
+            orphans_cleaned
+        );
+        out.push_str(&self.task_duration.render(
+            "term_executor_task_duration_seconds",
+            "Wall time of finished tasks.",
+        ));
+        out.push_str(&self.batch_duration.render(
+            "term_executor_batch_duration_seconds",
+            "Wall time of finished batches.",
+        ));
+        out.push_str(&self.task_peak_memory.render(
+            "term_executor_task_peak_memory_bytes",
+            "Peak resident memory of finished tasks.",
+        ));
+        out
     }
 }
 
@@ -117,4 +282,38 @@ mod tests {
         assert!(out.contains("term_executor_batches_total 1"));
         assert!(out.contains("term_executor_duration_ms_sum 1234"));
     }
+
+    #[test]
+    fn test_duration_histograms() {
+        let m = Metrics::new();
+        for ms in [200, 3_000, 45_000, 7_200_000] {
+            m.task_duration.observe_ms(ms);
+        }
+        m.finish_batch(true, 90_000);
+        let out = m.render_prometheus();
+        assert!(out.contains("# TYPE term_executor_task_duration_seconds histogram"));
+        assert!(out.contains("term_executor_task_duration_seconds_bucket{le=\"0.5\"} 1"));
+        assert!(out.contains("term_executor_task_duration_seconds_bucket{le=\"5\"} 2"));
+        assert!(out.contains("term_executor_task_duration_seconds_bucket{le=\"60\"} 3"));
+        assert!(out.contains("term_executor_task_duration_seconds_bucket{le=\"3600\"} 3"));
+        assert!(out.contains("term_executor_task_duration_seconds_bucket{le=\"+Inf\"} 4"));
+        assert!(out.contains("term_executor_task_duration_seconds_count 4"));
+        assert!(out.contains("term_executor_task_duration_seconds_sum 7248.2"));
+        assert!(out.contains("term_executor_batch_duration_seconds_bucket{le=\"60\"} 0"));
+        assert!(out.contains("term_executor_batch_duration_seconds_bucket{le=\"120\"} 1"));
+    }
+
+    #[test]
+    fn test_peak_memory_histogram() {
+        let m = Metrics::new();
+        m.task_peak_memory.observe(32 * 1024);
+        m.task_peak_memory.observe(3 * 1024 * 1024);
+        let out = m.render_prometheus();
+        assert!(out.contains("# TYPE term_executor_task_peak_memory_bytes histogram"));
+        assert!(out.contains("term_executor_task_peak_memory_bytes_bucket{le=\"67108864\"} 1"));
+        assert!(out.contains("term_executor_task_peak_memory_bytes_bucket{le=\"2147483648\"} 1"));
+        assert!(out.contains("term_executor_task_peak_memory_bytes_bucket{le=\"4294967296\"} 2"));
+        assert!(out.contains("term_executor_task_peak_memory_bytes_count 2"));
+        assert!(out.contains("term_executor_task_peak_memory_bytes_sum 3254779904"));
+    }
 }
@@ -0,0 +1,103 @@
+use dashmap::DashMap;
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const WINDOW: Duration = Duration::from_secs(60);
+const REAP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Sliding one-minute window of `/submit` calls per hotkey.
+pub struct SubmitRateLimiter {
+    per_min: usize,
+    hits: DashMap<String, VecDeque<Instant>>,
+}
+
+impl SubmitRateLimiter {
+    /// `per_min == 0` disables limiting.
+    pub fn new(per_min: usize) -> Self {
+        Self {
+            per_min,
+            hits: DashMap::new(),
+        }
+    }
+
+    /// Count a submission for `hotkey`, or return how long to wait before the
+    /// window has room again.
+    pub fn try_acquire(&self, hotkey: &str) -> Result<(), Duration> {
+        self.try_acquire_at(hotkey, Instant::now())
+    }
+
+    fn try_acquire_at(&self, hotkey: &str, now: Instant) -> Result<(), Duration> {
+        if self.per_min == 0 {
+            return Ok(());
+        }
+        let mut hits = self.hits.entry(hotkey.to_string()).or_default();
+        while hits
+            .front()
+            .is_some_and(|t| now.duration_since(*t) >= WINDOW)
+        {
+            hits.pop_front();
+        }
+        if hits.len() >= self.per_min {
+            let oldest = hits.front().copied().unwrap_or(now);
+            return Err(WINDOW.saturating_sub(now.duration_since(oldest)));
+        }
+        hits.push_back(now);
+        Ok(())
+    }
+
+    /// Give back the most recent slot taken by `hotkey`.
+    pub fn refund(&self, hotkey: &str) {
+        if let Some(mut hits) = self.hits.get_mut(hotkey) {
+            hits.pop_back();
+        }
+    }
+
+    pub async fn reaper_loop(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(REAP_INTERVAL);
+        loop {
+            interval.tick().await;
+            let now = Instant::now();
+            self.hits
+                .retain(|_, hits| hits.back().is_some_and(|t| now.duration_since(*t) < WINDOW));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limit_per_hotkey() {
+        let limiter = SubmitRateLimiter::new(2);
+        let now = Instant::now();
+        assert!(limiter.try_acquire_at("a", now).is_ok());
+        assert!(limiter.try_acquire_at("a", now).is_ok());
+        let wait = limiter.try_acquire_at("a", now).unwrap_err();
+        assert_eq!(wait, WINDOW);
+        assert!(limiter.try_acquire_at("b", now).is_ok());
+
+        assert!(limiter
+            .try_acquire_at("a", now + Duration::from_secs(60))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_refund_frees_slot() {
+        let limiter = SubmitRateLimiter::new(1);
+        let now = Instant::now();
+        assert!(limiter.try_acquire_at("a", now).is_ok());
+        limiter.refund("a");
+        assert!(limiter.try_acquire_at("a", now).is_ok());
+        assert!(limiter.try_acquire_at("a", now).is_err());
+    }
+
+    #[test]
+    fn test_zero_disables_limit() {
+        let limiter = SubmitRateLimiter::new(0);
+        for _ in 0..100 {
+            assert!(limiter.try_acquire("a").is_ok());
+        }
+    }
+}
@@ -0,0 +1,1222 @@
+use anyhow::{Context, Result};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+use tokio::process::Command;
+use tracing::warn;
+
+/// Whether a sandboxed command may use the network. Install stages need
+/// `Allow`; test stages can use `Deny` to stay hermetic.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum NetworkMode {
+    #[default]
+    Allow,
+    Deny,
+}
+
+impl std::str::FromStr for NetworkMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "allow" => Ok(NetworkMode::Allow),
+            "deny" => Ok(NetworkMode::Deny),
+            other => Err(format!("unknown network mode '{}'", other)),
+        }
+    }
+}
+
+/// Unprivileged identity untrusted commands are switched to (`RUN_AS_USER`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunAsUser {
+    pub uid: u32,
+    pub gid: u32,
+}
+
+impl RunAsUser {
+    /// Resolve a `RUN_AS_USER` value: a user name, `uid` or `uid:gid`. A bare
+    /// uid takes its primary group from the passwd database, or the same
+    /// number when it has no entry. `None` when `spec` is empty or this
+    /// process isn't root, since only root can switch users anyway.
+    pub fn resolve(spec: &str) -> Result<Option<Self>, String> {
+        let spec = spec.trim();
+        // SAFETY: geteuid(2) has no preconditions and cannot fail.
+        if spec.is_empty() || unsafe { libc::geteuid() } != 0 {
+            return Ok(None);
+        }
+        let user = match spec.split_once(':') {
+            Some((uid, gid)) => match (uid.parse(), gid.parse()) {
+                (Ok(uid), Ok(gid)) => RunAsUser { uid, gid },
+                _ => return Err(format!("RUN_AS_USER '{}' is not uid:gid", spec)),
+            },
+            None => match spec.parse::<u32>() {
+                Ok(uid) => RunAsUser {
+                    uid,
+                    gid: lookup_user(None, uid).map_or(uid, |u| u.gid),
+                },
+                Err(_) => lookup_user(Some(spec), 0)
+                    .ok_or_else(|| format!("RUN_AS_USER '{}' is not a known user", spec))?,
+            },
+        };
+        if user.uid == 0 {
+            return Err("RUN_AS_USER must not be root".to_string());
+        }
+        if !setpriv_available() {
+            return Err("RUN_AS_USER needs `setpriv` (util-linux) on PATH".to_string());
+        }
+        Ok(Some(user))
+    }
+}
+
+/// Look a user up by name, or by uid when `name` is `None`.
+fn lookup_user(name: Option<&str>, uid: u32) -> Option<RunAsUser> {
+    let name = name.map(std::ffi::CString::new).transpose().ok()?;
+    // SAFETY: the name is NUL-terminated and the returned entry is copied
+    // out before any other passwd call can overwrite it.
+    unsafe {
+        let entry = match &name {
+            Some(name) => libc::getpwnam(name.as_ptr()),
+            None => libc::getpwuid(uid),
+        };
+        entry.as_ref().map(|pw| RunAsUser {
+            uid: pw.pw_uid,
+            gid: pw.pw_gid,
+        })
+    }
+}
+
+fn setpriv_available() -> bool {
+    std::process::Command::new("setpriv")
+        .arg("--version")
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .is_ok_and(|s| s.success())
+}
+
+/// Hand `path` and everything under it to `user`, so a command running as
+/// that user can write its work dir. Symlinks are re-owned themselves, never
+/// followed. The walk is relative to directory fds opened with `O_NOFOLLOW`,
+/// so a directory swapped for a link while it runs can't send it elsewhere.
+pub fn chown_tree(path: &Path, user: RunAsUser) -> std::io::Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let root = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_DIRECTORY | libc::O_NOFOLLOW)
+        .open(path)?;
+    std::os::unix::fs::fchown(&root, Some(user.uid), Some(user.gid))?;
+    chown_dir(&root, user)
+}
+
+/// Re-own the entries of the open directory `dir`, descending into
+/// subdirectories.
+fn chown_dir(dir: &std::fs::File, user: RunAsUser) -> std::io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    // Listing through the fd's /proc link reads the directory already open,
+    // not whatever its path names now.
+    let listing = std::fs::read_dir(format!("/proc/self/fd/{}", dir.as_raw_fd()))?;
+    for entry in listing {
+        let entry = entry?;
+        let name = CString::new(entry.file_name().as_bytes())?;
+        // SAFETY: `dir` is an open directory fd and `name` a valid C string.
+        let sub = unsafe {
+            if libc::fchownat(
+                dir.as_raw_fd(),
+                name.as_ptr(),
+                user.uid,
+                user.gid,
+                libc::AT_SYMLINK_NOFOLLOW,
+            ) != 0
+            {
+                let err = std::io::Error::last_os_error();
+                // Removed since it was listed.
+                if err.kind() == std::io::ErrorKind::NotFound {
+                    continue;
+                }
+                return Err(err);
+            }
+            let fd = libc::openat(
+                dir.as_raw_fd(),
+                name.as_ptr(),
+                libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+            );
+            if fd < 0 {
+                // Not a directory (or a link to one): nothing to descend into.
+                continue;
+            }
+            std::fs::File::from_raw_fd(fd)
+        };
+        chown_dir(&sub, user)?;
+    }
+    Ok(())
+}
+
+/// Open the directory `rel` beneath `root`, creating missing components
+/// when `create` is set, without following a symlink anywhere on the way.
+/// Each step is an `openat(O_NOFOLLOW)` relative to the previous one, so a
+/// link swapped in by a process still running in the tree can't redirect it
+/// either.
+fn open_dir_beneath(root: &Path, rel: &Path, create: bool) -> std::io::Result<std::fs::File> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::OpenOptionsExt;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+    use std::path::Component;
+
+    let mut dir = std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_DIRECTORY | libc::O_NOFOLLOW)
+        .open(root)?;
+    for component in rel.components() {
+        let Component::Normal(name) = component else {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} leaves its root", rel.display()),
+            ));
+        };
+        let name = CString::new(name.as_bytes())?;
+        // SAFETY: `dir` is an open directory fd and `name` a valid C string.
+        unsafe {
+            if create && libc::mkdirat(dir.as_raw_fd(), name.as_ptr(), 0o755) != 0 {
+                let err = std::io::Error::last_os_error();
+                if err.kind() != std::io::ErrorKind::AlreadyExists {
+                    return Err(err);
+                }
+            }
+            let fd = libc::openat(
+                dir.as_raw_fd(),
+                name.as_ptr(),
+                libc::O_RDONLY | libc::O_DIRECTORY | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+            );
+            if fd < 0 {
+                return Err(std::io::Error::last_os_error());
+            }
+            dir = std::fs::File::from_raw_fd(fd);
+        }
+    }
+    Ok(dir)
+}
+
+/// Write `content` to `rel` beneath `root` with permissions `mode`, as root
+/// does for hidden test files in a tree the agent owned. Neither the parent
+/// directories nor the file itself may be symlinks.
+pub fn write_beneath(root: &Path, rel: &str, content: &[u8], mode: u32) -> std::io::Result<()> {
+    use std::ffi::CString;
+    use std::io::Write;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::io::{AsRawFd, FromRawFd};
+
+    let rel = Path::new(rel);
+    let name = rel
+        .file_name()
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "no file name"))?;
+    let dir = open_dir_beneath(root, rel.parent().unwrap_or(Path::new("")), true)?;
+    let name = CString::new(name.as_bytes())?;
+    // SAFETY: `dir` is an open directory fd and `name` a valid C string.
+    let mut file = unsafe {
+        let fd = libc::openat(
+            dir.as_raw_fd(),
+            name.as_ptr(),
+            libc::O_WRONLY | libc::O_CREAT | libc::O_TRUNC | libc::O_NOFOLLOW | libc::O_CLOEXEC,
+            mode as libc::c_uint,
+        );
+        if fd < 0 {
+            return Err(std::io::Error::last_os_error());
+        }
+        std::fs::File::from_raw_fd(fd)
+    };
+    file.write_all(content)?;
+    // An existing file keeps its old mode through O_CREAT.
+    file.set_permissions(std::fs::Permissions::from_mode(mode))
+}
+
+/// Read at most `limit` bytes of the file `name` in `dir`, refusing symlinks
+/// and anything that isn't a regular file. `Ok(None)` when it doesn't exist.
+pub fn read_no_follow(dir: &Path, name: &str, limit: u64) -> std::io::Result<Option<Vec<u8>>> {
+    use std::io::Read;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let file = match std::fs::OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW | libc::O_NONBLOCK)
+        .open(dir.join(name))
+    {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if !file.metadata()?.is_file() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} is not a regular file", name),
+        ));
+    }
+    let mut content = Vec::new();
+    file.take(limit).read_to_end(&mut content)?;
+    Ok(Some(content))
+}
+
+/// `read_no_follow` for `rel` beneath `root`, refusing a symlink at any
+/// component on the way, not just the last. `Ok(None)` when it doesn't exist.
+pub fn read_beneath_no_follow(
+    root: &Path,
+    rel: &str,
+    limit: u64,
+) -> std::io::Result<Option<Vec<u8>>> {
+    use std::os::unix::io::AsRawFd;
+
+    let rel = Path::new(rel);
+    let name = rel
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "no file name"))?;
+    let dir = match open_dir_beneath(root, rel.parent().unwrap_or(Path::new("")), false) {
+        Ok(dir) => dir,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    read_no_follow(
+        Path::new(&format!("/proc/self/fd/{}", dir.as_raw_fd())),
+        name,
+        limit,
+    )
+}
+
+/// Restrictions applied to a command before it is spawned.
+#[derive(Debug, Clone, Default)]
+pub struct SandboxConfig {
+    pub network: NetworkMode,
+    /// Virtual memory ceiling in MiB (`ulimit -v`).
+    pub memory_mb: Option<u64>,
+    /// Scheduling niceness applied with `nice -n`.
+    pub nice: Option<i32>,
+    /// CPU seconds before the kernel kills the command (`ulimit -t`).
+    pub cpu_time_secs: Option<u64>,
+    /// Processes the command's user may own (`ulimit -u`). Enforced per UID,
+    /// and not at all for root.
+    pub max_processes: Option<u64>,
+    /// Run as this user instead of the executor's (root) identity.
+    pub user: Option<RunAsUser>,
+    /// Where peak memory and OOM kills of the commands are recorded.
+    pub memory: Option<Arc<MemoryUsage>>,
+    /// How long a timed-out command gets between SIGTERM and SIGKILL; zero
+    /// kills it outright.
+    pub kill_grace: Duration,
+    /// Leave processes a command backgrounded running after it exits. Only
+    /// for trusted commands (installs starting a service); anything the agent
+    /// or a test leaves behind is killed with its process group.
+    pub keep_background: bool,
+    /// Output bytes the commands sharing this config may still keep; what
+    /// comes after is read and dropped as it arrives.
+    pub output_budget: Option<Arc<OutputBudget>>,
+}
+
+impl SandboxConfig {
+    pub fn with_network(mut self, network: NetworkMode) -> Self {
+        self.network = network;
+        self
+    }
+
+    /// Zero means unlimited, matching the config convention.
+    pub fn with_memory_mb(mut self, mb: u64) -> Self {
+        self.memory_mb = (mb > 0).then_some(mb);
+        self
+    }
+
+    /// Zero leaves the inherited niceness alone.
+    pub fn with_nice(mut self, nice: i32) -> Self {
+        self.nice = (nice != 0).then_some(nice);
+        self
+    }
+
+    /// Zero means unlimited, matching the config convention.
+    pub fn with_cpu_time_secs(mut self, secs: u64) -> Self {
+        self.cpu_time_secs = (secs > 0).then_some(secs);
+        self
+    }
+
+    /// Zero means unlimited, matching the config convention.
+    pub fn with_max_processes(mut self, max: u64) -> Self {
+        self.max_processes = (max > 0).then_some(max);
+        self
+    }
+
+    pub fn with_user(mut self, user: Option<RunAsUser>) -> Self {
+        self.user = user;
+        self
+    }
+
+    pub fn with_memory_usage(mut self, usage: Arc<MemoryUsage>) -> Self {
+        self.memory = Some(usage);
+        self
+    }
+
+    pub fn with_kill_grace(mut self, grace: Duration) -> Self {
+        self.kill_grace = grace;
+        self
+    }
+
+    pub fn with_keep_background(mut self, keep: bool) -> Self {
+        self.keep_background = keep;
+        self
+    }
+
+    /// Zero means unlimited, matching the config convention. Clones of the
+    /// config draw from the same budget.
+    pub fn with_output_budget(mut self, bytes: usize) -> Self {
+        self.output_budget = (bytes > 0).then(|| Arc::new(OutputBudget::new(bytes)));
+        self
+    }
+
+    fn ulimit_script(&self) -> Option<String> {
+        let mut limits = Vec::new();
+        if let Some(mb) = self.memory_mb {
+            limits.push(format!("ulimit -v {}", mb * 1024));
+        }
+        if let Some(secs) = self.cpu_time_secs {
+            limits.push(format!("ulimit -t {}", secs));
+        }
+        if let Some(max) = self.max_processes {
+            limits.push(format!("ulimit -u {}", max));
+        }
+        if limits.is_empty() {
+            return None;
+        }
+        limits.push("exec \"$@\"".to_string());
+        Some(limits.join(" && "))
+    }
+
+    /// Argv that runs `argv` under this sandbox. `Deny` runs the command in a
+    /// fresh network namespace (only loopback) via `unshare`; where that isn't
+    /// available the command runs unrestricted and a warning is logged once.
+    /// Privileges are dropped with `setpriv` inside the namespace, before
+    /// resource limits are set by a `bash` shim that then execs the command.
+    pub fn wrap_command(&self, argv: &[&str]) -> Vec<String> {
+        let mut wrapped: Vec<String> = Vec::new();
+        if self.network == NetworkMode::Deny {
+            match unshare_net_prefix() {
+                Some(prefix) => wrapped.extend(prefix.iter().map(|s| s.to_string())),
+                None => warn_unshare_unavailable(),
+            }
+        }
+        if let Some(user) = self.user {
+            wrapped.extend([
+                "setpriv".to_string(),
+                format!("--reuid={}", user.uid),
+                format!("--regid={}", user.gid),
+                "--clear-groups".to_string(),
+                "--".to_string(),
+            ]);
+        }
+        if let Some(nice) = self.nice {
+            wrapped.extend(["nice".to_string(), "-n".to_string(), nice.to_string()]);
+        }
+        if let Some(script) = self.ulimit_script() {
+            wrapped.extend([
+                "bash".to_string(),
+                "-c".to_string(),
+                script,
+                "sandbox".to_string(),
+            ]);
+        }
+        wrapped.extend(argv.iter().map(|s| s.to_string()));
+        wrapped
+    }
+}
+
+/// Run `argv` under `sandbox` in its own process group. The whole group is
+/// killed when the command exits (unless `sandbox.keep_background`), times
+/// out or the returned future is dropped, so children can't keep running
+/// after the command is done. On timeout the group first gets SIGTERM and
+/// `sandbox.kill_grace` to clean up.
+pub async fn run(
+    argv: &[&str],
+    cwd: &Path,
+    timeout: Duration,
+    env: Option<&[(&str, &str)]>,
+    sandbox: &SandboxConfig,
+    stdin: Option<&[u8]>,
+) -> Result<std::process::Output> {
+    let argv = sandbox.wrap_command(argv);
+    let (program, args) = argv.split_first().context("empty argv")?;
+
+    let mut cmd = Command::new(program);
+    cmd.args(args)
+        .current_dir(cwd)
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .process_group(0)
+        .kill_on_drop(true);
+    cmd.stdin(if stdin.is_some() {
+        std::process::Stdio::piped()
+    } else {
+        std::process::Stdio::null()
+    });
+
+    if let Some(vars) = env {
+        for (k, v) in vars {
+            cmd.env(k, v);
+        }
+    }
+
+    let oom = OomProbe::start();
+    let mut child = cmd.spawn().context("Failed to spawn process")?;
+    let pid = child.id();
+    let group = pid.map(ProcessGroup);
+
+    // Fed from a separate task so a child that writes before reading all of
+    // its input can't deadlock against `wait_with_output`. Dropping the pipe
+    // afterwards gives the child EOF; a child that exits early just causes
+    // a broken pipe, which is ignored.
+    if let (Some(input), Some(mut pipe)) = (stdin, child.stdin.take()) {
+        let input = input.to_vec();
+        tokio::spawn(async move {
+            use tokio::io::AsyncWriteExt;
+            let _ = pipe.write_all(&input).await;
+        });
+    }
+
+    let (stdout, stderr) = (child.stdout.take(), child.stderr.take());
+    let budget = sandbox.output_budget.as_deref();
+    let wait = async {
+        let (stdout, stderr, status) = tokio::try_join!(
+            capture(stdout, budget),
+            capture(stderr, budget),
+            child.wait()
+        )?;
+        Ok::<_, std::io::Error>(std::process::Output {
+            status,
+            stdout,
+            stderr,
+        })
+    };
+    tokio::pin!(wait);
+    let waited = match (&sandbox.memory, pid) {
+        (Some(usage), Some(pid)) => {
+            tokio::time::timeout(timeout, sample_memory(usage, pid, &mut wait)).await
+        }
+        _ => tokio::time::timeout(timeout, &mut wait).await,
+    };
+    match waited {
+        Ok(Ok(output)) => {
+            if sandbox.keep_background {
+                std::mem::forget(group);
+            } else {
+                drop(group);
+            }
+            if let Some(ref usage) = sandbox.memory {
+                use std::os::unix::process::ExitStatusExt;
+                let sigkill = output.status.signal() == Some(libc::SIGKILL)
+                    || output.status.code() == Some(128 + libc::SIGKILL);
+                if oom.killed(sigkill) {
+                    usage.oom_killed.store(true, Ordering::Relaxed);
+                }
+            }
+            Ok(output)
+        }
+        Ok(Err(e)) => anyhow::bail!("Process error: {}", e),
+        Err(_) => {
+            if let Some(pid) = pid {
+                terminate_group(pid, sandbox.kill_grace, wait).await;
+            }
+            // Dropping `group` SIGKILLs whatever is left.
+            Err(TimedOut(timeout.as_secs()).into())
+        }
+    }
+}
+
+/// Bytes of output a task's commands may keep between them. Granted as the
+/// output is read, so a chatty command can't hold more than the budget in
+/// memory before it is cut.
+#[derive(Debug)]
+pub struct OutputBudget {
+    total: usize,
+    remaining: AtomicUsize,
+}
+
+impl OutputBudget {
+    pub fn new(total: usize) -> Self {
+        Self {
+            total,
+            remaining: AtomicUsize::new(total),
+        }
+    }
+
+    /// Take up to `want` bytes; returns how many were granted.
+    fn take(&self, want: usize) -> usize {
+        let before = self
+            .remaining
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |r| {
+                Some(r - r.min(want))
+            })
+            .unwrap_or(0);
+        before.min(want)
+    }
+}
+
+/// Read `pipe` to EOF, keeping what `budget` grants. Dropped bytes are
+/// still read so the command never blocks on a full pipe, and a marker
+/// naming the budget ends the kept output.
+async fn capture<R: tokio::io::AsyncRead + Unpin>(
+    pipe: Option<R>,
+    budget: Option<&OutputBudget>,
+) -> std::io::Result<Vec<u8>> {
+    use tokio::io::AsyncReadExt;
+    let Some(mut pipe) = pipe else {
+        return Ok(Vec::new());
+    };
+    let mut out = Vec::new();
+    let mut buf = [0u8; 8192];
+    let mut dropped = 0;
+    loop {
+        let n = pipe.read(&mut buf).await?;
+        if n == 0 {
+            break;
+        }
+        let mut keep = budget.map_or(n, |b| b.take(n));
+        // Don't keep half of a UTF-8 sequence at the cut.
+        while keep > 0 && keep < n && (buf[keep] & 0xC0) == 0x80 {
+            keep -= 1;
+        }
+        out.extend_from_slice(&buf[..keep]);
+        dropped += n - keep;
+    }
+    if let (Some(budget), true) = (budget, dropped > 0) {
+        out.extend_from_slice(
+            format!(
+                "\n\n... [task output budget of {} bytes exhausted, {} bytes dropped]",
+                budget.total, dropped
+            )
+            .as_bytes(),
+        );
+    }
+    Ok(out)
+}
+
+/// Send SIGTERM to process group `pgid` and give it up to `grace` for
+/// `exited` to resolve, so test runners can flush artifacts on shutdown.
+/// Returns whether it did; the caller SIGKILLs the group either way.
+pub async fn terminate_group<F: std::future::Future>(
+    pgid: u32,
+    grace: Duration,
+    exited: F,
+) -> bool {
+    if grace.is_zero() {
+        return false;
+    }
+    // SAFETY: kill(2) has no memory-safety preconditions; a negative pid
+    // addresses the group the child leads.
+    unsafe {
+        libc::kill(-(pgid as libc::pid_t), libc::SIGTERM);
+    }
+    tokio::time::timeout(grace, exited).await.is_ok()
+}
+
+/// How often a tracked command's process group is sampled for memory.
+const MEMORY_POLL: Duration = Duration::from_millis(250);
+
+/// Peak resident memory and OOM kills across the commands of one task. The
+/// peak is sampled, so very short spikes can be missed.
+#[derive(Debug, Default)]
+pub struct MemoryUsage {
+    peak_kb: AtomicU64,
+    oom_killed: AtomicBool,
+}
+
+impl MemoryUsage {
+    /// Highest RSS seen, in KiB; `None` if nothing was sampled.
+    pub fn peak_kb(&self) -> Option<u64> {
+        Some(self.peak_kb.load(Ordering::Relaxed)).filter(|kb| *kb > 0)
+    }
+
+    /// Whether a command was SIGKILLed by the kernel OOM killer.
+    pub fn oom_killed(&self) -> bool {
+        self.oom_killed.load(Ordering::Relaxed)
+    }
+
+    fn record(&self, kb: u64) {
+        self.peak_kb.fetch_max(kb, Ordering::Relaxed);
+    }
+
+    /// Fold in the peak and OOM kill tracked for a single command.
+    pub fn absorb(&self, other: &MemoryUsage) {
+        self.record(other.peak_kb.load(Ordering::Relaxed));
+        if other.oom_killed() {
+            self.oom_killed.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Run `fut` while sampling the RSS of process group `pgid` into `usage`.
+pub async fn sample_memory<F: std::future::Future>(
+    usage: &MemoryUsage,
+    pgid: u32,
+    fut: F,
+) -> F::Output {
+    tokio::pin!(fut);
+    let mut interval = tokio::time::interval(MEMORY_POLL);
+    loop {
+        tokio::select! {
+            out = &mut fut => return out,
+            _ = interval.tick() => {
+                // A full /proc scan; keep it off the runtime threads.
+                let kb = tokio::task::spawn_blocking(move || group_rss_kb(pgid))
+                    .await
+                    .unwrap_or(0);
+                usage.record(kb);
+            }
+        }
+    }
+}
+
+/// Resident memory in KiB summed over the live processes of group `pgid`,
+/// read from `/proc/<pid>/stat`.
+fn group_rss_kb(pgid: u32) -> u64 {
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return 0;
+    };
+    // SAFETY: sysconf(3) has no memory-safety preconditions.
+    let page_kb = (unsafe { libc::sysconf(libc::_SC_PAGESIZE) }).max(4096) as u64 / 1024;
+    entries
+        .flatten()
+        .filter(|e| {
+            e.file_name()
+                .to_string_lossy()
+                .bytes()
+                .all(|b| b.is_ascii_digit())
+        })
+        .filter_map(|e| std::fs::read_to_string(e.path().join("stat")).ok())
+        .filter_map(|stat| {
+            // Fields after the parenthesised command name: state is the
+            // first, pgrp the third and rss (in pages) the 22nd.
+            let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+            let pgrp: u32 = fields.get(2)?.parse().ok()?;
+            let rss: u64 = fields.get(21)?.parse().ok()?;
+            (pgrp == pgid).then_some(rss * page_kb)
+        })
+        .sum()
+}
+
+/// Snapshot of the cgroup v2 `oom_kill` counter taken before a command runs.
+/// A SIGKILLed command counts as OOM-killed only when the counter moved in
+/// the meantime; without a readable counter there is no telling an OOM kill
+/// from a `kill -9`, so it never does.
+///
+/// Every command runs in the executor's cgroup, so the counter also moves
+/// for other tasks' kills. Each kill is claimed by at most one command (see
+/// `OOM_KILLS_CLAIMED`), which keeps one OOM kill from flagging every task
+/// that happened to be SIGKILLed around the same time.
+pub struct OomProbe(Option<u64>);
+
+/// Highest `oom_kill` counter value already attributed to a command.
+static OOM_KILLS_CLAIMED: AtomicU64 = AtomicU64::new(0);
+
+impl OomProbe {
+    pub fn start() -> Self {
+        Self(cgroup_oom_kills())
+    }
+
+    pub fn killed(&self, sigkill: bool) -> bool {
+        if !sigkill {
+            return false;
+        }
+        let (Some(before), Some(after)) = (self.0, cgroup_oom_kills()) else {
+            return false;
+        };
+        // Claim the first kill since `before` that no other command has.
+        OOM_KILLS_CLAIMED
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |claimed| {
+                let next = claimed.max(before) + 1;
+                (next <= after).then_some(next)
+            })
+            .is_ok()
+    }
+}
+
+/// The executor's own cgroup v2 directory, from `/proc/self/cgroup`.
+fn own_cgroup_dir() -> &'static Path {
+    static DIR: std::sync::OnceLock<std::path::PathBuf> = std::sync::OnceLock::new();
+    DIR.get_or_init(|| {
+        let rel = std::fs::read_to_string("/proc/self/cgroup")
+            .ok()
+            .and_then(|s| {
+                s.lines()
+                    .find_map(|l| l.strip_prefix("0::"))
+                    .map(|p| p.trim_start_matches('/').to_string())
+            })
+            .unwrap_or_default();
+        Path::new("/sys/fs/cgroup").join(rel)
+    })
+}
+
+fn cgroup_oom_kills() -> Option<u64> {
+    let events = std::fs::read_to_string(own_cgroup_dir().join("memory.events")).ok()?;
+    events
+        .lines()
+        .find_map(|l| l.strip_prefix("oom_kill "))
+        .and_then(|n| n.trim().parse().ok())
+}
+
+/// A command ran past its timeout and was killed.
+#[derive(Debug, thiserror::Error)]
+#[error("Command timed out after {0}s")]
+pub struct TimedOut(pub u64);
+
+/// Whether `e`, or anything it wraps, is a `TimedOut`.
+pub fn is_timeout(e: &anyhow::Error) -> bool {
+    e.chain().any(|cause| cause.is::<TimedOut>())
+}
+
+/// SIGKILL every process left in group `pgid`.
+pub fn kill_group(pgid: u32) {
+    // SAFETY: kill(2) has no memory-safety preconditions; a negative pid
+    // addresses the group the child leads.
+    unsafe {
+        libc::kill(-(pgid as libc::pid_t), libc::SIGKILL);
+    }
+}
+
+/// Kills a process group with SIGKILL when dropped.
+struct ProcessGroup(u32);
+
+impl Drop for ProcessGroup {
+    fn drop(&mut self) {
+        kill_group(self.0);
+    }
+}
+
+/// `unshare` invocation that works for this process, probed once. Root can
+/// create a network namespace directly; other users need a user namespace.
+fn unshare_net_prefix() -> Option<&'static [&'static str]> {
+    static PREFIX: OnceLock<Option<&'static [&'static str]>> = OnceLock::new();
+    *PREFIX.get_or_init(|| {
+        const CANDIDATES: &[&[&str]] = &[
+            &["unshare", "--net", "--"],
+            &["unshare", "--net", "--map-root-user", "--"],
+        ];
+        CANDIDATES.iter().copied().find(|prefix| {
+            std::process::Command::new(prefix[0])
+                .args(&prefix[1..])
+                .arg("true")
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .status()
+                .is_ok_and(|s| s.success())
+        })
+    })
+}
+
+fn warn_unshare_unavailable() {
+    static WARNED: OnceLock<()> = OnceLock::new();
+    WARNED.get_or_init(|| {
+        warn!(
+            "Network isolation requested but `unshare --net` is unavailable; running with network"
+        );
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_network_mode_parse() {
+        assert_eq!("deny".parse::<NetworkMode>(), Ok(NetworkMode::Deny));
+        assert_eq!(" Allow ".parse::<NetworkMode>(), Ok(NetworkMode::Allow));
+        assert!("off".parse::<NetworkMode>().is_err());
+    }
+
+    #[test]
+    fn test_write_beneath_refuses_symlinks() {
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let repo = root.path();
+
+        write_beneath(repo, "tests/nested/run.sh", b"exit 0\n", 0o755).unwrap();
+        let written = repo.join("tests/nested/run.sh");
+        assert_eq!(std::fs::read(&written).unwrap(), b"exit 0\n");
+        use std::os::unix::fs::PermissionsExt;
+        assert_eq!(
+            std::fs::metadata(&written).unwrap().permissions().mode() & 0o777,
+            0o755
+        );
+
+        // A planted directory link and a planted file link both fail.
+        std::os::unix::fs::symlink(outside.path(), repo.join("evil")).unwrap();
+        assert!(write_beneath(repo, "evil/x.py", b"x", 0o644).is_err());
+        let target = outside.path().join("victim");
+        std::fs::write(&target, "keep").unwrap();
+        std::os::unix::fs::symlink(&target, repo.join("conftest.py")).unwrap();
+        assert!(write_beneath(repo, "conftest.py", b"x", 0o644).is_err());
+        assert!(write_beneath(repo, "../escape.py", b"x", 0o644).is_err());
+        assert_eq!(std::fs::read_to_string(&target).unwrap(), "keep");
+        assert!(std::fs::read_dir(outside.path()).unwrap().count() == 1);
+
+        assert!(read_no_follow(repo, "conftest.py", 1024).is_err());
+        assert_eq!(read_no_follow(repo, "missing", 1024).unwrap(), None);
+        assert_eq!(
+            read_beneath_no_follow(repo, "tests/nested/run.sh", 4).unwrap(),
+            Some(b"exit".to_vec())
+        );
+        assert!(read_beneath_no_follow(repo, "evil/victim", 1024).is_err());
+        assert_eq!(
+            read_beneath_no_follow(repo, "tests/missing/run.sh", 1024).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_chown_tree_does_not_follow_links() {
+        use std::os::unix::fs::MetadataExt;
+
+        let root = tempfile::tempdir().unwrap();
+        let outside = tempfile::tempdir().unwrap();
+        let repo = root.path();
+        std::fs::create_dir_all(repo.join("a/b")).unwrap();
+        std::fs::write(repo.join("a/b/file"), "x").unwrap();
+        std::fs::write(outside.path().join("victim"), "x").unwrap();
+        std::os::unix::fs::symlink(outside.path(), repo.join("a/escape")).unwrap();
+        std::os::unix::fs::symlink("missing", repo.join("dangling")).unwrap();
+
+        // As root the tree goes to nobody; otherwise to ourselves, which
+        // still exercises the walk.
+        let me = std::fs::metadata(repo).unwrap();
+        let user = if me.uid() == 0 {
+            RunAsUser {
+                uid: 65534,
+                gid: 65534,
+            }
+        } else {
+            RunAsUser {
+                uid: me.uid(),
+                gid: me.gid(),
+            }
+        };
+        chown_tree(repo, user).unwrap();
+
+        for path in ["a", "a/b", "a/b/file", "a/escape", "dangling"] {
+            let meta = std::fs::symlink_metadata(repo.join(path)).unwrap();
+            assert_eq!((meta.uid(), meta.gid()), (user.uid, user.gid), "{}", path);
+        }
+        let victim = std::fs::metadata(outside.path().join("victim")).unwrap();
+        assert_eq!(victim.uid(), me.uid());
+        assert!(chown_tree(&repo.join("a/escape"), user).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_memory_usage_sampled() {
+        let usage = Arc::new(MemoryUsage::default());
+        assert_eq!(usage.peak_kb(), None);
+        let sandbox = SandboxConfig::default().with_memory_usage(usage.clone());
+        // Hold ~64 MiB resident for a few samples.
+        let output = run(
+            &[
+                "python3",
+                "-c",
+                "import time; b = bytearray(64 << 20); time.sleep(1)",
+            ],
+            Path::new("/tmp"),
+            Duration::from_secs(30),
+            None,
+            &sandbox,
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(output.status.success());
+        assert!(
+            usage.peak_kb().unwrap() >= 60 * 1024,
+            "{:?}",
+            usage.peak_kb()
+        );
+        assert!(!usage.oom_killed());
+    }
+
+    #[test]
+    fn test_oom_probe_needs_sigkill() {
+        let probe = OomProbe::start();
+        assert!(!probe.killed(false));
+        // Without a cgroup counter a SIGKILL alone proves nothing.
+        assert!(!OomProbe(None).killed(true));
+    }
+
+    #[tokio::test]
+    async fn test_output_budget_shared_across_commands() {
+        let tmp = tempfile::tempdir().unwrap();
+        let sandbox = SandboxConfig::default().with_output_budget(150);
+        let print = ["sh", "-c", "head -c 100 /dev/zero | tr '\\0' x"];
+        let timeout = Duration::from_secs(10);
+        let first = run(&print, tmp.path(), timeout, None, &sandbox.clone(), None)
+            .await
+            .unwrap();
+        assert_eq!(first.stdout, vec![b'x'; 100]);
+        let second = run(&print, tmp.path(), timeout, None, &sandbox, None)
+            .await
+            .unwrap();
+        let second = String::from_utf8(second.stdout).unwrap();
+        assert!(second.starts_with(&"x".repeat(50)));
+        assert!(second.ends_with("budget of 150 bytes exhausted, 50 bytes dropped]"));
+    }
+
+    #[tokio::test]
+    async fn test_run_pipes_stdin() {
+        let input = "line\n".repeat(50_000);
+        let output = run(
+            &["sh", "-c", "wc -l; echo done >&2"],
+            Path::new("."),
+            Duration::from_secs(10),
+            None,
+            &SandboxConfig::default(),
+            Some(input.as_bytes()),
+        )
+        .await
+        .unwrap();
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "50000");
+        assert_eq!(String::from_utf8_lossy(&output.stderr).trim(), "done");
+    }
+
+    #[test]
+    fn test_allow_leaves_command_untouched() {
+        let sandbox = SandboxConfig::default();
+        assert_eq!(
+            sandbox.wrap_command(&["bash", "t.sh"]),
+            vec!["bash", "t.sh"]
+        );
+    }
+
+    fn run_as_unprivileged(argv: &[String]) -> Option<std::process::Output> {
+        let is_root = std::process::Command::new("id")
+            .arg("-u")
+            .output()
+            .is_ok_and(|o| o.stdout.starts_with(b"0\n"));
+        let mut cmd = if is_root {
+            let mut cmd = std::process::Command::new("setpriv");
+            cmd.args(["--reuid=65534", "--regid=65534", "--clear-groups"]);
+            cmd.args(argv);
+            cmd
+        } else {
+            let mut cmd = std::process::Command::new(&argv[0]);
+            cmd.args(&argv[1..]);
+            cmd
+        };
+        cmd.current_dir("/").output().ok()
+    }
+
+    #[test]
+    fn test_ulimits_applied() {
+        let sandbox = SandboxConfig::default()
+            .with_cpu_time_secs(7)
+            .with_max_processes(0);
+        assert_eq!(sandbox.max_processes, None);
+        let argv = sandbox.wrap_command(&["bash", "-c", "ulimit -t"]);
+        let out = std::process::Command::new(&argv[0])
+            .args(&argv[1..])
+            .output()
+            .unwrap();
+        assert_eq!(String::from_utf8_lossy(&out.stdout).trim(), "7");
+    }
+
+    #[test]
+    fn test_fork_bomb_contained_by_process_limit() {
+        let sandbox = SandboxConfig::default().with_max_processes(8);
+        let spawn_many = format!(
+            "for i in {}; do sleep 1 & done; echo spawned",
+            (0..64).map(|i| i.to_string()).collect::<Vec<_>>().join(" ")
+        );
+        let argv = sandbox.wrap_command(&["sh", "-c", &spawn_many]);
+        // Root is exempt from RLIMIT_NPROC and needs setpriv to drop it.
+        let Some(out) = run_as_unprivileged(&argv) else {
+            return;
+        };
+        assert!(!out.status.success());
+        assert!(!String::from_utf8_lossy(&out.stdout).contains("spawned"));
+        assert!(String::from_utf8_lossy(&out.stderr).contains("fork"));
+    }
+
+    #[tokio::test]
+    async fn test_run_as_configured_user() {
+        // Only meaningful when running as root with setpriv installed.
+        let Ok(Some(user)) = RunAsUser::resolve("65534:65534") else {
+            return;
+        };
+        let tmp = tempfile::tempdir().unwrap();
+        std::fs::write(tmp.path().join("owned"), "").unwrap();
+        chown_tree(tmp.path(), user).unwrap();
+
+        let sandbox = SandboxConfig::default().with_user(Some(user));
+        let output = run(
+            &["sh", "-c", "id -u; id -g; touch written"],
+            tmp.path(),
+            Duration::from_secs(10),
+            None,
+            &sandbox,
+            None,
+        )
+        .await
+        .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(String::from_utf8_lossy(&output.stdout), "65534\n65534\n");
+
+        use std::os::unix::fs::MetadataExt;
+        let meta = std::fs::metadata(tmp.path().join("written")).unwrap();
+        assert_eq!((meta.uid(), meta.gid()), (65534, 65534));
+        assert_eq!(
+            RunAsUser::resolve("0"),
+            Err("RUN_AS_USER must not be root".to_string())
+        );
+    }
+
+    #[test]
+    fn test_wrap_order() {
+        let sandbox = SandboxConfig::default().with_nice(10).with_memory_mb(512);
+        assert_eq!(
+            sandbox.wrap_command(&["t.sh"]),
+            vec![
+                "nice",
+                "-n",
+                "10",
+                "bash",
+                "-c",
+                "ulimit -v 524288 && exec \"$@\"",
+                "sandbox",
+                "t.sh"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_kills_process_group_on_timeout() {
+        let tmp = tempfile::tempdir().unwrap();
+        let marker = tmp.path().join("survived");
+        let script = format!("(sleep 1; touch {}) & wait", marker.display());
+        let err = run(
+            &["sh", "-c", &script],
+            tmp.path(),
+            Duration::from_millis(200),
+            None,
+            &SandboxConfig::default(),
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert!(err.to_string().contains("timed out"));
+        tokio::time::sleep(Duration::from_millis(1500)).await;
+        assert!(!marker.exists());
+    }
+
+    #[tokio::test]
+    async fn test_run_kills_background_jobs_on_exit() {
+        let tmp = tempfile::tempdir().unwrap();
+        for (keep, name) in [(false, "killed"), (true, "kept")] {
+            let marker = tmp.path().join(name);
+            let script = format!("(sleep 1; touch {}) >/dev/null 2>&1 &", marker.display());
+            let output = run(
+                &["sh", "-c", &script],
+                tmp.path(),
+                Duration::from_secs(10),
+                None,
+                &SandboxConfig::default().with_keep_background(keep),
+                None,
+            )
+            .await
+            .unwrap();
+            assert!(output.status.success());
+            tokio::time::sleep(Duration::from_millis(1500)).await;
+            assert_eq!(marker.exists(), keep);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_grace_period_lets_command_clean_up() {
+        let tmp = tempfile::tempdir().unwrap();
+        let marker = tmp.path().join("flushed");
+        let script = format!(
+            "trap 'sleep 0.3; touch {}; exit 143' TERM; sleep 30 & wait",
+            marker.display()
+        );
+        let argv = ["sh", "-c", script.as_str()];
+
+        let sandbox = SandboxConfig::default().with_kill_grace(Duration::from_secs(5));
+        let started = std::time::Instant::now();
+        let err = run(
+            &argv,
+            tmp.path(),
+            Duration::from_millis(200),
+            None,
+            &sandbox,
+            None,
+        )
+        .await
+        .unwrap_err();
+        assert!(is_timeout(&err));
+        assert!(marker.exists(), "trap ran before SIGKILL");
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        // Without a grace period the trap never gets to run.
+        std::fs::remove_file(&marker).unwrap();
+        run(
+            &argv,
+            tmp.path(),
+            Duration::from_millis(200),
+            None,
+            &SandboxConfig::default(),
+            None,
+        )
+        .await
+        .unwrap_err();
+        tokio::time::sleep(Duration::from_millis(500)).await;
+        assert!(!marker.exists());
+    }
+
+    #[tokio::test]
+    async fn test_run_applies_limits() {
+        let tmp = tempfile::tempdir().unwrap();
+        let sandbox = SandboxConfig::default().with_nice(5).with_memory_mb(256);
+        let output = run(
+            &["sh", "-c", "nice; ulimit -v"],
+            tmp.path(),
+            Duration::from_secs(10),
+            None,
+            &sandbox,
+            None,
+        )
+        .await
+        .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let lines: Vec<&str> = stdout.lines().collect();
+        assert_eq!(lines.len(), 2, "{}", stdout);
+        assert!(lines[0].trim().parse::<i32>().unwrap() >= 5);
+        assert_eq!(lines[1].trim(), "262144");
+    }
+
+    #[test]
+    fn test_denied_command_has_no_network() {
+        // Needs a kernel and user that allow new network namespaces.
+        if unshare_net_prefix().is_none() {
+            return;
+        }
+        let sandbox = SandboxConfig::default().with_network(NetworkMode::Deny);
+        // Only loopback exists in the new namespace, and nothing can be
+        // reached through it.
+        let argv = sandbox.wrap_command(&[
+            "bash",
+            "-c",
+            "grep -v -E '^ *lo:|\\||^Inter' /proc/net/dev | grep -q . && exit 10; \
+             exec 3<>/dev/tcp/1.1.1.1/80",
+        ]);
+        let status = std::process::Command::new(&argv[0])
+            .args(&argv[1..])
+            .stderr(std::process::Stdio::null())
+            .status()
+            .unwrap();
+        assert!(!status.success());
+        assert_ne!(status.code(), Some(10));
+    }
+}
@@ -1,7 +1,7 @@
 use chrono::{DateTime, Utc};
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use tokio::sync::{broadcast, Mutex};
 use tracing::info;
@@ -9,6 +9,8 @@ use tracing::info;
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum BatchStatus {
+    /// Waiting in the `BATCH_QUEUE_DEPTH` queue for a free batch slot.
+    Queued,
     Pending,
     Extracting,
     Running,
@@ -16,6 +18,218 @@ pub enum BatchStatus {
     Failed,
 }
 
+/// How per-task rewards are combined into a batch's `aggregate_reward`.
+/// Tasks without a result count as reward 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RewardAggregation {
+    /// Sum of rewards over the task count.
+    #[default]
+    Mean,
+    /// Geometric mean; a single zero reward zeroes the batch.
+    Geometric,
+    /// Worst task reward.
+    Min,
+    /// Mean weighted by each task's `weight` from `workspace.yaml`.
+    Weighted,
+}
+
+impl std::str::FromStr for RewardAggregation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "mean" => Ok(RewardAggregation::Mean),
+            "geometric" => Ok(RewardAggregation::Geometric),
+            "min" => Ok(RewardAggregation::Min),
+            "weighted" => Ok(RewardAggregation::Weighted),
+            other => Err(format!("unknown reward aggregation '{}'", other)),
+        }
+    }
+}
+
+impl RewardAggregation {
+    /// Combine `(reward, weight)` pairs, one per task. Weights are only used
+    /// by `Weighted`. An empty batch scores 0.
+    pub fn aggregate(self, rewards: &[(f64, f64)]) -> f64 {
+        if rewards.is_empty() {
+            return 0.0;
+        }
+        let n = rewards.len() as f64;
+        match self {
+            RewardAggregation::Mean => rewards.iter().map(|(r, _)| r).sum::<f64>() / n,
+            RewardAggregation::Geometric => {
+                if rewards.iter().any(|(r, _)| *r <= 0.0) {
+                    return 0.0;
+                }
+                (rewards.iter().map(|(r, _)| r.ln()).sum::<f64>() / n).exp()
+            }
+            RewardAggregation::Min => rewards
+                .iter()
+                .map(|(r, _)| *r)
+                .fold(f64::INFINITY, f64::min),
+            RewardAggregation::Weighted => {
+                let total_weight: f64 = rewards.iter().map(|(_, w)| w).sum();
+                if total_weight <= 0.0 {
+                    return 0.0;
+                }
+                rewards.iter().map(|(r, w)| r * w).sum::<f64>() / total_weight
+            }
+        }
+    }
+}
+
+/// Order of the `tasks` array in a finished batch. Tasks still run
+/// concurrently; only the assembled result is sorted.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum TaskOrder {
+    /// As the tasks appear in the submitted archive.
+    #[default]
+    Archive,
+    /// By task id.
+    Id,
+    /// Highest reward first, ties broken by task id.
+    Reward,
+}
+
+impl std::str::FromStr for TaskOrder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "archive" => Ok(TaskOrder::Archive),
+            "id" => Ok(TaskOrder::Id),
+            "reward" => Ok(TaskOrder::Reward),
+            other => Err(format!("unknown task order '{}'", other)),
+        }
+    }
+}
+
+impl TaskOrder {
+    /// Sort `tasks` (in completion order) deterministically. `archive_order`
+    /// lists the task ids as submitted; ids not in it go last, by id.
+    pub fn sort(self, tasks: &mut [TaskResult], archive_order: &[String]) {
+        match self {
+            TaskOrder::Archive => {
+                let position = |id: &str| {
+                    archive_order
+                        .iter()
+                        .position(|a| a == id)
+                        .unwrap_or(usize::MAX)
+                };
+                tasks.sort_by(|a, b| {
+                    (position(&a.task_id), &a.task_id).cmp(&(position(&b.task_id), &b.task_id))
+                });
+            }
+            TaskOrder::Id => tasks.sort_by(|a, b| a.task_id.cmp(&b.task_id)),
+            TaskOrder::Reward => tasks.sort_by(|a, b| {
+                b.reward
+                    .total_cmp(&a.reward)
+                    .then_with(|| a.task_id.cmp(&b.task_id))
+            }),
+        }
+    }
+}
+
+/// How a task's reward is derived from its test results when a batch is
+/// rescored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScoringMode {
+    /// 1.0 when every test script passed, 0.0 otherwise (the run-time rule).
+    #[default]
+    Binary,
+    /// Share of individual tests passed when every script reported counts,
+    /// otherwise the share of test scripts that passed.
+    Fractional,
+}
+
+impl ScoringMode {
+    /// Reward for one task's test results. No results scores 0.
+    pub fn score(self, tests: &[TaskTestResult]) -> f64 {
+        if tests.is_empty() {
+            return 0.0;
+        }
+        match self {
+            ScoringMode::Binary => {
+                if tests.iter().all(|t| t.passed) {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+            ScoringMode::Fractional => {
+                let counts: Option<Vec<(u32, u32)>> = tests
+                    .iter()
+                    .map(|t| t.tests_passed.zip(t.tests_total))
+                    .collect();
+                let (passed, total) = match counts {
+                    Some(counts) if counts.iter().any(|(_, total)| *total > 0) => counts
+                        .iter()
+                        .fold((0, 0), |(p, n), (tp, tn)| (p + tp, n + tn)),
+                    _ => (
+                        tests.iter().filter(|t| t.passed).count() as u32,
+                        tests.len() as u32,
+                    ),
+                };
+                passed as f64 / total as f64
+            }
+        }
+    }
+
+    /// Reward for a whole task as at run time: a task with evaluation
+    /// stages scores their weighted average scaled by its test score, and a
+    /// failed lint gate scores 0.
+    pub fn score_task(self, task: &TaskResult) -> f64 {
+        if task.lint.as_ref().is_some_and(|l| !l.passed) {
+            return 0.0;
+        }
+        if task.stages.is_empty() {
+            return self.score(&task.test_results);
+        }
+        let tests = if task.test_results.is_empty() {
+            1.0
+        } else {
+            self.score(&task.test_results)
+        };
+        let total_weight: f64 = task.stages.iter().map(|s| s.weight).sum();
+        if total_weight <= 0.0 {
+            return 0.0;
+        }
+        let weighted_sum: f64 = task.stages.iter().map(|s| s.score * s.weight).sum();
+        tests * (weighted_sum / total_weight).clamp(0.0, 1.0)
+    }
+}
+
+/// Batch statuses that occupy the executor. `Pending` is included so a batch
+/// that has been created but not yet started still blocks new submissions.
+pub const ACTIVE_BATCH_STATUSES: &[BatchStatus] = &[
+    BatchStatus::Pending,
+    BatchStatus::Extracting,
+    BatchStatus::Running,
+];
+
+impl BatchStatus {
+    pub fn is_active(&self) -> bool {
+        ACTIVE_BATCH_STATUSES.contains(self)
+    }
+
+    /// Finished either way; nothing will touch the batch again unless an
+    /// interrupted one is resumed.
+    pub fn is_terminal(&self) -> bool {
+        matches!(self, BatchStatus::Completed | BatchStatus::Failed)
+    }
+}
+
+/// Outcome of `SessionManager::remove_batch`.
+#[derive(Debug, PartialEq)]
+pub enum BatchRemoval {
+    Removed(BatchStatus),
+    NotFound,
+    /// Still queued or running; only terminal batches can be removed.
+    NotTerminal(BatchStatus),
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "snake_case")]
 pub enum TaskStatus {
@@ -26,6 +240,58 @@ pub enum TaskStatus {
     RunningTests,
     Completed,
     Failed,
+    DiskQuotaExceeded,
+}
+
+impl TaskStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TaskStatus::Queued => "queued",
+            TaskStatus::CloningRepo => "cloning_repo",
+            TaskStatus::InstallingDeps => "installing_deps",
+            TaskStatus::RunningAgent => "running_agent",
+            TaskStatus::RunningTests => "running_tests",
+            TaskStatus::Completed => "completed",
+            TaskStatus::Failed => "failed",
+            TaskStatus::DiskQuotaExceeded => "disk_quota_exceeded",
+        }
+    }
+}
+
+/// Why a task could not be evaluated, for consumers that would otherwise
+/// have to pattern-match `TaskResult.error`. `None` on a task that ran to
+/// the end, even if its tests failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskFailureKind {
+    CloneFailed,
+    InstallFailed,
+    AgentTimeout,
+    /// A test script was killed at its timeout; the task may still have run
+    /// to the end.
+    TestTimeout,
+    /// A test script failed for environmental reasons (command not found,
+    /// OOM kill, ...); the reward is not a judgement of the agent.
+    TestInfra,
+    /// A command of the task was SIGKILLed by the kernel OOM killer.
+    OomKilled,
+    Cancelled,
+    QuotaExceeded,
+    Internal,
+}
+
+impl TaskFailureKind {
+    /// Kind of an error that aborted the pipeline while in `phase`.
+    pub fn from_phase(phase: &TaskStatus, e: &anyhow::Error) -> Self {
+        let timed_out = crate::sandbox::is_timeout(e);
+        match phase {
+            TaskStatus::CloningRepo => TaskFailureKind::CloneFailed,
+            TaskStatus::InstallingDeps => TaskFailureKind::InstallFailed,
+            TaskStatus::RunningAgent if timed_out => TaskFailureKind::AgentTimeout,
+            TaskStatus::RunningTests if timed_out => TaskFailureKind::TestTimeout,
+            _ => TaskFailureKind::Internal,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -34,6 +300,70 @@ pub struct TaskTestResult {
     pub passed: bool,
     pub output: String,
     pub exit_code: i32,
+    /// Individual test counts parsed from `output` (pytest, cargo, go -v,
+    /// jest); `None` when the output has no recognisable summary.
+    #[serde(default)]
+    pub tests_total: Option<u32>,
+    #[serde(default)]
+    pub tests_passed: Option<u32>,
+    #[serde(default)]
+    pub tests_failed: Option<u32>,
+    /// The script was killed at the test timeout.
+    #[serde(default)]
+    pub timed_out: bool,
+    /// The script ended with an infrastructure failure (see
+    /// `executor::INFRA_EXIT_CODES`) even after a retry, so its failure says
+    /// nothing about the agent.
+    #[serde(default)]
+    pub infra_error: bool,
+}
+
+impl TaskTestResult {
+    pub fn new(name: String, passed: bool, output: String, exit_code: i32) -> Self {
+        let counts = crate::task::test_counts::parse_test_counts(&output);
+        Self {
+            name,
+            passed,
+            output,
+            exit_code,
+            tests_total: counts.map(|c| c.total),
+            tests_passed: counts.map(|c| c.passed),
+            tests_failed: counts.map(|c| c.failed),
+            timed_out: false,
+            infra_error: false,
+        }
+    }
+}
+
+/// A file declared under `artifacts:` in `workspace.yaml`, read from the repo
+/// after the test stage.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TaskArtifact {
+    /// Path relative to the repo root.
+    pub name: String,
+    pub size_bytes: u64,
+    /// `content` holds only the first part of the file.
+    pub truncated: bool,
+    pub content: String,
+}
+
+/// Structured result an agent may leave in `_agent_result.json` at the repo root.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct AgentReport {
+    pub status: String,
+    #[serde(default)]
+    pub modified_files: Vec<String>,
+    #[serde(default)]
+    pub notes: Option<String>,
+}
+
+impl AgentReport {
+    pub fn claims_failure(&self) -> bool {
+        matches!(
+            self.status.to_ascii_lowercase().as_str(),
+            "failed" | "failure" | "error" | "unsolved" | "gave_up"
+        )
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,7 +377,54 @@ pub struct TaskResult {
     pub agent_output: String,
     pub agent_patch: String,
     pub error: Option<String>,
+    #[serde(default)]
+    pub failure_kind: Option<TaskFailureKind>,
     pub duration_ms: Option<u64>,
+    /// Time spent waiting for a concurrency slot.
+    #[serde(default)]
+    pub queue_ms: Option<u64>,
+    /// Time from acquiring the slot until the task finished.
+    #[serde(default)]
+    pub exec_ms: Option<u64>,
+    /// (phase, error/stderr) for every phase that reported a problem, in order.
+    #[serde(default)]
+    pub phase_errors: Vec<(String, String)>,
+    /// SHA-256 of `agent_patch`, set once the agent has run.
+    #[serde(default)]
+    pub agent_patch_hash: Option<String>,
+    /// Whether the diff differs from the previous run of the same task by
+    /// the same agent; `None` when there is no such run to compare against.
+    #[serde(default)]
+    pub agent_output_changed: Option<bool>,
+    #[serde(default)]
+    pub agent_report: Option<AgentReport>,
+    /// The agent process exited faster than `MIN_AGENT_RUNTIME_MS`.
+    #[serde(default)]
+    pub suspicious_agent: bool,
+    /// False when an install command failed, so test failures may be down to
+    /// the environment rather than the agent.
+    #[serde(default = "default_setup_ok")]
+    pub setup_ok: bool,
+    /// Each install command run, with the exit code and stderr tail of the
+    /// ones that failed.
+    #[serde(default)]
+    pub setup_log: String,
+    /// Files matched by the task's `artifacts:` globs (coverage, junit, ...).
+    #[serde(default)]
+    pub artifacts: Vec<TaskArtifact>,
+    /// Scores of the task's `stages:`, when it declares any.
+    #[serde(default)]
+    pub stages: Vec<crate::evaluation::pipeline::StageResult>,
+    /// Outcome of the task's `lint:` gate, when it declares one.
+    #[serde(default)]
+    pub lint: Option<TaskTestResult>,
+    /// Highest resident memory of the agent and test commands, in KiB.
+    #[serde(default)]
+    pub peak_memory_kb: Option<u64>,
+}
+
+fn default_setup_ok() -> bool {
+    true
 }
 
 impl TaskResult {
@@ -62,9 +439,27 @@ impl TaskResult {
             agent_output: String::new(),
             agent_patch: String::new(),
             error: None,
+            failure_kind: None,
             duration_ms: None,
+            queue_ms: None,
+            exec_ms: None,
+            phase_errors: Vec::new(),
+            agent_patch_hash: None,
+            agent_output_changed: None,
+            agent_report: None,
+            suspicious_agent: false,
+            setup_ok: true,
+            setup_log: String::new(),
+            artifacts: Vec::new(),
+            stages: Vec::new(),
+            lint: None,
+            peak_memory_kb: None,
         }
     }
+
+    pub fn record_phase_error(&mut self, phase: &str, detail: impl Into<String>) {
+        self.phase_errors.push((phase.to_string(), detail.into()));
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -77,8 +472,48 @@ pub struct BatchResult {
     pub failed_tasks: usize,
     pub tasks: Vec<TaskResult>,
     pub aggregate_reward: f64,
+    /// Strategy used to compute `aggregate_reward`.
+    #[serde(default)]
+    pub reward_aggregation: RewardAggregation,
     pub error: Option<String>,
     pub duration_ms: Option<u64>,
+    /// Retries drawn from the batch's `BATCH_RETRY_BUDGET`.
+    #[serde(default)]
+    pub retries_used: u32,
+}
+
+impl BatchResult {
+    /// Per-task rewards recomputed from the stored test, stage and lint
+    /// results, and their aggregate. Nothing is re-executed. `task_weights`
+    /// lists the batch's tasks with their weights; as at run time, a listed
+    /// task without a result counts as 0, and so does each task of
+    /// `total_tasks` beyond the list, with weight 1.
+    pub fn rescore(
+        &self,
+        mode: ScoringMode,
+        aggregation: RewardAggregation,
+        task_weights: &[(String, f64)],
+    ) -> (Vec<(String, f64)>, f64) {
+        let rewards: Vec<(String, f64)> = self
+            .tasks
+            .iter()
+            .map(|t| (t.task_id.clone(), mode.score_task(t)))
+            .collect();
+        let missing = self.total_tasks.saturating_sub(task_weights.len());
+        let weighted: Vec<(f64, f64)> = task_weights
+            .iter()
+            .map(|(id, weight)| {
+                let reward = rewards
+                    .iter()
+                    .find(|(task_id, _)| task_id == id)
+                    .map_or(0.0, |(_, reward)| *reward);
+                (reward, *weight)
+            })
+            .chain(std::iter::repeat_n((0.0, 1.0), missing))
+            .collect();
+        let aggregate = aggregation.aggregate(&weighted);
+        (rewards, aggregate)
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -90,23 +525,165 @@ pub struct WsEvent {
     pub data: serde_json::Value,
 }
 
+/// Capacity of the all-batches event channel.
+const FIREHOSE_CAPACITY: usize = 1024;
+/// Concurrent all-batches WebSocket subscribers allowed.
+pub const MAX_FIREHOSE_SUBSCRIBERS: usize = 16;
+/// Diff hashes kept for `agent_output_changed`; the oldest goes first.
+const MAX_PATCH_HASHES: usize = 10_000;
+
 pub struct Batch {
     pub id: String,
     pub created_at: DateTime<Utc>,
     pub result: Arc<Mutex<BatchResult>>,
     pub events_tx: broadcast::Sender<WsEvent>,
     pub cancel: tokio::sync::watch::Sender<bool>,
+    /// The agent the batch evaluated, recorded when it is launched.
+    pub agent: std::sync::OnceLock<BatchAgent>,
+    /// What each task will run, recorded when the batch is launched.
+    pub plans: std::sync::OnceLock<Vec<TaskPlan>>,
+    firehose_tx: broadcast::Sender<WsEvent>,
 }
 
-impl Batch {
-    pub async fn emit_event(&self, event: &str, task_id: Option<&str>, data: serde_json::Value) {
-        let ws_event = WsEvent {
+/// The agent submission behind a batch, kept for dispute resolution. The
+/// code itself is only retained with `RETAIN_BATCH_AGENT_CODE`.
+#[derive(Debug, Clone, Serialize)]
+pub struct BatchAgent {
+    pub language: String,
+    pub code_sha256: String,
+    pub code_bytes: usize,
+    /// Hash of the packed `agent_code/` tree of a multi-file agent, whose
+    /// other files `code_sha256` doesn't cover.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archive_sha256: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<String>,
+}
+
+impl BatchAgent {
+    /// Identifies the agent across batches: the whole packed tree when
+    /// there is one, otherwise the code.
+    pub fn fingerprint(&self) -> &str {
+        self.archive_sha256.as_deref().unwrap_or(&self.code_sha256)
+    }
+
+    pub fn new(code: &str, language: &str, archive: Option<&[u8]>, retain_code: bool) -> Self {
+        use sha2::{Digest, Sha256};
+        Self {
+            language: language.to_string(),
+            code_sha256: hex::encode(Sha256::digest(code.as_bytes())),
+            code_bytes: code.len(),
+            archive_sha256: archive.map(|a| hex::encode(Sha256::digest(a))),
+            code: retain_code.then(|| code.to_string()),
+        }
+    }
+}
+
+/// A task's repo, install commands and test script names as submitted, so a
+/// validator can review what will run before results arrive. A repo's
+/// `.task-executor.yaml` or `AUTO_TEST` may still change them at run time.
+#[derive(Debug, Clone)]
+pub struct TaskPlan {
+    pub task_id: String,
+    pub repo: String,
+    pub base_commit: Option<String>,
+    pub install: Vec<String>,
+    pub lint: Option<String>,
+    pub test_scripts: Vec<String>,
+    /// The task's `weight` in the batch aggregate.
+    pub weight: f64,
+    /// (name, content) of each test script, attached once the task has
+    /// finished so the agent can't read its hidden tests while it runs.
+    script_contents: std::sync::OnceLock<Vec<(String, String)>>,
+}
+
+impl TaskPlan {
+    pub fn new(task: &crate::task::SweForgeTask) -> Self {
+        Self {
+            task_id: task.id.clone(),
+            repo: task.workspace.repo.clone(),
+            base_commit: task.workspace.base_commit.clone(),
+            install: task.workspace.install.clone().unwrap_or_default(),
+            lint: task.workspace.lint.clone(),
+            test_scripts: task
+                .test_scripts
+                .iter()
+                .map(|(name, _)| name.clone())
+                .collect(),
+            weight: task.workspace.weight(),
+            script_contents: std::sync::OnceLock::new(),
+        }
+    }
+
+    /// Make the test script contents available. Call only once the task is
+    /// in a terminal state.
+    pub fn reveal_scripts(&self, scripts: Vec<(String, String)>) {
+        let _ = self.script_contents.set(scripts);
+    }
+
+    /// The plan as JSON. Script contents can be large, so they are only
+    /// included when asked for, and only once the task has finished.
+    pub fn to_json(&self, include_scripts: bool) -> serde_json::Value {
+        let contents = self.script_contents.get().filter(|_| include_scripts);
+        let scripts: Vec<serde_json::Value> = self
+            .test_scripts
+            .iter()
+            .map(|name| {
+                let content = contents
+                    .and_then(|c| c.iter().find(|(n, _)| n == name))
+                    .map(|(_, content)| content);
+                match content {
+                    Some(content) => serde_json::json!({ "name": name, "content": content }),
+                    None => serde_json::json!({ "name": name }),
+                }
+            })
+            .collect();
+        serde_json::json!({
+            "task_id": self.task_id,
+            "repo": self.repo,
+            "base_commit": self.base_commit,
+            "install": self.install,
+            "lint": self.lint,
+            "test_scripts": scripts,
+        })
+    }
+}
+
+/// Publishes to a batch's own channel and to the all-batches channel.
+#[derive(Clone)]
+pub struct EventSender {
+    batch_id: String,
+    batch_tx: broadcast::Sender<WsEvent>,
+    firehose_tx: broadcast::Sender<WsEvent>,
+}
+
+impl EventSender {
+    pub fn send(&self, event: WsEvent) {
+        let _ = self.firehose_tx.send(event.clone());
+        let _ = self.batch_tx.send(event);
+    }
+
+    pub fn emit(&self, event: &str, task_id: Option<&str>, data: serde_json::Value) {
+        self.send(WsEvent {
             event: event.to_string(),
-            batch_id: self.id.clone(),
+            batch_id: self.batch_id.clone(),
             task_id: task_id.map(|s| s.to_string()),
             data,
-        };
-        let _ = self.events_tx.send(ws_event);
+        });
+    }
+}
+
+impl Batch {
+    pub fn event_sender(&self) -> EventSender {
+        EventSender {
+            batch_id: self.id.clone(),
+            batch_tx: self.events_tx.clone(),
+            firehose_tx: self.firehose_tx.clone(),
+        }
+    }
+
+    pub async fn emit_event(&self, event: &str, task_id: Option<&str>, data: serde_json::Value) {
+        self.event_sender().emit(event, task_id, data);
     }
 }
 
@@ -130,63 +707,181 @@ impl SessionStats {
 
 pub struct SessionManager {
     batches: DashMap<String, Arc<Batch>>,
+    /// Last diff hash per (agent fingerprint, task id). Outlives batch
+    /// reaping so reruns can be compared against earlier batches, but is
+    /// dropped `ttl_secs` after it was last recorded and held to
+    /// `MAX_PATCH_HASHES` entries.
+    patch_hashes: DashMap<(String, String), (String, DateTime<Utc>)>,
+    /// Client-chosen `Idempotency-Key` -> batch id. Entries live as long as
+    /// the batch they point to.
+    idempotency_keys: DashMap<String, String>,
+    /// Events from every batch, plus `batch_created`.
+    firehose_tx: broadcast::Sender<WsEvent>,
+    draining: AtomicBool,
+    /// Set by `/admin/quiesce`: no new batches until `/admin/resume`.
+    quiesced: AtomicBool,
+    /// Batches holding a run slot; see `try_reserve_batch_slot`.
+    running_batches: Arc<AtomicUsize>,
     ttl_secs: u64,
     pub stats: SessionStats,
 }
 
+/// One of the `MAX_CONCURRENT_BATCHES` run slots, held by a batch from
+/// admission until it finishes. Dropping it frees the slot.
+#[derive(Debug)]
+pub struct BatchSlot(Arc<AtomicUsize>);
+
+impl Drop for BatchSlot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
 impl SessionManager {
     pub fn new(ttl_secs: u64) -> Self {
         Self {
             batches: DashMap::new(),
+            patch_hashes: DashMap::new(),
+            idempotency_keys: DashMap::new(),
+            firehose_tx: broadcast::channel(FIREHOSE_CAPACITY).0,
+            draining: AtomicBool::new(false),
+            quiesced: AtomicBool::new(false),
+            running_batches: Arc::new(AtomicUsize::new(0)),
             ttl_secs,
             stats: SessionStats::new(),
         }
     }
 
-    pub fn create_batch(&self, total_tasks: usize) -> Arc<Batch> {
-        let id = uuid::Uuid::new_v4().to_string();
+    fn new_batch(&self, result: BatchResult) -> Arc<Batch> {
         let (events_tx, _) = broadcast::channel(256);
         let (cancel_tx, _) = tokio::sync::watch::channel(false);
-
-        let batch = Arc::new(Batch {
-            id: id.clone(),
+        Arc::new(Batch {
+            id: result.batch_id.clone(),
             created_at: Utc::now(),
-            result: Arc::new(Mutex::new(BatchResult {
-                batch_id: id.clone(),
-                status: BatchStatus::Pending,
-                total_tasks,
-                completed_tasks: 0,
-                passed_tasks: 0,
-                failed_tasks: 0,
-                tasks: Vec::new(),
-                aggregate_reward: 0.0,
-                error: None,
-                duration_ms: None,
-            })),
+            result: Arc::new(Mutex::new(result)),
             events_tx,
             cancel: cancel_tx,
+            agent: std::sync::OnceLock::new(),
+            plans: std::sync::OnceLock::new(),
+            firehose_tx: self.firehose_tx.clone(),
+        })
+    }
+
+    pub fn create_batch(&self, total_tasks: usize) -> Arc<Batch> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let batch = self.new_batch(BatchResult {
+            batch_id: id.clone(),
+            status: BatchStatus::Pending,
+            total_tasks,
+            completed_tasks: 0,
+            passed_tasks: 0,
+            failed_tasks: 0,
+            tasks: Vec::new(),
+            aggregate_reward: 0.0,
+            reward_aggregation: RewardAggregation::default(),
+            error: None,
+            duration_ms: None,
+            retries_used: 0,
         });
 
+        let _ = self.firehose_tx.send(WsEvent {
+            event: "batch_created".to_string(),
+            batch_id: id.clone(),
+            task_id: None,
+            data: serde_json::json!({ "total_tasks": total_tasks }),
+        });
         self.batches.insert(id, batch.clone());
         self.stats.created.fetch_add(1, Ordering::Relaxed);
         self.stats.active.fetch_add(1, Ordering::Relaxed);
         batch
     }
 
+    /// Re-register a batch interrupted by a restart, holding the tasks that
+    /// had already finished. It stays `Failed` until resumed.
+    pub fn restore_batch(
+        &self,
+        batch_id: &str,
+        total_tasks: usize,
+        completed: Vec<TaskResult>,
+    ) -> Arc<Batch> {
+        let passed_tasks = completed.iter().filter(|t| t.reward == 1.0).count();
+        let batch = self.new_batch(BatchResult {
+            batch_id: batch_id.to_string(),
+            status: BatchStatus::Failed,
+            total_tasks,
+            completed_tasks: completed.len(),
+            passed_tasks,
+            failed_tasks: completed.len() - passed_tasks,
+            tasks: completed,
+            aggregate_reward: 0.0,
+            reward_aggregation: RewardAggregation::default(),
+            error: Some(format!(
+                "interrupted by restart; resume with POST /batch/{}/resume",
+                batch_id
+            )),
+            duration_ms: None,
+            retries_used: 0,
+        });
+        self.batches.insert(batch_id.to_string(), batch.clone());
+        self.stats.created.fetch_add(1, Ordering::Relaxed);
+        self.stats.failed.fetch_add(1, Ordering::Relaxed);
+        batch
+    }
+
+    /// A restored batch is running again.
+    pub fn mark_resumed(&self) {
+        self.stats.failed.fetch_sub(1, Ordering::Relaxed);
+        self.stats.active.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Subscribe to events from all batches, or `None` once
+    /// `MAX_FIREHOSE_SUBSCRIBERS` are already listening.
+    pub fn subscribe_all(&self) -> Option<broadcast::Receiver<WsEvent>> {
+        (self.firehose_tx.receiver_count() < MAX_FIREHOSE_SUBSCRIBERS)
+            .then(|| self.firehose_tx.subscribe())
+    }
+
     pub fn get(&self, id: &str) -> Option<Arc<Batch>> {
         self.batches.get(id).map(|b| b.value().clone())
     }
 
     pub fn has_active_batch(&self) -> bool {
-        for entry in self.batches.iter() {
-            let result = entry.value().result.try_lock();
-            if let Ok(r) = result {
-                if r.status == BatchStatus::Running || r.status == BatchStatus::Extracting {
-                    return true;
-                }
+        self.active_batch_count() > 0
+    }
+
+    /// Claim a run slot unless `max` batches (at least one) already hold
+    /// one. Checking and claiming is a single step, so two submissions
+    /// can't both take the last slot.
+    pub fn try_reserve_batch_slot(&self, max: usize) -> Option<BatchSlot> {
+        let max = max.max(1);
+        self.running_batches
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| {
+                (n < max).then_some(n + 1)
+            })
+            .ok()?;
+        Some(BatchSlot(self.running_batches.clone()))
+    }
+
+    /// Number of batches holding a run slot.
+    pub fn active_batch_count(&self) -> usize {
+        self.running_batches.load(Ordering::SeqCst)
+    }
+
+    /// Id and completed/total fraction of an active batch. Batches whose
+    /// result is locked at the moment are skipped rather than waited on.
+    pub fn active_batch_progress(&self) -> Option<(String, f64)> {
+        self.batches.iter().find_map(|entry| {
+            let result = entry.value().result.try_lock().ok()?;
+            if !result.status.is_active() {
+                return None;
             }
-        }
-        false
+            let progress = if result.total_tasks == 0 {
+                0.0
+            } else {
+                result.completed_tasks as f64 / result.total_tasks as f64
+            };
+            Some((entry.key().clone(), progress))
+        })
     }
 
     pub fn list_batches(&self) -> Vec<BatchSummary> {
@@ -208,6 +903,41 @@ impl SessionManager {
             .collect()
     }
 
+    pub fn remember_idempotency_key(&self, key: &str, batch_id: &str) {
+        self.idempotency_keys
+            .insert(key.to_string(), batch_id.to_string());
+    }
+
+    /// Batch previously started under `key`, while it is still retained.
+    pub fn batch_for_idempotency_key(&self, key: &str) -> Option<Arc<Batch>> {
+        let batch_id = self.idempotency_keys.get(key)?.value().clone();
+        let batch = self.get(&batch_id);
+        if batch.is_none() {
+            self.idempotency_keys.remove(key);
+        }
+        batch
+    }
+
+    /// Store `hash` as the latest diff hash of `agent` on `task_id` and
+    /// report whether it differs from that agent's previous run (`None` on
+    /// the first run).
+    pub fn record_patch_hash(&self, agent: &str, task_id: &str, hash: &str) -> Option<bool> {
+        let key = (agent.to_string(), task_id.to_string());
+        if self.patch_hashes.len() >= MAX_PATCH_HASHES && !self.patch_hashes.contains_key(&key) {
+            let oldest = self
+                .patch_hashes
+                .iter()
+                .min_by_key(|e| e.value().1)
+                .map(|e| e.key().clone());
+            if let Some(oldest) = oldest {
+                self.patch_hashes.remove(&oldest);
+            }
+        }
+        self.patch_hashes
+            .insert(key, (hash.to_string(), Utc::now()))
+            .map(|(prev, _)| prev != hash)
+    }
+
     pub fn mark_completed(&self) {
         self.stats.active.fetch_sub(1, Ordering::Relaxed);
         self.stats.completed.fetch_add(1, Ordering::Relaxed);
@@ -218,6 +948,74 @@ impl SessionManager {
         self.stats.failed.fetch_add(1, Ordering::Relaxed);
     }
 
+    /// Drop a terminal batch before the reaper would. The status is checked
+    /// under the result lock, so a batch can't start running while it is
+    /// being removed, and the active/completed/failed stats are untouched.
+    pub async fn remove_batch(&self, id: &str) -> BatchRemoval {
+        let Some(batch) = self.get(id) else {
+            return BatchRemoval::NotFound;
+        };
+        let result = batch.result.lock().await;
+        if !result.status.is_terminal() {
+            return BatchRemoval::NotTerminal(result.status.clone());
+        }
+        self.batches.remove(id);
+        self.idempotency_keys.retain(|_, batch_id| batch_id != id);
+        info!("Removed batch {}", id);
+        BatchRemoval::Removed(result.status.clone())
+    }
+
+    /// Ids of terminal batches, optionally only those in `status`. Batches
+    /// whose result is locked at the moment are skipped.
+    pub fn terminal_batch_ids(&self, status: Option<&BatchStatus>) -> Vec<String> {
+        self.batches
+            .iter()
+            .filter(|entry| {
+                entry.value().result.try_lock().is_ok_and(|r| {
+                    r.status.is_terminal() && (status.is_none() || status == Some(&r.status))
+                })
+            })
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+
+    /// Stop accepting new batches; running ones are left to finish.
+    pub fn begin_drain(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Stop or resume accepting new batches for maintenance. Returns the
+    /// previous setting.
+    pub fn set_quiesced(&self, quiesced: bool) -> bool {
+        self.quiesced.swap(quiesced, Ordering::SeqCst)
+    }
+
+    pub fn is_quiesced(&self) -> bool {
+        self.quiesced.load(Ordering::SeqCst)
+    }
+
+    /// Wait until no batch is running. Returns false if `timeout` elapses first.
+    pub async fn wait_until_idle(&self, timeout: std::time::Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        while self.stats.active.load(Ordering::Relaxed) > 0 {
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+        }
+        true
+    }
+
+    pub fn cancel_all(&self) {
+        for entry in self.batches.iter() {
+            let _ = entry.value().cancel.send(true);
+        }
+    }
+
     pub async fn reaper_loop(&self) {
         let mut interval = tokio::time::interval(tokio::time::Duration::from_secs(60));
         loop {
@@ -238,6 +1036,10 @@ impl SessionManager {
                     info!("Reaped expired batch {}", id);
                 }
             }
+            self.idempotency_keys
+                .retain(|_, batch_id| self.batches.contains_key(batch_id));
+            self.patch_hashes
+                .retain(|_, (_, at)| ((now - *at).num_seconds() as u64) <= self.ttl_secs);
         }
     }
 }
@@ -248,3 +1050,430 @@ pub struct BatchSummary {
     pub created_at: DateTime<Utc>,
     pub status: BatchStatus,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_firehose_sees_every_batch() {
+        let mgr = SessionManager::new(3600);
+        let mut rx = mgr.subscribe_all().unwrap();
+
+        let a = mgr.create_batch(1);
+        let b = mgr.create_batch(2);
+        a.emit_event("batch_started", None, serde_json::json!({}))
+            .await;
+        b.event_sender().send(WsEvent {
+            event: "task_started".into(),
+            batch_id: b.id.clone(),
+            task_id: Some("t1".into()),
+            data: serde_json::json!({}),
+        });
+
+        let mut seen = Vec::new();
+        for _ in 0..4 {
+            let e = rx.recv().await.unwrap();
+            seen.push((e.event, e.batch_id));
+        }
+        assert_eq!(
+            seen,
+            vec![
+                ("batch_created".to_string(), a.id.clone()),
+                ("batch_created".to_string(), b.id.clone()),
+                ("batch_started".to_string(), a.id.clone()),
+                ("task_started".to_string(), b.id.clone()),
+            ]
+        );
+
+        let _others: Vec<_> = (1..MAX_FIREHOSE_SUBSCRIBERS)
+            .map(|_| mgr.subscribe_all().unwrap())
+            .collect();
+        assert!(mgr.subscribe_all().is_none());
+    }
+
+    #[test]
+    fn test_failure_kind_from_phase() {
+        let timeout: anyhow::Error = crate::sandbox::TimedOut(30).into();
+        let timeout = timeout.context("agent run failed");
+        let other = anyhow::anyhow!("exit 128");
+
+        for (phase, e, kind) in [
+            (
+                TaskStatus::CloningRepo,
+                &other,
+                TaskFailureKind::CloneFailed,
+            ),
+            (
+                TaskStatus::CloningRepo,
+                &timeout,
+                TaskFailureKind::CloneFailed,
+            ),
+            (
+                TaskStatus::InstallingDeps,
+                &other,
+                TaskFailureKind::InstallFailed,
+            ),
+            (
+                TaskStatus::RunningAgent,
+                &timeout,
+                TaskFailureKind::AgentTimeout,
+            ),
+            (TaskStatus::RunningAgent, &other, TaskFailureKind::Internal),
+            (
+                TaskStatus::RunningTests,
+                &timeout,
+                TaskFailureKind::TestTimeout,
+            ),
+            (TaskStatus::Queued, &other, TaskFailureKind::Internal),
+        ] {
+            assert_eq!(TaskFailureKind::from_phase(&phase, e), kind, "{:?}", phase);
+        }
+
+        let mut result = TaskResult::new("t".into());
+        result.failure_kind = Some(TaskFailureKind::AgentTimeout);
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["failure_kind"], "agent_timeout");
+    }
+
+    #[test]
+    fn test_task_plan_hides_script_contents_until_revealed() {
+        let plan = TaskPlan {
+            task_id: "t1".into(),
+            repo: "https://github.com/o/r".into(),
+            base_commit: Some("abc".into()),
+            install: vec!["pip install -e .".into()],
+            lint: None,
+            test_scripts: vec!["tests/run.sh".into()],
+            weight: 1.0,
+            script_contents: std::sync::OnceLock::new(),
+        };
+        let brief = plan.to_json(false);
+        assert_eq!(brief["test_scripts"][0]["name"], "tests/run.sh");
+        assert!(brief["test_scripts"][0].get("content").is_none());
+        assert_eq!(brief["install"][0], "pip install -e .");
+        let running = plan.to_json(true);
+        assert!(running["test_scripts"][0].get("content").is_none());
+
+        plan.reveal_scripts(vec![("tests/run.sh".into(), "pytest -q\n".into())]);
+        assert!(plan.to_json(false)["test_scripts"][0]
+            .get("content")
+            .is_none());
+        let full = plan.to_json(true);
+        assert_eq!(full["test_scripts"][0]["content"], "pytest -q\n");
+    }
+
+    #[test]
+    fn test_reward_aggregation() {
+        let mixed = [(1.0, 1.0), (0.25, 3.0)];
+        let all_pass = [(1.0, 1.0), (1.0, 2.0)];
+        let all_fail = [(0.0, 1.0), (0.0, 2.0)];
+        let close = |a: f64, b: f64| (a - b).abs() < 1e-9;
+
+        assert!(close(RewardAggregation::Mean.aggregate(&mixed), 0.625));
+        assert!(close(RewardAggregation::Geometric.aggregate(&mixed), 0.5));
+        assert!(close(RewardAggregation::Min.aggregate(&mixed), 0.25));
+        assert!(close(RewardAggregation::Weighted.aggregate(&mixed), 0.4375));
+
+        for strategy in [
+            RewardAggregation::Mean,
+            RewardAggregation::Geometric,
+            RewardAggregation::Min,
+            RewardAggregation::Weighted,
+        ] {
+            assert!(close(strategy.aggregate(&all_pass), 1.0), "{:?}", strategy);
+            assert!(close(strategy.aggregate(&all_fail), 0.0), "{:?}", strategy);
+            assert!(close(strategy.aggregate(&[]), 0.0), "{:?}", strategy);
+        }
+
+        assert!(close(
+            RewardAggregation::Geometric.aggregate(&[(1.0, 1.0), (0.0, 1.0)]),
+            0.0
+        ));
+        assert!(close(
+            RewardAggregation::Weighted.aggregate(&[(1.0, 0.0), (0.5, 0.0)]),
+            0.0
+        ));
+        assert_eq!("Geometric".parse(), Ok(RewardAggregation::Geometric));
+        assert!("median".parse::<RewardAggregation>().is_err());
+    }
+
+    #[test]
+    fn test_task_order_independent_of_completion_order() {
+        let archive: Vec<String> = ["c", "a", "b"].iter().map(|s| s.to_string()).collect();
+        let run = |completion: &[(&str, f64)], order: TaskOrder| {
+            let mut tasks: Vec<TaskResult> = completion
+                .iter()
+                .map(|(id, reward)| {
+                    let mut t = TaskResult::new(id.to_string());
+                    t.reward = *reward;
+                    t
+                })
+                .collect();
+            order.sort(&mut tasks, &archive);
+            tasks.into_iter().map(|t| t.task_id).collect::<Vec<_>>()
+        };
+        let first = [("b", 1.0), ("c", 0.0), ("a", 1.0)];
+        let second = [("a", 1.0), ("b", 1.0), ("c", 0.0)];
+
+        for (order, expected) in [
+            (TaskOrder::Archive, ["c", "a", "b"]),
+            (TaskOrder::Id, ["a", "b", "c"]),
+            (TaskOrder::Reward, ["a", "b", "c"]),
+        ] {
+            assert_eq!(run(&first, order), expected, "{:?}", order);
+            assert_eq!(run(&second, order), expected, "{:?}", order);
+        }
+        assert_eq!(
+            run(&[("z", 0.0), ("c", 0.0)], TaskOrder::Archive),
+            ["c", "z"]
+        );
+        assert_eq!("Reward".parse(), Ok(TaskOrder::Reward));
+        assert!("random".parse::<TaskOrder>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rescore_from_stored_test_results() {
+        let batch = SessionManager::new(60).create_batch(3);
+        let mut res = batch.result.lock().await;
+        let mut partial = TaskResult::new("partial".to_string());
+        partial.test_results = vec![
+            TaskTestResult::new("unit.sh".into(), true, "3 passed in 0.1s".into(), 0),
+            TaskTestResult::new(
+                "e2e.sh".into(),
+                false,
+                "1 failed, 4 passed in 2.0s".into(),
+                1,
+            ),
+        ];
+        let mut scripts = TaskResult::new("scripts".to_string());
+        scripts.test_results = vec![
+            TaskTestResult::new("a.sh".into(), true, String::new(), 0),
+            TaskTestResult::new("b.sh".into(), false, String::new(), 1),
+        ];
+        res.tasks = vec![partial, scripts];
+
+        let unweighted = [("partial".to_string(), 1.0), ("scripts".to_string(), 1.0)];
+        let (rewards, aggregate) =
+            res.rescore(ScoringMode::Binary, RewardAggregation::Mean, &unweighted);
+        assert_eq!(
+            rewards,
+            vec![("partial".into(), 0.0), ("scripts".into(), 0.0)]
+        );
+        assert_eq!(aggregate, 0.0);
+
+        let (rewards, aggregate) = res.rescore(
+            ScoringMode::Fractional,
+            RewardAggregation::Mean,
+            &unweighted,
+        );
+        assert_eq!(
+            rewards,
+            vec![("partial".into(), 0.875), ("scripts".into(), 0.5)]
+        );
+        // The third task never reported and counts as 0.
+        assert!((aggregate - 1.375 / 3.0).abs() < 1e-9);
+
+        let weighted = [
+            ("partial".to_string(), 2.0),
+            ("scripts".to_string(), 1.0),
+            ("silent".to_string(), 0.5),
+        ];
+        let (_, aggregate) = res.rescore(
+            ScoringMode::Fractional,
+            RewardAggregation::Weighted,
+            &weighted,
+        );
+        assert!((aggregate - 2.25 / 3.5).abs() < 1e-9);
+
+        // Stages scale the test score and a failed lint gate zeroes it.
+        res.tasks[0].stages = vec![crate::evaluation::pipeline::StageResult {
+            stage_name: "build".into(),
+            score: 0.25,
+            weight: 1.0,
+            execution_time_ms: 0,
+            metadata: serde_json::json!({}),
+        }];
+        res.tasks[1].lint = Some(TaskTestResult::new("lint".into(), false, String::new(), 1));
+        let (rewards, _) = res.rescore(
+            ScoringMode::Fractional,
+            RewardAggregation::Mean,
+            &unweighted,
+        );
+        assert_eq!(
+            rewards,
+            vec![("partial".into(), 0.875 * 0.25), ("scripts".into(), 0.0)]
+        );
+
+        // Passing stages don't rescue failing tests.
+        res.tasks[0].stages[0].score = 1.0;
+        let (rewards, _) = res.rescore(ScoringMode::Binary, RewardAggregation::Mean, &unweighted);
+        assert_eq!(rewards[0], ("partial".into(), 0.0));
+    }
+
+    #[test]
+    fn test_batch_slot_counts_as_active() {
+        let sessions = SessionManager::new(60);
+        assert!(!sessions.has_active_batch());
+
+        let slot = sessions.try_reserve_batch_slot(1).unwrap();
+        assert!(sessions.has_active_batch());
+        assert_eq!(sessions.active_batch_count(), 1);
+
+        drop(slot);
+        assert!(!sessions.has_active_batch());
+        assert_eq!(sessions.active_batch_count(), 0);
+    }
+
+    #[test]
+    fn test_batch_agent_keeps_code_only_when_retained() {
+        let hashed = BatchAgent::new("print('hi')\n", "python", None, false);
+        assert_eq!(hashed.code_bytes, 12);
+        assert_eq!(hashed.code_sha256.len(), 64);
+        assert!(hashed.code.is_none());
+        assert!(serde_json::to_value(&hashed).unwrap().get("code").is_none());
+
+        let retained = BatchAgent::new("print('hi')\n", "python", None, true);
+        assert_eq!(retained.code_sha256, hashed.code_sha256);
+        assert_eq!(retained.code.as_deref(), Some("print('hi')\n"));
+        assert!(serde_json::to_value(&hashed)
+            .unwrap()
+            .get("archive_sha256")
+            .is_none());
+
+        let multi = BatchAgent::new("print('hi')\n", "python", Some(b"zip-a"), false);
+        let other = BatchAgent::new("print('hi')\n", "python", Some(b"zip-b"), false);
+        assert_eq!(multi.code_sha256, other.code_sha256);
+        assert_ne!(multi.archive_sha256, other.archive_sha256);
+
+        let batch = SessionManager::new(60).create_batch(1);
+        assert!(batch.agent.get().is_none());
+        batch.agent.set(hashed).unwrap();
+        assert!(batch.agent.set(retained).is_err());
+    }
+
+    #[test]
+    fn test_patch_hashes_bounded() {
+        let sessions = SessionManager::new(60);
+        for i in 0..MAX_PATCH_HASHES {
+            sessions.record_patch_hash("a", &format!("t{}", i), "h");
+        }
+        assert_eq!(sessions.record_patch_hash("a", "t0", "h"), Some(false));
+        assert_eq!(sessions.record_patch_hash("a", "new", "h"), None);
+        assert_eq!(sessions.patch_hashes.len(), MAX_PATCH_HASHES);
+    }
+
+    #[test]
+    fn test_batch_capacity() {
+        let sessions = SessionManager::new(60);
+        let first = sessions.try_reserve_batch_slot(0).unwrap();
+        assert!(sessions.try_reserve_batch_slot(1).is_none());
+
+        let _second = sessions.try_reserve_batch_slot(2).unwrap();
+        assert!(sessions.try_reserve_batch_slot(2).is_none());
+        assert_eq!(sessions.active_batch_count(), 2);
+        drop(first);
+        assert!(sessions.try_reserve_batch_slot(2).is_some());
+    }
+
+    #[tokio::test]
+    async fn test_active_batch_progress() {
+        let sessions = SessionManager::new(60);
+        assert!(sessions.active_batch_progress().is_none());
+
+        let batch = sessions.create_batch(4);
+        {
+            let mut result = batch.result.lock().await;
+            result.status = BatchStatus::Running;
+            result.total_tasks = 4;
+            result.completed_tasks = 1;
+            // A busy batch is skipped, not waited on.
+            assert!(sessions.active_batch_progress().is_none());
+        }
+        let (id, progress) = sessions.active_batch_progress().unwrap();
+        assert_eq!(id, batch.id);
+        assert!((progress - 0.25).abs() < f64::EPSILON);
+
+        batch.result.lock().await.status = BatchStatus::Completed;
+        assert!(sessions.active_batch_progress().is_none());
+    }
+
+    #[test]
+    fn test_idempotency_key_maps_to_retained_batch() {
+        let sessions = SessionManager::new(60);
+        let batch = sessions.create_batch(2);
+        sessions.remember_idempotency_key("hk:retry-1", &batch.id);
+
+        let found = sessions.batch_for_idempotency_key("hk:retry-1").unwrap();
+        assert_eq!(found.id, batch.id);
+        assert!(sessions.batch_for_idempotency_key("hk:other").is_none());
+
+        sessions.batches.remove(&batch.id);
+        assert!(sessions.batch_for_idempotency_key("hk:retry-1").is_none());
+        assert!(sessions.idempotency_keys.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_drain_waits_for_active_batch() {
+        let sessions = SessionManager::new(60);
+        let batch = sessions.create_batch(1);
+        let mut cancel_rx = batch.cancel.subscribe();
+
+        sessions.begin_drain();
+        assert!(sessions.is_draining());
+        assert!(
+            !sessions
+                .wait_until_idle(std::time::Duration::from_millis(50))
+                .await
+        );
+
+        sessions.cancel_all();
+        assert!(*cancel_rx.borrow_and_update());
+
+        sessions.mark_completed();
+        assert!(
+            sessions
+                .wait_until_idle(std::time::Duration::from_millis(50))
+                .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_remove_batch_only_when_terminal() {
+        let mgr = SessionManager::new(3600);
+        let done = mgr.create_batch(1);
+        let failed = mgr.create_batch(1);
+        let running = mgr.create_batch(1);
+        done.result.lock().await.status = BatchStatus::Completed;
+        failed.result.lock().await.status = BatchStatus::Failed;
+        running.result.lock().await.status = BatchStatus::Running;
+        mgr.mark_completed();
+        mgr.mark_failed();
+        mgr.remember_idempotency_key("k1", &done.id);
+
+        let mut ids = mgr.terminal_batch_ids(None);
+        ids.sort();
+        let mut expected = vec![done.id.clone(), failed.id.clone()];
+        expected.sort();
+        assert_eq!(ids, expected);
+        assert_eq!(
+            mgr.terminal_batch_ids(Some(&BatchStatus::Completed)),
+            vec![done.id.clone()]
+        );
+
+        assert_eq!(
+            mgr.remove_batch(&running.id).await,
+            BatchRemoval::NotTerminal(BatchStatus::Running)
+        );
+        assert_eq!(
+            mgr.remove_batch(&done.id).await,
+            BatchRemoval::Removed(BatchStatus::Completed)
+        );
+        assert_eq!(mgr.remove_batch(&done.id).await, BatchRemoval::NotFound);
+        assert!(mgr.get(&done.id).is_none());
+        assert!(mgr.batch_for_idempotency_key("k1").is_none());
+
+        assert_eq!(mgr.stats.active.load(Ordering::Relaxed), 1);
+        assert_eq!(mgr.stats.completed.load(Ordering::Relaxed), 1);
+    }
+}
@@ -0,0 +1,68 @@
+use anyhow::{Context, Result};
+use schnorrkel::{ExpansionMode, Keypair, MiniSecretKey};
+
+/// Signing context for executor responses, distinct from the `substrate`
+/// context validators use for request auth.
+pub const RESPONSE_SIGNING_CONTEXT: &[u8] = b"term-executor-response";
+
+/// Header carrying the hex sr25519 signature over the exact response body.
+pub const SIGNATURE_HEADER: &str = "X-Executor-Signature";
+
+/// sr25519 keypair used to sign batch results and submit acks so validators
+/// can check they came from this executor.
+pub struct ResponseSigner {
+    keypair: Keypair,
+}
+
+impl ResponseSigner {
+    /// `seed_hex` is a 32-byte mini secret key, optionally `0x`-prefixed.
+    pub fn from_seed_hex(seed_hex: &str) -> Result<Self> {
+        let seed = hex::decode(seed_hex.trim().trim_start_matches("0x"))
+            .context("signing key is not valid hex")?;
+        let mini = MiniSecretKey::from_bytes(&seed)
+            .map_err(|e| anyhow::anyhow!("invalid signing key: {}", e))?;
+        Ok(Self {
+            keypair: mini.expand_to_keypair(ExpansionMode::Ed25519),
+        })
+    }
+
+    pub fn public_key_hex(&self) -> String {
+        hex::encode(self.keypair.public.to_bytes())
+    }
+
+    pub fn sign(&self, body: &[u8]) -> String {
+        let context = schnorrkel::signing_context(RESPONSE_SIGNING_CONTEXT);
+        hex::encode(self.keypair.sign(context.bytes(body)).to_bytes())
+    }
+}
+
+/// Check `signature_hex` over `body` against a hex sr25519 public key.
+#[cfg(test)]
+pub fn verify(public_key_hex: &str, body: &[u8], signature_hex: &str) -> bool {
+    let Ok(pub_bytes) = hex::decode(public_key_hex.trim_start_matches("0x")) else {
+        return false;
+    };
+    let Ok(sig_bytes) = hex::decode(signature_hex.trim_start_matches("0x")) else {
+        return false;
+    };
+    let (Ok(public_key), Ok(signature)) = (
+        schnorrkel::PublicKey::from_bytes(&pub_bytes),
+        schnorrkel::Signature::from_bytes(&sig_bytes),
+    ) else {
+        return false;
+    };
+    let context = schnorrkel::signing_context(RESPONSE_SIGNING_CONTEXT);
+    public_key.verify(context.bytes(body), &signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejects_bad_seed() {
+        assert!(ResponseSigner::from_seed_hex("zz").is_err());
+        assert!(ResponseSigner::from_seed_hex("abcd").is_err());
+        assert!(ResponseSigner::from_seed_hex(&format!("0x{}", "11".repeat(32))).is_ok());
+    }
+}
@@ -1,5 +1,7 @@
 use anyhow::{Context, Result};
+use async_trait::async_trait;
 use std::path::Path;
+use std::time::Duration;
 use tracing::{debug, info, warn};
 
 use super::types::{DatasetConfig, DatasetEntry, HfRowsResponse, HuggingFaceDataset};
@@ -10,71 +12,318 @@ const DEFAULT_TIMEOUT_SECS: u64 = 30;
 const MAX_PAGE_SIZE: usize = 100;
 /// Minimum number of task directories to consider the snapshot cache valid.
 const SNAPSHOT_MIN_DIRS: usize = 5;
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 4;
+const DEFAULT_BACKOFF_BASE_MS: u64 = 500;
+const DEFAULT_BACKOFF_MAX_MS: u64 = 30_000;
+
+/// Non-success responses from the HuggingFace APIs, split by cause so callers
+/// can tell a missing token apart from a missing dataset or throttling.
+#[derive(Debug, thiserror::Error)]
+pub enum HfApiError {
+    #[error("HuggingFace authentication failed (HTTP {status}): dataset may be gated or private, set HF_TOKEN")]
+    Unauthorized { status: u16 },
+    #[error("HuggingFace dataset or split not found (HTTP 404): {body}")]
+    NotFound { body: String },
+    #[error("HuggingFace rate limit exceeded (HTTP 429)")]
+    RateLimited { retry_after_secs: Option<u64> },
+    #[error("HuggingFace API returned HTTP {status}: {body}")]
+    Http { status: u16, body: String },
+}
+
+impl HfApiError {
+    fn from_status(status: reqwest::StatusCode, retry_after_secs: Option<u64>, body: &str) -> Self {
+        let mut end = body.len().min(500);
+        while !body.is_char_boundary(end) {
+            end -= 1;
+        }
+        let body = body[..end].to_string();
+        match status.as_u16() {
+            401 | 403 => Self::Unauthorized {
+                status: status.as_u16(),
+            },
+            404 => Self::NotFound { body },
+            429 => Self::RateLimited { retry_after_secs },
+            code => Self::Http { status: code, body },
+        }
+    }
+}
+
+/// A multi-page fetch that failed part-way. Carries the entries fetched so far
+/// so callers can keep them or continue with `HuggingFaceClient::resume_dataset`.
+#[derive(Debug, thiserror::Error)]
+#[error("HuggingFace fetch stopped at offset {next_offset} after {} entries: {cause}", .entries.len())]
+pub struct PartialFetchError {
+    pub entries: Vec<DatasetEntry>,
+    pub next_offset: usize,
+    pub total_count: usize,
+    pub cause: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            base_delay: Duration::from_millis(DEFAULT_BACKOFF_BASE_MS),
+            max_delay: Duration::from_millis(DEFAULT_BACKOFF_MAX_MS),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Delay before retrying after the `attempt`-th (1-based) failure, or `None`
+    /// if the error will not go away by retrying (auth, not found).
+    fn delay_for(&self, attempt: u32, err: &anyhow::Error) -> Option<Duration> {
+        let backoff = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+            .min(self.max_delay);
+        match err.downcast_ref::<HfApiError>() {
+            // Capped like the backoff, so a huge `Retry-After` can't stall
+            // the fetch.
+            Some(HfApiError::RateLimited {
+                retry_after_secs: Some(secs),
+            }) => Some(Duration::from_secs(*secs).min(self.max_delay)),
+            Some(HfApiError::RateLimited { .. }) => Some(backoff),
+            Some(HfApiError::Http { status, .. }) if *status >= 500 => Some(backoff),
+            Some(_) => None,
+            // Transport-level failures (timeouts, resets) are worth retrying.
+            None => Some(backoff),
+        }
+    }
+}
+
+/// One page of the dataset viewer `/rows` API. Implemented over HTTP by
+/// `HuggingFaceClient`; tests substitute an in-memory source.
+#[async_trait]
+pub(crate) trait RowsSource: Send + Sync {
+    async fn fetch_rows(
+        &self,
+        dataset_id: &str,
+        split: &str,
+        offset: usize,
+        length: usize,
+    ) -> Result<HfRowsResponse>;
+}
+
+async fn fetch_page_with_retry(
+    source: &dyn RowsSource,
+    policy: &RetryPolicy,
+    dataset_id: &str,
+    split: &str,
+    offset: usize,
+    length: usize,
+) -> Result<HfRowsResponse> {
+    let mut attempt = 0;
+    loop {
+        attempt += 1;
+        let err = match source.fetch_rows(dataset_id, split, offset, length).await {
+            Ok(response) => return Ok(response),
+            Err(e) => e,
+        };
+        let delay = match policy.delay_for(attempt, &err) {
+            Some(d) if attempt < policy.max_attempts => d,
+            _ => return Err(err),
+        };
+        warn!(
+            attempt,
+            offset,
+            delay_ms = delay.as_millis() as u64,
+            error = %err,
+            "HuggingFace page fetch failed, retrying"
+        );
+        tokio::time::sleep(delay).await;
+    }
+}
+
+/// Pages through the dataset starting at `offset`, appending to `entries`
+/// until `config.limit` entries are held, or with `config.instance_ids` set,
+/// until each of those has been found. A page that still fails after retries
+/// yields a `PartialFetchError` once at least one page has succeeded.
+async fn fetch_dataset_from(
+    source: &dyn RowsSource,
+    policy: &RetryPolicy,
+    config: &DatasetConfig,
+    mut offset: usize,
+    mut entries: Vec<DatasetEntry>,
+    mut total_count: usize,
+) -> Result<HuggingFaceDataset> {
+    let wanted: std::collections::HashSet<&str> =
+        config.instance_ids.iter().map(String::as_str).collect();
+    let target = if wanted.is_empty() {
+        config.limit
+    } else {
+        wanted.len()
+    };
+    while entries.len() < target {
+        let page_size = if wanted.is_empty() {
+            MAX_PAGE_SIZE.min(target - entries.len())
+        } else {
+            MAX_PAGE_SIZE
+        };
+
+        let response = match fetch_page_with_retry(
+            source,
+            policy,
+            &config.dataset_id,
+            &config.split,
+            offset,
+            page_size,
+        )
+        .await
+        {
+            Ok(r) => r,
+            Err(e) if entries.is_empty() => return Err(e),
+            Err(e) => {
+                return Err(PartialFetchError {
+                    entries,
+                    next_offset: offset,
+                    total_count,
+                    cause: format!("{:#}", e),
+                }
+                .into())
+            }
+        };
+
+        if let Some(total) = response.num_rows_total {
+            total_count = total;
+        }
+
+        let row_count = response.rows.len();
+        if row_count == 0 {
+            break;
+        }
+
+        for wrapper in response.rows {
+            let row = wrapper.row;
+            if !wanted.is_empty()
+                && (!wanted.contains(row.instance_id.as_str())
+                    || entries.iter().any(|e| e.instance_id == row.instance_id))
+            {
+                continue;
+            }
+            entries.push(row);
+        }
+
+        offset += row_count;
+
+        if row_count < page_size {
+            break;
+        }
+    }
+
+    info!(
+        "Fetched {} entries from {} (total available: {})",
+        entries.len(),
+        config.dataset_id,
+        total_count
+    );
+    if entries.len() < wanted.len() {
+        let missing: Vec<&str> = wanted
+            .iter()
+            .filter(|id| !entries.iter().any(|e| e.instance_id == **id))
+            .copied()
+            .collect();
+        warn!(
+            "Instance ids not found in {}: {}",
+            config.dataset_id,
+            missing.join(", ")
+        );
+    }
+
+    Ok(HuggingFaceDataset {
+        dataset_id: config.dataset_id.clone(),
+        split: config.split.clone(),
+        entries,
+        total_count,
+    })
+}
 
 pub struct HuggingFaceClient {
     client: reqwest::Client,
+    token: Option<String>,
+    retry: RetryPolicy,
 }
 
 impl HuggingFaceClient {
+    /// Creates a client, picking up `HF_TOKEN` from the environment for gated datasets.
     pub fn new() -> Result<Self> {
         let client = reqwest::Client::builder()
             .timeout(std::time::Duration::from_secs(DEFAULT_TIMEOUT_SECS))
             .build()
             .context("Failed to build HTTP client for HuggingFace")?;
-        Ok(Self { client })
+        let token = std::env::var("HF_TOKEN").ok().filter(|s| !s.is_empty());
+        Ok(Self {
+            client,
+            token,
+            retry: RetryPolicy::default(),
+        })
     }
 
-    pub async fn fetch_dataset(&self, config: &DatasetConfig) -> Result<HuggingFaceDataset> {
-        info!(
-            "Fetching HuggingFace dataset: {} (split={}, offset={}, limit={})",
-            config.dataset_id, config.split, config.offset, config.limit
-        );
-
-        let mut all_entries = Vec::new();
-        let mut offset = config.offset;
-        let mut total_count = 0;
-        let remaining = config.limit;
-
-        while all_entries.len() < remaining {
-            let page_size = MAX_PAGE_SIZE.min(remaining - all_entries.len());
-
-            let response = self
-                .fetch_page(&config.dataset_id, &config.split, offset, page_size)
-                .await?;
-
-            if let Some(total) = response.num_rows_total {
-                total_count = total;
-            }
-
-            let row_count = response.rows.len();
-            if row_count == 0 {
-                break;
-            }
+    /// Attempts per page (`HF_FETCH_MAX_ATTEMPTS`), at least one.
+    pub fn with_max_attempts(mut self, attempts: u32) -> Self {
+        self.retry.max_attempts = attempts.max(1);
+        self
+    }
 
-            for wrapper in response.rows {
-                all_entries.push(wrapper.row);
-            }
+    pub fn with_retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
 
-            offset += row_count;
+    pub fn with_token(mut self, token: impl Into<String>) -> Self {
+        self.token = Some(token.into());
+        self
+    }
 
-            if row_count < page_size {
-                break;
-            }
+    fn get(&self, url: &str) -> reqwest::RequestBuilder {
+        let req = self.client.get(url);
+        match self.token {
+            Some(ref token) => req.bearer_auth(token),
+            None => req,
         }
+    }
 
+    pub async fn fetch_dataset(&self, config: &DatasetConfig) -> Result<HuggingFaceDataset> {
         info!(
-            "Fetched {} entries from {} (total available: {})",
-            all_entries.len(),
+            "Fetching HuggingFace dataset: {} (split={}, offset={}, limit={}, instance_ids={})",
             config.dataset_id,
-            total_count
+            config.split,
+            config.offset,
+            config.limit,
+            config.instance_ids.len()
         );
+        fetch_dataset_from(self, &self.retry, config, config.offset, Vec::new(), 0).await
+    }
 
-        Ok(HuggingFaceDataset {
-            dataset_id: config.dataset_id.clone(),
-            split: config.split.clone(),
-            entries: all_entries,
-            total_count,
-        })
+    /// Continue a fetch that failed with `PartialFetchError`, keeping the
+    /// entries it already holds and starting at its `next_offset`.
+    pub async fn resume_dataset(
+        &self,
+        config: &DatasetConfig,
+        partial: PartialFetchError,
+    ) -> Result<HuggingFaceDataset> {
+        info!(
+            "Resuming HuggingFace dataset {} at offset {} ({} entries already fetched)",
+            config.dataset_id,
+            partial.next_offset,
+            partial.entries.len()
+        );
+        fetch_dataset_from(
+            self,
+            &self.retry,
+            config,
+            partial.next_offset,
+            partial.entries,
+            partial.total_count,
+        )
+        .await
     }
 
     pub async fn fetch_entry(
@@ -88,7 +337,8 @@ impl HuggingFaceClient {
             dataset_id, index
         );
 
-        let response = self.fetch_page(dataset_id, split, index, 1).await?;
+        let response =
+            fetch_page_with_retry(self, &self.retry, dataset_id, split, index, 1).await?;
 
         response
             .rows
@@ -151,7 +401,6 @@ impl HuggingFaceClient {
             debug!("Downloading {} -> {}", download_url, local_path.display());
 
             let resp = self
-                .client
                 .get(&download_url)
                 .send()
                 .await
@@ -268,7 +517,6 @@ impl HuggingFaceClient {
                 HF_REPO_BASE, dataset_id, dir_path
             );
             let resp = self
-                .client
                 .get(&url)
                 .send()
                 .await
@@ -300,8 +548,11 @@ impl HuggingFaceClient {
 
         Ok(all_files)
     }
+}
 
-    async fn fetch_page(
+#[async_trait]
+impl RowsSource for HuggingFaceClient {
+    async fn fetch_rows(
         &self,
         dataset_id: &str,
         split: &str,
@@ -316,7 +567,6 @@ impl HuggingFaceClient {
         debug!("Requesting HuggingFace API: {}", url);
 
         let resp = self
-            .client
             .get(&url)
             .send()
             .await
@@ -324,12 +574,13 @@ impl HuggingFaceClient {
 
         let status = resp.status();
         if !status.is_success() {
+            let retry_after_secs = resp
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|v| v.trim().parse().ok());
             let body = resp.text().await.unwrap_or_default();
-            anyhow::bail!(
-                "HuggingFace API returned HTTP {}: {}",
-                status.as_u16(),
-                &body[..body.len().min(500)]
-            );
+            return Err(HfApiError::from_status(status, retry_after_secs, &body).into());
         }
 
         let response: HfRowsResponse = resp
@@ -344,6 +595,211 @@ impl HuggingFaceClient {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::swe_forge::types::HfRowWrapper;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn test_policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(5),
+        }
+    }
+
+    fn page(offset: usize, length: usize, total: usize) -> HfRowsResponse {
+        let rows = (offset..(offset + length).min(total))
+            .map(|i| HfRowWrapper {
+                row: serde_json::from_value(serde_json::json!({
+                    "repo": "org/repo",
+                    "instance_id": format!("task-{}", i),
+                    "base_commit": "abc",
+                    "patch": "",
+                    "problem_statement": "fix it",
+                }))
+                .expect("valid entry"),
+            })
+            .collect();
+        HfRowsResponse {
+            rows,
+            num_rows_total: Some(total),
+        }
+    }
+
+    /// Fails with HTTP 503 for the first `failures` calls, then serves pages.
+    /// Offsets at or beyond `broken_from` fail forever.
+    struct MockSource {
+        failures: usize,
+        broken_from: Option<usize>,
+        total: usize,
+        calls: AtomicUsize,
+    }
+
+    impl MockSource {
+        fn new(failures: usize, broken_from: Option<usize>, total: usize) -> Self {
+            Self {
+                failures,
+                broken_from,
+                total,
+                calls: AtomicUsize::new(0),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl RowsSource for MockSource {
+        async fn fetch_rows(
+            &self,
+            _dataset_id: &str,
+            _split: &str,
+            offset: usize,
+            length: usize,
+        ) -> Result<HfRowsResponse> {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            let broken = self.broken_from.is_some_and(|b| offset >= b);
+            if call < self.failures || broken {
+                return Err(HfApiError::Http {
+                    status: 503,
+                    body: String::new(),
+                }
+                .into());
+            }
+            Ok(page(offset, length, self.total))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_recovers_from_transient_errors() {
+        let source = MockSource::new(2, None, 10);
+        let resp = fetch_page_with_retry(&source, &test_policy(4), "d", "train", 0, 5)
+            .await
+            .expect("should recover");
+        assert_eq!(resp.rows.len(), 5);
+        assert_eq!(source.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_gives_up_after_max_attempts() {
+        let source = MockSource::new(10, None, 10);
+        let result = fetch_page_with_retry(&source, &test_policy(3), "d", "train", 0, 5).await;
+        assert!(result.is_err());
+        assert_eq!(source.calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[test]
+    fn test_retry_delay_policy() {
+        let policy = test_policy(4);
+        let rate_limited = anyhow::Error::from(HfApiError::RateLimited {
+            retry_after_secs: Some(7),
+        });
+        let patient = RetryPolicy {
+            max_delay: Duration::from_secs(30),
+            ..test_policy(4)
+        };
+        assert_eq!(
+            patient.delay_for(1, &rate_limited),
+            Some(Duration::from_secs(7))
+        );
+        // A Retry-After beyond the cap is clamped to it.
+        let hostile = anyhow::Error::from(HfApiError::RateLimited {
+            retry_after_secs: Some(86_400),
+        });
+        assert_eq!(
+            patient.delay_for(1, &hostile),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            policy.delay_for(1, &rate_limited),
+            Some(Duration::from_millis(5))
+        );
+        let unauthorized = anyhow::Error::from(HfApiError::Unauthorized { status: 401 });
+        assert_eq!(policy.delay_for(1, &unauthorized), None);
+        let unavailable = anyhow::Error::from(HfApiError::Http {
+            status: 503,
+            body: String::new(),
+        });
+        assert_eq!(
+            policy.delay_for(2, &unavailable),
+            Some(Duration::from_millis(2))
+        );
+        assert_eq!(
+            policy.delay_for(10, &unavailable),
+            Some(Duration::from_millis(5))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_partial_fetch_keeps_entries_and_resumes() {
+        let config = DatasetConfig {
+            limit: 250,
+            ..DatasetConfig::default()
+        };
+        let policy = test_policy(2);
+
+        let broken = MockSource::new(0, Some(200), 1000);
+        let err = fetch_dataset_from(&broken, &policy, &config, 0, Vec::new(), 0)
+            .await
+            .expect_err("third page should fail");
+        let partial = err
+            .downcast::<PartialFetchError>()
+            .expect("should be a partial fetch");
+        assert_eq!(partial.entries.len(), 200);
+        assert_eq!(partial.next_offset, 200);
+
+        let healthy = MockSource::new(0, None, 1000);
+        let dataset = fetch_dataset_from(
+            &healthy,
+            &policy,
+            &config,
+            partial.next_offset,
+            partial.entries,
+            partial.total_count,
+        )
+        .await
+        .expect("resume should succeed");
+        assert_eq!(dataset.entries.len(), 250);
+        assert_eq!(dataset.entries[249].instance_id, "task-249");
+        assert_eq!(dataset.total_count, 1000);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_by_instance_ids_stops_when_all_found() {
+        let config = DatasetConfig {
+            limit: 1,
+            instance_ids: vec!["task-250".into(), "task-3".into()],
+            ..DatasetConfig::default()
+        };
+        let source = MockSource::new(0, None, 1000);
+        let dataset = fetch_dataset_from(&source, &test_policy(1), &config, 0, Vec::new(), 0)
+            .await
+            .unwrap();
+        let ids: Vec<&str> = dataset
+            .entries
+            .iter()
+            .map(|e| e.instance_id.as_str())
+            .collect();
+        assert_eq!(ids, vec!["task-3", "task-250"]);
+        // Pages of 100: the third one holds task-250, the rest are skipped.
+        assert_eq!(source.calls.load(Ordering::SeqCst), 3);
+
+        let config = DatasetConfig {
+            instance_ids: vec!["task-5".into(), "missing".into()],
+            ..DatasetConfig::default()
+        };
+        let source = MockSource::new(0, None, 150);
+        let dataset = fetch_dataset_from(&source, &test_policy(1), &config, 0, Vec::new(), 0)
+            .await
+            .unwrap();
+        assert_eq!(dataset.entries.len(), 1);
+        assert_eq!(dataset.entries[0].instance_id, "task-5");
+    }
+
+    #[test]
+    fn test_max_attempts_at_least_one() {
+        let client = HuggingFaceClient::new().unwrap().with_max_attempts(0);
+        assert_eq!(client.retry.max_attempts, 1);
+        let client = HuggingFaceClient::new().unwrap().with_max_attempts(7);
+        assert_eq!(client.retry.max_attempts, 7);
+    }
 
     #[test]
     fn test_client_creation() {
@@ -351,6 +807,44 @@ mod tests {
         assert!(client.is_ok());
     }
 
+    #[test]
+    fn test_token_sets_authorization_header() {
+        let client = HuggingFaceClient::new()
+            .expect("client")
+            .with_token("hf_secret");
+        let req = client.get(HF_DATASET_VIEWER_BASE).build().expect("request");
+        assert_eq!(
+            req.headers()
+                .get(reqwest::header::AUTHORIZATION)
+                .and_then(|v| v.to_str().ok()),
+            Some("Bearer hf_secret")
+        );
+    }
+
+    #[test]
+    fn test_http_errors_are_classified() {
+        let err = HfApiError::from_status(reqwest::StatusCode::FORBIDDEN, None, "");
+        assert!(matches!(err, HfApiError::Unauthorized { status: 403 }));
+        let err = HfApiError::from_status(reqwest::StatusCode::NOT_FOUND, None, "missing");
+        assert!(matches!(err, HfApiError::NotFound { .. }));
+        let err = HfApiError::from_status(reqwest::StatusCode::TOO_MANY_REQUESTS, Some(7), "");
+        assert!(matches!(
+            err,
+            HfApiError::RateLimited {
+                retry_after_secs: Some(7)
+            }
+        ));
+        let err = HfApiError::from_status(reqwest::StatusCode::BAD_GATEWAY, None, "oops");
+        assert!(err.to_string().contains("HTTP 502"));
+
+        // Byte 500 falls inside a multibyte char.
+        let body = format!("{}{}", "a".repeat(499), "€".repeat(10));
+        match HfApiError::from_status(reqwest::StatusCode::NOT_FOUND, None, &body) {
+            HfApiError::NotFound { body } => assert_eq!(body.len(), 499),
+            other => panic!("{:?}", other),
+        }
+    }
+
     #[test]
     fn test_hf_rows_response_deserialize() {
         let json = r#"{
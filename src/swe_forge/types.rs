@@ -58,6 +58,10 @@ pub struct DatasetConfig {
     pub limit: usize,
     #[serde(default)]
     pub offset: usize,
+    /// When set, only entries with these instance ids are kept (and `limit`
+    /// is ignored); paging stops once all of them are found.
+    #[serde(default)]
+    pub instance_ids: Vec<String>,
 }
 
 fn default_split() -> String {
@@ -75,6 +79,7 @@ impl Default for DatasetConfig {
             split: default_split(),
             limit: default_limit(),
             offset: 0,
+            instance_ids: Vec::new(),
         }
     }
 }
@@ -0,0 +1,197 @@
+use std::path::Path;
+
+use crate::session::TaskArtifact;
+
+/// Most files collected per task, whatever the patterns match.
+const MAX_ARTIFACTS: usize = 16;
+/// Directory entries visited while expanding wildcard patterns.
+const MAX_WALK_ENTRIES: usize = 50_000;
+/// Directories never searched for artifacts.
+const SKIPPED_DIRS: &[&str] = &[".git", "node_modules", "target", ".venv", "venv"];
+
+/// Patterns must stay inside the repo: no absolute paths and no `..`.
+pub fn valid_pattern(pattern: &str) -> bool {
+    !pattern.is_empty()
+        && !pattern.starts_with('/')
+        && !pattern.split('/').any(|segment| segment == "..")
+}
+
+/// Match a repo-relative path against a pattern where `*` and `?` stay within
+/// one path segment and a `**` segment spans any number of them.
+pub fn glob_match(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split('/').filter(|s| !s.is_empty()).collect();
+    let path: Vec<&str> = path.split('/').collect();
+    match_segments(&pattern, &path)
+}
+
+/// Iterative, so neither repeated `**` segments nor repeated `*`s cost more
+/// than pattern length times path length.
+fn match_segments(pattern: &[&str], path: &[&str]) -> bool {
+    // `matched[j]`: the pattern segments seen so far match `path[..j]`.
+    let mut matched = vec![false; path.len() + 1];
+    matched[0] = true;
+    for &segment in pattern {
+        if segment == "**" {
+            for j in 1..=path.len() {
+                matched[j] |= matched[j - 1];
+            }
+        } else {
+            for j in (1..=path.len()).rev() {
+                matched[j] = matched[j - 1] && crate::executor::glob_match(segment, path[j - 1]);
+            }
+            matched[0] = false;
+        }
+    }
+    matched[path.len()]
+}
+
+/// Read the files under `repo_dir` matching `patterns` (as declared in
+/// `workspace.yaml` `artifacts:`). Each is cut to `max_bytes` and the total
+/// to `max_total_bytes`, and no more than that is read. Symlinks are never
+/// followed, at any path component, so a task can't point an artifact
+/// outside its repo. Results are sorted by path.
+pub fn collect_artifacts(
+    repo_dir: &Path,
+    patterns: &[String],
+    max_bytes: usize,
+    max_total_bytes: usize,
+) -> Vec<TaskArtifact> {
+    let patterns: Vec<&str> = patterns
+        .iter()
+        .map(String::as_str)
+        .filter(|p| valid_pattern(p))
+        .collect();
+    if patterns.is_empty() {
+        return Vec::new();
+    }
+
+    let mut paths = Vec::new();
+    let mut visited = 0;
+    walk(repo_dir, "", &patterns, &mut paths, &mut visited);
+    paths.sort();
+    paths.dedup();
+    paths.truncate(MAX_ARTIFACTS);
+
+    let mut remaining = max_total_bytes;
+    let mut artifacts = Vec::new();
+    for (rel, size) in paths {
+        let limit = max_bytes.min(remaining);
+        // One byte past the limit tells whether the cut lands mid-character.
+        let Ok(Some(data)) =
+            crate::sandbox::read_beneath_no_follow(repo_dir, &rel, limit as u64 + 1)
+        else {
+            continue;
+        };
+        let truncated = data.len() > limit;
+        let mut end = data.len().min(limit);
+        while end > 0 && end < data.len() && (data[end] & 0xC0) == 0x80 {
+            end -= 1;
+        }
+        remaining -= end;
+        artifacts.push(TaskArtifact {
+            name: rel,
+            size_bytes: size.max(data.len() as u64),
+            truncated,
+            content: String::from_utf8_lossy(&data[..end]).into_owned(),
+        });
+        if remaining == 0 {
+            break;
+        }
+    }
+    artifacts
+}
+
+/// Collect `(path, size)` of matching regular files.
+fn walk(
+    dir: &Path,
+    prefix: &str,
+    patterns: &[&str],
+    out: &mut Vec<(String, u64)>,
+    visited: &mut usize,
+) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        *visited += 1;
+        if *visited > MAX_WALK_ENTRIES || out.len() >= MAX_ARTIFACTS * 4 {
+            return;
+        }
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let rel = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+        if file_type.is_dir() {
+            if !SKIPPED_DIRS.contains(&name.as_str()) {
+                walk(&entry.path(), &rel, patterns, out, visited);
+            }
+        } else if file_type.is_file() && patterns.iter().any(|p| glob_match(p, &rel)) {
+            let size = entry.metadata().map_or(0, |m| m.len());
+            out.push((rel, size));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("coverage.xml", "coverage.xml"));
+        assert!(!glob_match("coverage.xml", "build/coverage.xml"));
+        assert!(glob_match("**/coverage.xml", "build/coverage.xml"));
+        assert!(glob_match("**/coverage.xml", "coverage.xml"));
+        assert!(glob_match("reports/*.xml", "reports/junit.xml"));
+        assert!(!glob_match("reports/*.xml", "reports/sub/junit.xml"));
+        assert!(glob_match("cov?rage/lcov.info", "coverage/lcov.info"));
+        assert!(glob_match("**/a/**/b", "x/a/y/z/b"));
+        assert!(glob_match("*.t*r.gz", "out.tar.gz"));
+        assert!(!glob_match("a*b", "aab/c"));
+        // Would take exponential time with naive backtracking.
+        let deep = vec!["d"; 40].join("/");
+        assert!(!glob_match(
+            &format!("{}y", "**/".repeat(20)),
+            &format!("{}/x", deep)
+        ));
+        assert!(!glob_match(
+            &format!("{}b", "*a".repeat(20)),
+            &"a".repeat(60)
+        ));
+        assert!(!valid_pattern("../secret"));
+        assert!(!valid_pattern("/etc/passwd"));
+    }
+
+    #[test]
+    fn test_collect_artifacts_caps_and_skips_symlinks() {
+        let tmp = tempfile::tempdir().unwrap();
+        let repo = &tmp.path().join("repo");
+        std::fs::create_dir_all(repo.join("reports")).unwrap();
+        std::fs::write(repo.join("coverage.xml"), "<coverage line-rate=\"0.8\"/>").unwrap();
+        std::fs::write(repo.join("reports/junit.xml"), "x".repeat(100)).unwrap();
+        std::fs::write(tmp.path().join("outside.txt"), "secret").unwrap();
+        std::os::unix::fs::symlink("/etc/passwd", repo.join("reports/link.xml")).unwrap();
+
+        let patterns = vec![
+            "coverage.xml".to_string(),
+            "reports/*.xml".to_string(),
+            "../outside.txt".to_string(),
+        ];
+        let artifacts = collect_artifacts(repo, &patterns, 64, 1024);
+        let names: Vec<&str> = artifacts.iter().map(|a| a.name.as_str()).collect();
+        assert_eq!(names, vec!["coverage.xml", "reports/junit.xml"]);
+        assert!(!artifacts[0].truncated);
+        assert!(artifacts[1].truncated);
+        assert_eq!(artifacts[1].content.len(), 64);
+        assert_eq!(artifacts[1].size_bytes, 100);
+
+        let artifacts = collect_artifacts(repo, &patterns, 64, 40);
+        assert_eq!(artifacts.len(), 2);
+        assert_eq!(artifacts[1].content.len(), 40 - artifacts[0].content.len());
+    }
+}
@@ -1,7 +1,11 @@
+pub mod artifacts;
+pub mod checks;
 #[allow(dead_code)]
 pub mod config;
 #[allow(dead_code)]
 pub mod registry;
+pub mod repo_config;
+pub mod test_counts;
 #[allow(dead_code)]
 pub mod types;
 
@@ -14,7 +18,62 @@ pub use types::SweForgeTaskFields;
 
 const MAX_ARCHIVE_SIZE: usize = 500 * 1024 * 1024;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// How many directory levels below the extraction dir are searched for the
+/// archive root by default.
+pub const DEFAULT_ARCHIVE_ROOT_DEPTH: usize = 2;
+
+/// Upper bound on directories inspected while searching for the archive root.
+const MAX_ROOT_SEARCH_DIRS: usize = 1000;
+
+/// Interpreter argv prefix per test script extension. Scripts with any other
+/// extension run under bash.
+pub const TEST_INTERPRETERS: &[(&str, &[&str])] = &[
+    (".sh", &["bash"]),
+    (".py", &["python3"]),
+    (".js", &["node"]),
+    (".ts", &["npx", "tsx"]),
+    (".rb", &["ruby"]),
+    (".pl", &["perl"]),
+];
+
+/// Argv prefix used to run the test script `name`.
+pub fn test_interpreter(name: &str) -> &'static [&'static str] {
+    TEST_INTERPRETERS
+        .iter()
+        .find(|(ext, _)| name.ends_with(ext))
+        .map(|(_, argv)| *argv)
+        .unwrap_or(&["bash"])
+}
+
+/// Whether a file under `tests/` is a script to execute rather than a source
+/// file to copy: all `.sh` files, plus `run_tests.<ext>` for any extension in
+/// `TEST_INTERPRETERS`.
+fn is_test_script(fname: &str) -> bool {
+    if fname.ends_with(".sh") {
+        return true;
+    }
+    let base = Path::new(fname)
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_default();
+    TEST_INTERPRETERS
+        .iter()
+        .any(|(ext, _)| base == format!("run_tests{}", ext))
+}
+
+/// Optional manifest in `agent_code/` naming the file to execute, either as a
+/// bare path or as `entrypoint: <path>`.
+pub const AGENT_ENTRYPOINT_FILE: &str = "entrypoint";
+
+/// Optional manifest in `agent_code/` declaring the agent language, either
+/// bare (`go`) or as `language: go`. Takes precedence over extension sniffing.
+pub const AGENT_LANGUAGE_FILE: &str = "language";
+
+/// Agent language used when nothing in the archive says otherwise and no
+/// `DEFAULT_AGENT_LANGUAGE` is configured.
+pub const DEFAULT_AGENT_LANGUAGE: &str = "python";
+
+#[derive(Debug, Clone, Serialize, Deserialize, schemars::JsonSchema)]
 pub struct WorkspaceConfig {
     pub repo: String,
     #[serde(default)]
@@ -25,6 +84,40 @@ pub struct WorkspaceConfig {
     pub install: Option<Vec<String>>,
     #[serde(default)]
     pub language: Option<String>,
+    /// Explicit test command (e.g. `make test`), used instead of test scripts.
+    #[serde(default)]
+    pub test_command: Option<String>,
+    /// Test script exit codes that count as passing. Defaults to `[0]`.
+    #[serde(default)]
+    pub pass_exit_codes: Option<Vec<i32>>,
+    /// Relative weight under `REWARD_AGGREGATION=weighted`. Defaults to 1.
+    #[serde(default)]
+    pub weight: Option<f64>,
+    /// Per-task overrides of `TEST_TIMEOUT_SECS` / `AGENT_TIMEOUT_SECS`,
+    /// capped at `MAX_TASK_TIMEOUT_SECS`.
+    #[serde(default)]
+    pub test_timeout_secs: Option<u64>,
+    #[serde(default)]
+    pub agent_timeout_secs: Option<u64>,
+    /// Files to return after the test stage (e.g. `coverage.xml`,
+    /// `**/junit.xml`), matched relative to the repo root.
+    #[serde(default)]
+    pub artifacts: Vec<String>,
+    /// Separately weighted steps run after the tests (e.g. `compile`,
+    /// `lint`), in the order declared. When present, the task's reward is
+    /// their weighted score instead of a flat pass/fail.
+    #[serde(default, with = "ordered_stages")]
+    #[schemars(with = "std::collections::BTreeMap<String, TaskStage>")]
+    pub stages: Vec<(String, TaskStage)>,
+    /// Shell command run after the agent and before the tests (e.g.
+    /// `flake8`, `gofmt -l .`). A non-passing exit fails the task and zeroes
+    /// its reward even when every test passes.
+    #[serde(default)]
+    pub lint: Option<String>,
+    /// Run the test scripts one at a time even when `TEST_SCRIPT_CONCURRENCY`
+    /// allows more, for scripts that depend on each other's order.
+    #[serde(default)]
+    pub sequential_tests: bool,
     // SWE-bench / swe-forge fields
     #[serde(default)]
     pub fail_to_pass: Option<Vec<String>>,
@@ -46,17 +139,207 @@ pub struct WorkspaceConfig {
     pub runtime_install: Option<String>,
 }
 
-#[derive(Debug, Clone)]
+impl WorkspaceConfig {
+    pub fn pass_exit_codes(&self) -> &[i32] {
+        self.pass_exit_codes.as_deref().unwrap_or(&[0])
+    }
+
+    /// Generate the runtime install command from install_config version
+    /// fields. Stored separately so the executor can run it without
+    /// filter_install_command, and never read from workspace.yaml.
+    pub fn refresh_runtime_install(&mut self) {
+        if let Some(ref ic) = self.install_config {
+            let runtime_cmd = runtime_install_command(ic);
+            if !runtime_cmd.is_empty() {
+                self.runtime_install = Some(runtime_cmd);
+            }
+        }
+    }
+
+    /// Test timeout for this task: the override clamped to `max`, or
+    /// `default` when unset or 0.
+    pub fn test_timeout_secs(&self, default: u64, max: u64) -> u64 {
+        timeout_override(self.test_timeout_secs, default, max)
+    }
+
+    /// Agent timeout for this task, resolved like `test_timeout_secs`.
+    pub fn agent_timeout_secs(&self, default: u64, max: u64) -> u64 {
+        timeout_override(self.agent_timeout_secs, default, max)
+    }
+
+    /// Negative or non-finite weights are treated as 0.
+    pub fn weight(&self) -> f64 {
+        match self.weight {
+            Some(w) if w.is_finite() => w.max(0.0),
+            Some(_) => 0.0,
+            None => 1.0,
+        }
+    }
+}
+
+/// One entry of `stages:` in `workspace.yaml`, e.g.
+/// `compile: {command: "cargo build", weight: 0.3}`. The stage scores 1 when
+/// `command` exits with a passing code and 0 otherwise.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, schemars::JsonSchema)]
+pub struct TaskStage {
+    pub command: String,
+    /// Relative weight in `[0, 1]`. Defaults to 1.
+    #[serde(default = "default_stage_weight")]
+    pub weight: f64,
+}
+
+fn default_stage_weight() -> f64 {
+    1.0
+}
+
+/// `stages:` is a YAML map whose order is the run order, so it is read into
+/// a list rather than a (sorted or hashed) map.
+mod ordered_stages {
+    use super::TaskStage;
+    use serde::de::{MapAccess, Visitor};
+    use serde::ser::SerializeMap;
+    use serde::{Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        stages: &[(String, TaskStage)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(stages.len()))?;
+        for (name, stage) in stages {
+            map.serialize_entry(name, stage)?;
+        }
+        map.end()
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<(String, TaskStage)>, D::Error> {
+        struct StagesVisitor;
+
+        impl<'de> Visitor<'de> for StagesVisitor {
+            type Value = Vec<(String, TaskStage)>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("a map of stage names to {command, weight}")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut stages = Vec::new();
+                while let Some((name, stage)) = map.next_entry::<String, TaskStage>()? {
+                    if stages.iter().any(|(n, _)| *n == name) {
+                        return Err(serde::de::Error::custom(format!(
+                            "duplicate stage '{}'",
+                            name
+                        )));
+                    }
+                    stages.push((name, stage));
+                }
+                Ok(stages)
+            }
+        }
+
+        deserializer.deserialize_map(StagesVisitor)
+    }
+}
+
+fn timeout_override(value: Option<u64>, default: u64, max: u64) -> u64 {
+    match value {
+        Some(secs) if secs > 0 => secs.min(max),
+        _ => default,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SweForgeTask {
     pub id: String,
     pub workspace: WorkspaceConfig,
     pub prompt: String,
     pub test_scripts: Vec<(String, String)>,
     pub test_source_files: Vec<(String, String)>,
+    /// Raw `checks.txt` lines, kept so they can be vetted before running.
+    #[serde(default)]
+    pub checks: Vec<String>,
     #[allow(dead_code)]
     pub swe_forge_fields: Option<SweForgeTaskFields>,
 }
 
+/// What to do when a batch contains the same task twice, either by id or by
+/// repo, base commit and prompt.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DuplicateTaskPolicy {
+    /// Fail the submission.
+    Reject,
+    /// Keep the first occurrence and drop the rest.
+    #[default]
+    Coalesce,
+}
+
+impl std::str::FromStr for DuplicateTaskPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "reject" => Ok(DuplicateTaskPolicy::Reject),
+            "coalesce" => Ok(DuplicateTaskPolicy::Coalesce),
+            other => Err(format!("unknown duplicate task policy '{}'", other)),
+        }
+    }
+}
+
+/// Apply `policy` to duplicate tasks and return the ids of the ones dropped.
+pub fn dedupe_tasks(
+    tasks: &mut Vec<SweForgeTask>,
+    policy: DuplicateTaskPolicy,
+) -> Result<Vec<String>> {
+    let mut seen_ids = std::collections::HashSet::new();
+    let mut seen_content = std::collections::HashSet::new();
+    let keep: Vec<bool> = tasks
+        .iter()
+        .map(|t| {
+            let new_id = seen_ids.insert(t.id.as_str());
+            let new_content = seen_content.insert((
+                t.workspace.repo.as_str(),
+                t.workspace.base_commit.as_deref(),
+                t.prompt.as_str(),
+            ));
+            new_id && new_content
+        })
+        .collect();
+    let duplicates: Vec<String> = tasks
+        .iter()
+        .zip(&keep)
+        .filter(|(_, keep)| !**keep)
+        .map(|(t, _)| t.id.clone())
+        .collect();
+
+    if !duplicates.is_empty() && policy == DuplicateTaskPolicy::Reject {
+        anyhow::bail!("Duplicate tasks in batch: {}", duplicates.join(", "));
+    }
+    let mut keep = keep.into_iter();
+    tasks.retain(|_| keep.next().unwrap_or(true));
+    Ok(duplicates)
+}
+
+/// Task ids requested for a run that the archive doesn't contain.
+#[derive(Debug, thiserror::Error)]
+#[error("tasks not found in archive: {}", .0.join(", "))]
+pub struct UnknownTasks(pub Vec<String>);
+
+/// Keep only the tasks whose id is in `ids`, in archive order. Fails with
+/// `UnknownTasks` when an id matches no task.
+pub fn select_tasks(tasks: &mut Vec<SweForgeTask>, ids: &[String]) -> Result<()> {
+    let missing: Vec<String> = ids
+        .iter()
+        .filter(|id| !tasks.iter().any(|t| &t.id == *id))
+        .cloned()
+        .collect();
+    if !missing.is_empty() {
+        return Err(UnknownTasks(missing).into());
+    }
+    tasks.retain(|t| ids.contains(&t.id));
+    Ok(())
+}
+
 #[derive(Debug)]
 pub struct ExtractedArchive {
     pub tasks: Vec<SweForgeTask>,
@@ -66,16 +349,76 @@ pub struct ExtractedArchive {
 }
 
 pub fn extract_archive_bytes(data: &[u8], dest: &Path) -> Result<()> {
-    if let Ok(mut archive) = zip::ZipArchive::new(std::io::Cursor::new(data)) {
+    extract_archive_from(std::io::Cursor::new(data), dest)
+}
+
+pub fn extract_archive_file(path: &Path, dest: &Path) -> Result<()> {
+    let file = std::fs::File::open(path).context("Failed to open archive file")?;
+    extract_archive_from(std::io::BufReader::new(file), dest)
+}
+
+fn extract_archive_from<R: std::io::Read + std::io::Seek>(
+    mut reader: R,
+    dest: &Path,
+) -> Result<()> {
+    if let Ok(mut archive) = zip::ZipArchive::new(&mut reader) {
         debug!("Extracting ZIP archive ({} entries)", archive.len());
+        // `extract` recreates symlinks, so vet them all before anything is
+        // written.
+        let mut links = Vec::new();
+        for i in 0..archive.len() {
+            let mut file = archive.by_index(i).context("Failed to read ZIP entry")?;
+            if !file.is_symlink() {
+                continue;
+            }
+            let name = PathBuf::from(file.name());
+            let mut target = String::new();
+            std::io::Read::read_to_string(&mut file, &mut target)
+                .context("Failed to read ZIP symlink target")?;
+            links.push(ArchiveLink {
+                name,
+                target: PathBuf::from(target),
+                hard: false,
+            });
+        }
+        check_links(&links)?;
         archive
             .extract(dest)
             .context("Failed to extract ZIP archive")?;
         return Ok(());
     }
 
-    let gz = flate2::read::GzDecoder::new(data);
+    // A first pass collects every link, so each can be checked against
+    // links that come later in the archive too.
+    reader.seek(std::io::SeekFrom::Start(0))?;
+    let mut links = Vec::new();
+    let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(&mut reader));
+    for entry in archive
+        .entries()
+        .context("Failed to extract tar.gz archive")?
+    {
+        let entry = entry.context("Failed to extract tar.gz archive")?;
+        let kind = entry.header().entry_type();
+        if kind.is_symlink() || kind.is_hard_link() {
+            links.push(ArchiveLink {
+                name: entry.path().context("Invalid tar entry path")?.into_owned(),
+                target: entry
+                    .link_name()
+                    .context("Invalid tar link target")?
+                    .context("tar link entry without a target")?
+                    .into_owned(),
+                hard: kind.is_hard_link(),
+            });
+        }
+    }
+    check_links(&links)?;
+
+    reader.seek(std::io::SeekFrom::Start(0))?;
+    let gz = flate2::read::GzDecoder::new(reader);
     let mut archive = tar::Archive::new(gz);
+    archive.set_unpack_xattrs(false);
+    archive.set_preserve_ownerships(false);
+    archive.set_preserve_permissions(false);
     archive
         .unpack(dest)
         .context("Failed to extract tar.gz archive")?;
@@ -84,8 +427,100 @@ pub fn extract_archive_bytes(data: &[u8], dest: &Path) -> Result<()> {
     Ok(())
 }
 
+/// A symlink or hard-link entry of an archive.
+struct ArchiveLink {
+    name: PathBuf,
+    target: PathBuf,
+    hard: bool,
+}
+
+/// `check_link_target` for every link of an archive, each against the full
+/// set of symlinks the archive creates.
+fn check_links(links: &[ArchiveLink]) -> Result<()> {
+    let symlinks: std::collections::HashSet<PathBuf> = links
+        .iter()
+        .filter(|l| !l.hard)
+        .map(|l| lexical_path(&l.name))
+        .collect();
+    for link in links {
+        check_link_target(&link.name, &link.target, link.hard, &symlinks)?;
+    }
+    Ok(())
+}
+
+/// `path` without `.` components, for comparing archive entry names.
+fn lexical_path(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| !matches!(c, std::path::Component::CurDir))
+        .collect()
+}
+
+/// Fail unless a link entry at `name` pointing at `target` stays inside the
+/// extraction directory. Symlink targets resolve against the link's own
+/// directory, hard-link targets against the archive root; absolute targets
+/// are always rejected. Checked lexically, before anything is written, so a
+/// later entry can't write through the link to somewhere else. Lexical
+/// resolution is only sound if no directory on the way is itself a link, so
+/// a path through any of the archive's `symlinks` is rejected as well.
+fn check_link_target(
+    name: &Path,
+    target: &Path,
+    hard_link: bool,
+    symlinks: &std::collections::HashSet<PathBuf>,
+) -> Result<()> {
+    use std::path::Component;
+
+    let base = if hard_link {
+        Path::new("")
+    } else {
+        name.parent().unwrap_or(Path::new(""))
+    };
+    let components: Vec<Component> = base.components().chain(target.components()).collect();
+    let mut resolved = PathBuf::new();
+    let mut inside = true;
+    for (i, c) in components.iter().enumerate() {
+        match c {
+            Component::Normal(part) => {
+                resolved.push(part);
+                // The final component may be a link: it resolves on its own.
+                if i + 1 < components.len() && symlinks.contains(&resolved) {
+                    anyhow::bail!(
+                        "archive link {} -> {} passes through link {}",
+                        name.display(),
+                        target.display(),
+                        resolved.display()
+                    );
+                }
+            }
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !resolved.pop() {
+                    inside = false;
+                    break;
+                }
+            }
+            Component::RootDir | Component::Prefix(_) => {
+                inside = false;
+                break;
+            }
+        }
+    }
+    if !inside {
+        anyhow::bail!(
+            "archive link {} -> {} points outside the archive",
+            name.display(),
+            target.display()
+        );
+    }
+    Ok(())
+}
+
 /// Extract only the agent code from an archive (no tasks/ required).
-pub async fn extract_agent_only(data: &[u8], dest: &Path) -> Result<(String, String)> {
+pub async fn extract_agent_only(
+    data: &[u8],
+    dest: &Path,
+    default_language: &str,
+) -> Result<(String, String)> {
     if data.len() > MAX_ARCHIVE_SIZE {
         anyhow::bail!(
             "Archive too large: {} bytes (max {})",
@@ -105,11 +540,17 @@ pub async fn extract_agent_only(data: &[u8], dest: &Path) -> Result<(String, Str
 
     let root = find_agent_root(dest)?;
     let agent_code = load_agent_code(&root)?;
-    let agent_language = detect_agent_language(&root);
+    let agent_language = detect_agent_language(&root, default_language)?;
     Ok((agent_code, agent_language))
 }
 
-pub async fn extract_uploaded_archive(data: &[u8], dest: &Path) -> Result<ExtractedArchive> {
+pub async fn extract_uploaded_archive(
+    data: &[u8],
+    dest: &Path,
+    root_depth: usize,
+    max_tasks: usize,
+    default_language: &str,
+) -> Result<ExtractedArchive> {
     if data.len() > MAX_ARCHIVE_SIZE {
         anyhow::bail!(
             "Archive too large: {} bytes (max {})",
@@ -130,11 +571,83 @@ pub async fn extract_uploaded_archive(data: &[u8], dest: &Path) -> Result<Extrac
         .await
         .context("Extract task panicked")??;
 
-    let root = find_archive_root(dest)?;
+    load_extracted_archive(dest, root_depth, max_tasks, default_language)
+}
+
+/// Like `extract_uploaded_archive`, for an archive already spooled to disk.
+pub async fn extract_uploaded_archive_file(
+    path: &Path,
+    dest: &Path,
+    root_depth: usize,
+    max_tasks: usize,
+    default_language: &str,
+) -> Result<ExtractedArchive> {
+    let size = tokio::fs::metadata(path)
+        .await
+        .context("Failed to stat archive file")?
+        .len();
+    info!(
+        "Extracting {} bytes archive from {}...",
+        size,
+        path.display()
+    );
+
+    tokio::fs::create_dir_all(dest)
+        .await
+        .context("Failed to create extraction directory")?;
+
+    let path_owned = path.to_path_buf();
+    let dest_owned = dest.to_path_buf();
+    tokio::task::spawn_blocking(move || extract_archive_file(&path_owned, &dest_owned))
+        .await
+        .context("Extract task panicked")??;
+
+    load_extracted_archive(dest, root_depth, max_tasks, default_language)
+}
+
+/// Combine the archives of one submission into a single batch. Tasks keep
+/// upload order and duplicates are left for `dedupe_tasks`. Every archive
+/// must carry the same agent, otherwise this fails with
+/// `AgentCodeError::Conflict`; more than `max_tasks` tasks in total (0 = no
+/// limit) fails with `TooManyTasks`.
+pub fn merge_archives(
+    archives: Vec<ExtractedArchive>,
+    max_tasks: usize,
+) -> Result<ExtractedArchive> {
+    let mut archives = archives.into_iter();
+    let mut merged = archives.next().context("No archives to merge")?;
+    for (index, archive) in archives.enumerate() {
+        if archive.agent_code != merged.agent_code
+            || archive.agent_language != merged.agent_language
+            || archive.agent_archive != merged.agent_archive
+        {
+            return Err(AgentCodeError::Conflict(index + 1).into());
+        }
+        merged.tasks.extend(archive.tasks);
+    }
+    if max_tasks > 0 && merged.tasks.len() > max_tasks {
+        return Err(TooManyTasks {
+            found: merged.tasks.len(),
+            max: max_tasks,
+        }
+        .into());
+    }
+    Ok(merged)
+}
+
+fn load_extracted_archive(
+    dest: &Path,
+    root_depth: usize,
+    max_tasks: usize,
+    default_language: &str,
+) -> Result<ExtractedArchive> {
+    let root = find_archive_root(dest, root_depth)?;
 
     let agent_code = load_agent_code(&root)?;
-    let agent_language = detect_agent_language(&root);
-    let tasks = load_tasks(&root)?;
+    let agent_language = detect_agent_language(&root, default_language)?;
+    let tasks = load_tasks(&root, max_tasks)?;
+    // Multi-file agents run from their extracted tree, so keep it.
+    let has_entrypoint = read_agent_entrypoint(&root.join("agent_code"))?.is_some();
 
     info!(
         "Extracted {} tasks, agent language: {}",
@@ -146,10 +659,62 @@ pub async fn extract_uploaded_archive(data: &[u8], dest: &Path) -> Result<Extrac
         tasks,
         agent_code,
         agent_language,
-        agent_archive: None,
+        agent_archive: if has_entrypoint {
+            Some(pack_agent_code(&root)?)
+        } else {
+            None
+        },
     })
 }
 
+/// Zip `agent_code/` under `root` on its own, for running a multi-file agent.
+/// The rest of the upload, `tasks/` with its hidden tests in particular, is
+/// left out so the agent can't read it. Entries are sorted and carry a fixed
+/// timestamp, so one agent tree always packs to the same bytes. Symlinks are
+/// skipped.
+fn pack_agent_code(root: &Path) -> Result<Vec<u8>> {
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated)
+        .last_modified_time(zip::DateTime::default());
+    let mut zip = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    pack_dir(&mut zip, root, "agent_code", options)?;
+    Ok(zip
+        .finish()
+        .context("Failed to pack agent_code")?
+        .into_inner())
+}
+
+fn pack_dir(
+    zip: &mut zip::ZipWriter<std::io::Cursor<Vec<u8>>>,
+    root: &Path,
+    rel: &str,
+    options: zip::write::SimpleFileOptions,
+) -> Result<()> {
+    zip.add_directory(format!("{}/", rel), options)?;
+    let mut entries: Vec<std::fs::DirEntry> = std::fs::read_dir(root.join(rel))
+        .with_context(|| format!("Failed to read {}", rel))?
+        .collect::<std::io::Result<_>>()?;
+    entries.sort_by_key(|e| e.file_name());
+    for entry in entries {
+        let name = format!("{}/{}", rel, entry.file_name().to_string_lossy());
+        let meta = std::fs::symlink_metadata(entry.path())?;
+        if meta.is_dir() {
+            pack_dir(zip, root, &name, options)?;
+        } else if meta.is_file() {
+            #[cfg(unix)]
+            let options = {
+                use std::os::unix::fs::PermissionsExt;
+                options.unix_permissions(meta.permissions().mode() & 0o777)
+            };
+            zip.start_file(name.as_str(), options)?;
+            let data =
+                std::fs::read(entry.path()).with_context(|| format!("Failed to read {}", name))?;
+            std::io::Write::write_all(zip, &data)?;
+        }
+    }
+    Ok(())
+}
+
 fn find_agent_root(base: &Path) -> Result<PathBuf> {
     if base.join("agent_code").exists() {
         return Ok(base.to_path_buf());
@@ -164,41 +729,308 @@ fn find_agent_root(base: &Path) -> Result<PathBuf> {
     anyhow::bail!("No agent_code/ found in archive at {}", base.display())
 }
 
-fn find_archive_root(base: &Path) -> Result<PathBuf> {
-    if base.join("tasks").exists() || base.join("agent_code").exists() {
-        return Ok(base.to_path_buf());
-    }
+/// Breadth-first search for the shallowest directory containing `tasks/` or
+/// `agent_code/`, at most `max_depth` levels below `base`. Symlinks are not
+/// followed and the walk gives up after `MAX_ROOT_SEARCH_DIRS` directories.
+fn find_archive_root(base: &Path, max_depth: usize) -> Result<PathBuf> {
+    let mut queue = std::collections::VecDeque::from([(base.to_path_buf(), 0usize)]);
+    let mut visited = 0usize;
 
-    for entry in std::fs::read_dir(base).context("Failed to read extracted directory")? {
-        let entry = entry?;
-        let path = entry.path();
-        if path.is_dir() && (path.join("tasks").exists() || path.join("agent_code").exists()) {
-            return Ok(path);
+    while let Some((dir, depth)) = queue.pop_front() {
+        if dir.join("tasks").is_dir() || dir.join("agent_code").is_dir() {
+            return Ok(dir);
+        }
+        visited += 1;
+        if visited >= MAX_ROOT_SEARCH_DIRS {
+            anyhow::bail!(
+                "Archive root not found within the first {} directories of {}",
+                MAX_ROOT_SEARCH_DIRS,
+                base.display()
+            );
         }
+        if depth >= max_depth {
+            continue;
+        }
+
+        let mut children: Vec<PathBuf> = std::fs::read_dir(&dir)
+            .context("Failed to read extracted directory")?
+            .filter_map(|e| e.ok())
+            .filter(|e| e.file_type().map(|t| t.is_dir()).unwrap_or(false))
+            .map(|e| e.path())
+            .collect();
+        children.sort();
+        queue.extend(children.into_iter().map(|p| (p, depth + 1)));
     }
 
     anyhow::bail!(
-        "No tasks/ or agent_code/ found in archive at {}",
-        base.display()
+        "No tasks/ or agent_code/ found in archive at {} (searched {} levels deep)",
+        base.display(),
+        max_depth
     )
 }
 
+/// Read the `agent_code/entrypoint` manifest, if present. The named file must
+/// be a relative path inside `agent_dir`.
+pub fn read_agent_entrypoint(agent_dir: &Path) -> Result<Option<String>> {
+    let manifest = agent_dir.join(AGENT_ENTRYPOINT_FILE);
+    if !manifest.is_file() {
+        return Ok(None);
+    }
+    let content = std::fs::read_to_string(&manifest)
+        .with_context(|| format!("Failed to read {}", manifest.display()))?;
+    let line = content
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with('#'))
+        .unwrap_or("");
+    let entry = line
+        .strip_prefix("entrypoint:")
+        .unwrap_or(line)
+        .trim()
+        .trim_matches(|c| c == '"' || c == '\'');
+
+    let path = Path::new(entry);
+    if entry.is_empty()
+        || path.is_absolute()
+        || path
+            .components()
+            .any(|c| matches!(c, std::path::Component::ParentDir))
+    {
+        anyhow::bail!(
+            "agent_code/{}: invalid entrypoint '{}'",
+            AGENT_ENTRYPOINT_FILE,
+            entry
+        );
+    }
+    if !agent_dir.join(path).is_file() {
+        return Err(AgentCodeError::EntrypointNotFound(entry.to_string()).into());
+    }
+    Ok(Some(entry.to_string()))
+}
+
+/// Why an archive's agent cannot run, as opposed to the archive itself being
+/// unreadable. Carried inside `anyhow::Error` so handlers can downcast it.
+#[derive(Debug, thiserror::Error)]
+pub enum AgentCodeError {
+    #[error("agent_code/ directory not found in archive")]
+    Missing,
+    #[error("no agent files in agent_code/")]
+    NoFiles,
+    #[error("agent_code empty: agent files contain only whitespace")]
+    Empty,
+    #[error("agent_code/{AGENT_ENTRYPOINT_FILE}: entrypoint '{0}' not found")]
+    EntrypointNotFound(String),
+    #[error("archive {0} has a different agent_code/ than the first archive")]
+    Conflict(usize),
+    #[error("agent_code/{AGENT_LANGUAGE_FILE}: unknown language '{0}'")]
+    UnknownLanguage(String),
+}
+
+impl AgentCodeError {
+    pub fn code(&self) -> &'static str {
+        match self {
+            AgentCodeError::Missing => "agent_code_missing",
+            AgentCodeError::NoFiles => "no_agent_files",
+            AgentCodeError::Empty => "agent_code_empty",
+            AgentCodeError::EntrypointNotFound(_) => "agent_entrypoint_not_found",
+            AgentCodeError::Conflict(_) => "agent_code_conflict",
+            AgentCodeError::UnknownLanguage(_) => "agent_language_unknown",
+        }
+    }
+}
+
+/// Cheap check of an uploaded archive before it enters a consensus round:
+/// lists the `agent_code/` and `tasks/` entries without extracting anything.
+/// Only the root extraction would pick is judged: the shallowest directory,
+/// at most `root_depth` levels down, holding either of them. Fails with
+/// `AgentCodeError` or `TooManyTasks` (`max_tasks` 0 = no limit), otherwise
+/// returns the number of task dirs. Archives that can't be read are let
+/// through with `None`; extraction reports those.
+pub fn precheck_archive(path: &Path, max_tasks: usize, root_depth: usize) -> Result<Option<usize>> {
+    let Ok(file) = std::fs::File::open(path) else {
+        return Ok(None);
+    };
+    let mut reader = std::io::BufReader::new(file);
+    let mut scan = ArchiveScan::new(root_depth);
+
+    if let Ok(mut archive) = zip::ZipArchive::new(&mut reader) {
+        for i in 0..archive.len() {
+            let Ok(entry) = archive.by_index(i) else {
+                return Ok(None);
+            };
+            let name = PathBuf::from(entry.name());
+            let is_file = entry.is_file();
+            scan.visit(&name, is_file, entry);
+            if max_tasks > 0 && scan.task_count() > max_tasks {
+                break;
+            }
+        }
+    } else {
+        use std::io::Seek;
+        if reader.seek(std::io::SeekFrom::Start(0)).is_err() {
+            return Ok(None);
+        }
+        let mut archive = tar::Archive::new(flate2::read::GzDecoder::new(reader));
+        let Ok(entries) = archive.entries() else {
+            return Ok(None);
+        };
+        for entry in entries {
+            let Ok(entry) = entry else {
+                return Ok(None);
+            };
+            let Ok(name) = entry.path().map(|p| p.into_owned()) else {
+                continue;
+            };
+            let is_file = entry.header().entry_type().is_file();
+            scan.visit(&name, is_file, entry);
+            if max_tasks > 0 && scan.task_count() > max_tasks {
+                break;
+            }
+        }
+    }
+    if max_tasks > 0 && scan.task_count() > max_tasks {
+        return Err(TooManyTasks {
+            found: scan.task_count(),
+            max: max_tasks,
+        }
+        .into());
+    }
+    scan.verdict()?;
+    Ok(Some(scan.task_count()))
+}
+
+struct ArchiveScan {
+    root_depth: usize,
+    /// Findings under each directory that could be the archive root: one
+    /// within `root_depth` levels that holds `agent_code/` or `tasks/`.
+    roots: std::collections::HashMap<PathBuf, RootScan>,
+}
+
+#[derive(Default)]
+struct RootScan {
+    found_dir: bool,
+    files: usize,
+    has_content: bool,
+    /// Names of the dirs under `tasks/`.
+    task_dirs: std::collections::HashSet<std::ffi::OsString>,
+}
+
+impl ArchiveScan {
+    fn new(root_depth: usize) -> Self {
+        Self {
+            root_depth,
+            roots: std::collections::HashMap::new(),
+        }
+    }
+
+    fn visit(&mut self, name: &Path, is_file: bool, content: impl std::io::Read) {
+        let components: Vec<&std::ffi::OsStr> = name
+            .components()
+            .filter(|c| matches!(c, std::path::Component::Normal(_)))
+            .map(|c| c.as_os_str())
+            .collect();
+        let mut content = Some(content);
+        let mut has_content = None;
+        for (depth, top) in components.iter().enumerate().take(self.root_depth + 1) {
+            if *top != "agent_code" && *top != "tasks" {
+                continue;
+            }
+            let rest = &components[depth + 1..];
+            if rest.is_empty() && is_file {
+                continue;
+            }
+            let root: PathBuf = components[..depth].iter().collect();
+            let scan = self.roots.entry(root).or_default();
+            if *top == "tasks" {
+                if let Some(task) = rest.first().filter(|_| rest.len() > 1 || !is_file) {
+                    scan.task_dirs.insert(task.to_os_string());
+                }
+                continue;
+            }
+            scan.found_dir = true;
+            if !is_file
+                || rest.is_empty()
+                || rest == [AGENT_ENTRYPOINT_FILE]
+                || rest == [AGENT_LANGUAGE_FILE]
+            {
+                continue;
+            }
+            scan.files += 1;
+            if !scan.has_content {
+                // The entry can only be read once, but may sit under two
+                // candidate roots.
+                scan.has_content = *has_content
+                    .get_or_insert_with(|| content.take().is_none_or(has_non_whitespace));
+            }
+        }
+    }
+
+    /// The root `find_archive_root` would pick: shallowest first, then in
+    /// path order.
+    fn root(&self) -> Option<&RootScan> {
+        self.roots
+            .iter()
+            .min_by_key(|(path, _)| (path.components().count(), *path))
+            .map(|(_, scan)| scan)
+    }
+
+    fn task_count(&self) -> usize {
+        self.root().map_or(0, |r| r.task_dirs.len())
+    }
+
+    fn verdict(&self) -> std::result::Result<(), AgentCodeError> {
+        let Some(root) = self.root().filter(|r| r.found_dir) else {
+            return Err(AgentCodeError::Missing);
+        };
+        if root.files == 0 {
+            Err(AgentCodeError::NoFiles)
+        } else if !root.has_content {
+            Err(AgentCodeError::Empty)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Whether `r` holds anything besides whitespace, looking at the first MiB.
+fn has_non_whitespace(r: impl std::io::Read) -> bool {
+    let mut buf = Vec::new();
+    if std::io::Read::read_to_end(&mut r.take(1024 * 1024), &mut buf).is_err() {
+        // Unreadable content is for extraction to report.
+        return true;
+    }
+    buf.iter().any(|b| !b.is_ascii_whitespace())
+}
+
 fn load_agent_code(root: &Path) -> Result<String> {
     let agent_dir = root.join("agent_code");
     if !agent_dir.exists() {
-        anyhow::bail!("agent_code/ directory not found in archive");
+        return Err(AgentCodeError::Missing.into());
+    }
+
+    if let Some(entry) = read_agent_entrypoint(&agent_dir)? {
+        let code = std::fs::read_to_string(agent_dir.join(&entry))
+            .with_context(|| format!("Failed to read agent entrypoint: {}", entry))?;
+        if code.trim().is_empty() {
+            return Err(AgentCodeError::Empty.into());
+        }
+        return Ok(code);
     }
 
+    // Without a manifest, fall back to concatenating the files for
+    // single-script agents.
     let mut agent_content = String::new();
     let mut files: Vec<_> = std::fs::read_dir(&agent_dir)?
         .filter_map(|e| e.ok())
-        .filter(|e| e.path().is_file())
+        .filter(|e| e.path().is_file() && e.file_name() != AGENT_LANGUAGE_FILE)
         .collect();
     files.sort_by_key(|e| e.file_name());
 
+    let mut blank = true;
     for entry in &files {
         let content = std::fs::read_to_string(entry.path())
             .with_context(|| format!("Failed to read agent file: {:?}", entry.path()))?;
+        blank &= content.trim().is_empty();
         if files.len() == 1 {
             agent_content = content;
         } else {
@@ -211,42 +1043,209 @@ fn load_agent_code(root: &Path) -> Result<String> {
         }
     }
 
-    if agent_content.is_empty() {
-        anyhow::bail!("agent_code/ directory is empty");
+    if files.is_empty() {
+        return Err(AgentCodeError::NoFiles.into());
+    }
+    if blank {
+        return Err(AgentCodeError::Empty.into());
     }
 
     Ok(agent_content)
 }
 
-fn detect_agent_language(root: &Path) -> String {
+/// Agent language implied by a file name, if any.
+pub fn language_for_file(name: &str) -> Option<&'static str> {
+    if name.ends_with(".py") {
+        Some("python")
+    } else if name.ends_with(".js") {
+        Some("javascript")
+    } else if name.ends_with(".ts") {
+        Some("typescript")
+    } else if name.ends_with(".sh") {
+        Some("shell")
+    } else if name.ends_with(".rs") || name.ends_with("Cargo.toml") {
+        Some("rust")
+    } else if name.ends_with(".go") {
+        Some("go")
+    } else {
+        None
+    }
+}
+
+/// Agent languages by canonical name, with the aliases accepted for each.
+const AGENT_LANGUAGES: &[(&str, &[&str])] = &[
+    ("python", &["py"]),
+    ("javascript", &["js", "node"]),
+    ("typescript", &["ts"]),
+    ("shell", &["bash", "sh"]),
+    ("rust", &["rs"]),
+    ("go", &["golang"]),
+    ("ruby", &["rb"]),
+];
+
+/// Canonical name of an agent language or one of its aliases.
+pub fn normalize_agent_language(name: &str) -> Option<&'static str> {
+    let name = name.trim().to_lowercase();
+    AGENT_LANGUAGES
+        .iter()
+        .find(|(canonical, aliases)| *canonical == name || aliases.contains(&name.as_str()))
+        .map(|(canonical, _)| *canonical)
+}
+
+/// Read the `agent_code/language` manifest, if present.
+fn read_agent_language(agent_dir: &Path) -> Result<Option<&'static str>> {
+    let manifest = agent_dir.join(AGENT_LANGUAGE_FILE);
+    if !manifest.is_file() {
+        return Ok(None);
+    }
+    let content = std::fs::read_to_string(&manifest)
+        .with_context(|| format!("Failed to read {}", manifest.display()))?;
+    let line = content
+        .lines()
+        .map(str::trim)
+        .find(|l| !l.is_empty() && !l.starts_with('#'))
+        .unwrap_or("");
+    let name = line
+        .strip_prefix("language:")
+        .unwrap_or(line)
+        .trim()
+        .trim_matches(|c| c == '"' || c == '\'');
+    normalize_agent_language(name)
+        .map(Some)
+        .ok_or_else(|| AgentCodeError::UnknownLanguage(name.to_string()).into())
+}
+
+/// Agent language, from `agent_code/language` when declared, else from the
+/// entrypoint or file extensions, else `default_language`.
+fn detect_agent_language(root: &Path, default_language: &str) -> Result<String> {
     let agent_dir = root.join("agent_code");
+    if let Some(lang) = read_agent_language(&agent_dir)? {
+        return Ok(lang.to_string());
+    }
+    if let Ok(Some(entry)) = read_agent_entrypoint(&agent_dir) {
+        if let Some(lang) = language_for_file(&entry) {
+            return Ok(lang.to_string());
+        }
+    }
     if let Ok(entries) = std::fs::read_dir(&agent_dir) {
         for entry in entries.flatten() {
             let name = entry.file_name().to_string_lossy().to_string();
-            if name.ends_with(".py") {
-                return "python".to_string();
-            }
-            if name.ends_with(".js") {
-                return "javascript".to_string();
-            }
-            if name.ends_with(".ts") {
-                return "typescript".to_string();
-            }
-            if name.ends_with(".sh") {
-                return "shell".to_string();
-            }
-            if name.ends_with(".rs") {
-                return "rust".to_string();
-            }
-            if name.ends_with(".go") {
-                return "go".to_string();
+            if let Some(lang) = language_for_file(&name) {
+                return Ok(lang.to_string());
             }
         }
     }
-    "python".to_string()
+    Ok(default_language.to_string())
+}
+
+/// Longest task id accepted as a work dir name.
+const MAX_TASK_ID_LEN: usize = 200;
+
+/// A task id that can't be used to name a directory.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+#[error("invalid task id '{id}': {reason}")]
+pub struct InvalidTaskId {
+    pub id: String,
+    pub reason: &'static str,
 }
 
-fn load_tasks(root: &Path) -> Result<Vec<SweForgeTask>> {
+/// Directory name for a task's work dir under the workspace base. Ids such
+/// as `org/repo-1` are flattened to `org__repo-1`; empty, `.` or `..`
+/// segments, absolute paths, characters outside `[A-Za-z0-9._+-]` and names
+/// that could clash with the workspace's own dirs are refused. The task
+/// keeps its original id everywhere else.
+pub fn task_dir_name(id: &str) -> std::result::Result<String, InvalidTaskId> {
+    let invalid = |reason| InvalidTaskId {
+        id: id.to_string(),
+        reason,
+    };
+    if id.is_empty() {
+        return Err(invalid("empty"));
+    }
+    if id.len() > MAX_TASK_ID_LEN {
+        return Err(invalid("too long"));
+    }
+    let segments: Vec<&str> = id.split(['/', '\\']).collect();
+    if segments
+        .iter()
+        .any(|s| s.is_empty() || *s == "." || *s == "..")
+    {
+        return Err(invalid("empty, '.' or '..' path segment"));
+    }
+    if !segments.iter().all(|s| {
+        s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '+' | '-'))
+    }) {
+        return Err(invalid("characters outside [A-Za-z0-9._+-]"));
+    }
+    let name = segments.join("__");
+    if name.starts_with(['.', '_'])
+        || name == crate::cleanup::KEPT_FAILED_DIR
+        || name == crate::cleanup::KEPT_DIR
+    {
+        return Err(invalid("reserved name"));
+    }
+    Ok(name)
+}
+
+/// An archive holds more task directories than `MAX_TASKS_PER_BATCH`.
+#[derive(Debug, thiserror::Error)]
+#[error("archive has {found} tasks, more than the limit of {max}")]
+pub struct TooManyTasks {
+    pub found: usize,
+    pub max: usize,
+}
+
+/// Machine-readable description of the archive layout `load_tasks` and
+/// `load_agent_code` accept, with the JSON Schema of `workspace.yaml`, so
+/// archives can be checked client-side before upload.
+pub fn archive_schema(
+    max_tasks: usize,
+    root_depth: usize,
+    default_language: &str,
+) -> serde_json::Value {
+    let interpreters: serde_json::Map<String, serde_json::Value> = TEST_INTERPRETERS
+        .iter()
+        .map(|(ext, argv)| (ext.to_string(), serde_json::json!(argv)))
+        .collect();
+    serde_json::json!({
+        "root": {
+            "description": "Directory holding agent_code/ and tasks/; may be nested inside the archive",
+            "max_depth": root_depth,
+        },
+        "agent_code": {
+            "path": "agent_code/",
+            "required": true,
+            "description": "Agent source files; all files are concatenated unless an entrypoint is named",
+            "optional_files": {
+                AGENT_ENTRYPOINT_FILE: "File to execute, bare or as `entrypoint: <path>`",
+                AGENT_LANGUAGE_FILE: "Agent language, bare or as `language: <name>`",
+            },
+            "languages": AGENT_LANGUAGES.iter().map(|(name, _)| *name).collect::<Vec<_>>(),
+            "default_language": default_language,
+        },
+        "tasks": {
+            "path": "tasks/<task_id>/",
+            "required": true,
+            "max_tasks": (max_tasks > 0).then_some(max_tasks),
+            "required_files": {
+                "workspace.yaml": "Task configuration, see workspace_schema",
+                "prompt.md": "Instruction given to the agent",
+            },
+            "optional_files": {
+                "tests/": "Test scripts (`*.sh` and `run_tests.<ext>`) run after the agent; other files are copied into the repo",
+                "checks.txt": "One shell command per line, used when there are no test scripts",
+            },
+            "test_interpreters": interpreters,
+            "test_fallback": "Without test scripts or checks.txt, `fail_to_pass` and `pass_to_pass` commands from workspace.yaml are run",
+        },
+        "workspace_schema": schemars::schema_for!(WorkspaceConfig),
+    })
+}
+
+/// Parse every directory under `tasks/`. More than `max_tasks` directories
+/// (0 = no limit) fails before any of them is parsed.
+fn load_tasks(root: &Path, max_tasks: usize) -> Result<Vec<SweForgeTask>> {
     let tasks_dir = root.join("tasks");
     if !tasks_dir.exists() {
         anyhow::bail!("tasks/ directory not found in archive");
@@ -258,6 +1257,13 @@ fn load_tasks(root: &Path) -> Result<Vec<SweForgeTask>> {
         .filter(|e| e.path().is_dir())
         .collect();
     entries.sort_by_key(|e| e.file_name());
+    if max_tasks > 0 && entries.len() > max_tasks {
+        return Err(TooManyTasks {
+            found: entries.len(),
+            max: max_tasks,
+        }
+        .into());
+    }
 
     for entry in entries {
         let task_dir = entry.path();
@@ -312,10 +1318,20 @@ pub fn parse_task(task_dir: &Path) -> Result<SweForgeTask> {
         )?;
     }
 
+    if let Some(ref cmd) = workspace.test_command {
+        // Keep any shipped scripts in the repo so the command can call them.
+        test_source_files.append(&mut test_scripts);
+        test_scripts.push((
+            "_test_command.sh".to_string(),
+            format!("#!/bin/bash\nset -e\n{}\n", cmd),
+        ));
+    }
+
     let checks_path = task_dir.join("checks.txt");
+    let mut checks = Vec::new();
     if checks_path.exists() && test_scripts.is_empty() {
-        let checks = std::fs::read_to_string(&checks_path).context("Failed to read checks.txt")?;
-        for (i, line) in checks.lines().enumerate() {
+        let content = std::fs::read_to_string(&checks_path).context("Failed to read checks.txt")?;
+        for (i, line) in content.lines().enumerate() {
             let line = line.trim();
             if line.is_empty() || line.starts_with('#') {
                 continue;
@@ -323,6 +1339,7 @@ pub fn parse_task(task_dir: &Path) -> Result<SweForgeTask> {
             let name = format!("check_{}.sh", i);
             let content = format!("#!/bin/sh\nset -e\n{}\n", line);
             test_scripts.push((name, content));
+            checks.push(line.to_string());
         }
     }
 
@@ -356,14 +1373,7 @@ pub fn parse_task(task_dir: &Path) -> Result<SweForgeTask> {
         }
     }
 
-    // Generate runtime install command from install_config version fields.
-    // Stored separately so executor can run it without filter_install_command.
-    if let Some(ref ic) = workspace.install_config {
-        let runtime_cmd = runtime_install_command(ic);
-        if !runtime_cmd.is_empty() {
-            workspace.runtime_install = Some(runtime_cmd);
-        }
-    }
+    workspace.refresh_runtime_install();
 
     Ok(SweForgeTask {
         id,
@@ -371,6 +1381,7 @@ pub fn parse_task(task_dir: &Path) -> Result<SweForgeTask> {
         prompt,
         test_scripts,
         test_source_files,
+        checks,
         swe_forge_fields: None,
     })
 }
@@ -472,7 +1483,7 @@ fn load_tests_recursive(
         let content = std::fs::read_to_string(&path)
             .with_context(|| format!("Failed to read test file: {}", fname))?;
 
-        if fname.ends_with(".sh") {
+        if is_test_script(&fname) {
             scripts.push((fname, content));
         } else {
             source_files.push((fname, content));
@@ -481,11 +1492,53 @@ fn load_tests_recursive(
     Ok(())
 }
 
-#[allow(dead_code)]
-pub async fn download_and_extract(url: &str, dest: &Path) -> Result<()> {
+/// An archive download went past the size limit.
+#[derive(Debug, thiserror::Error)]
+#[error("archive download exceeds {max_bytes} bytes")]
+pub struct ArchiveTooLarge {
+    pub max_bytes: usize,
+    pub bytes_seen: usize,
+}
+
+/// Size and digests of an archive written by [`download_archive`].
+#[derive(Debug)]
+pub struct DownloadedArchive {
+    pub size: usize,
+    pub sha256: String,
+    pub blake2b_256: String,
+}
+
+/// Stream the archive at `url` into `dest`, hashing it on the way so the
+/// bytes can vote in consensus like an upload. `check_url` is applied to the
+/// URL and to every redirect hop. Fails with [`ArchiveTooLarge`] as soon as
+/// more than `max_bytes` arrive, and the whole download must finish within
+/// `timeout_secs`. Extraction is left to the caller once consensus is reached.
+pub async fn download_archive(
+    url: &str,
+    dest: &Path,
+    max_bytes: usize,
+    timeout_secs: u64,
+    check_url: impl Fn(&str) -> Result<()> + Clone + Send + Sync + 'static,
+) -> Result<DownloadedArchive> {
+    use blake2::{digest::consts::U32, Blake2b};
+    use futures::TryStreamExt;
+    use sha2::{Digest, Sha256};
+    use tokio::io::AsyncWriteExt;
+
+    check_url(url)?;
     info!("Downloading task archive from {}", url);
+    let check_redirect = check_url.clone();
     let client = reqwest::Client::builder()
-        .timeout(std::time::Duration::from_secs(120))
+        .timeout(std::time::Duration::from_secs(timeout_secs))
+        .redirect(reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() >= 5 {
+                attempt.error("too many redirects")
+            } else if let Err(e) = check_redirect(attempt.url().as_str()) {
+                attempt.error(e.to_string())
+            } else {
+                attempt.follow()
+            }
+        }))
         .build()?;
 
     let resp = client
@@ -500,34 +1553,521 @@ pub async fn download_and_extract(url: &str, dest: &Path) -> Result<()> {
             resp.status().as_u16()
         );
     }
-
-    let bytes = resp.bytes().await.context("Failed to read response body")?;
-
-    if bytes.len() > MAX_ARCHIVE_SIZE {
-        anyhow::bail!(
-            "Task archive too large: {} bytes (max {})",
-            bytes.len(),
-            MAX_ARCHIVE_SIZE
-        );
+    if let Some(len) = resp.content_length() {
+        if len > max_bytes as u64 {
+            return Err(ArchiveTooLarge {
+                max_bytes,
+                bytes_seen: len as usize,
+            }
+            .into());
+        }
     }
 
-    tokio::fs::create_dir_all(dest)
+    let mut writer = tokio::fs::File::create(dest)
         .await
-        .context("Failed to create extraction directory")?;
-
-    let dest = dest.to_path_buf();
-    let bytes_vec = bytes.to_vec();
-    tokio::task::spawn_blocking(move || extract_archive_bytes(&bytes_vec, &dest))
+        .context("Failed to create archive file")?;
+    let mut sha256 = Sha256::new();
+    let mut blake2b = Blake2b::<U32>::new();
+    let mut size = 0usize;
+    let mut stream = resp.bytes_stream();
+    while let Some(chunk) = stream
+        .try_next()
         .await
-        .context("Extract task panicked")??;
+        .context("Failed to read task archive")?
+    {
+        if size + chunk.len() > max_bytes {
+            return Err(ArchiveTooLarge {
+                max_bytes,
+                bytes_seen: size + chunk.len(),
+            }
+            .into());
+        }
+        size += chunk.len();
+        sha256.update(&chunk);
+        blake2b.update(&chunk);
+        writer
+            .write_all(&chunk)
+            .await
+            .context("Failed to write archive file")?;
+    }
+    writer
+        .flush()
+        .await
+        .context("Failed to write archive file")?;
 
-    Ok(())
+    Ok(DownloadedArchive {
+        size,
+        sha256: hex::encode(sha256.finalize()),
+        blake2b_256: hex::encode(blake2b.finalize()),
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// Answer one HTTP request on a local port with `body`.
+    async fn serve_once(body: &'static [u8]) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut conn, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = conn.read(&mut buf).await;
+            let head = format!(
+                "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                body.len()
+            );
+            conn.write_all(head.as_bytes()).await.unwrap();
+            conn.write_all(body).await.unwrap();
+        });
+        format!("http://{}/archive.tar.gz", addr)
+    }
+
+    fn tar_gz_with_link(name: &str, target: &str, kind: tar::EntryType) -> Vec<u8> {
+        let mut tar_gz = Vec::new();
+        {
+            let enc = flate2::write::GzEncoder::new(&mut tar_gz, flate2::Compression::fast());
+            let mut builder = tar::Builder::new(enc);
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(kind);
+            header.set_size(0);
+            header.set_mode(0o777);
+            builder.append_link(&mut header, name, target).unwrap();
+            let mut header = tar::Header::new_gnu();
+            header.set_size(2);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder
+                .append_data(&mut header, "agent_code/agent.py", &b"x\n"[..])
+                .unwrap();
+            builder.into_inner().unwrap().finish().unwrap();
+        }
+        tar_gz
+    }
+
+    #[test]
+    fn test_extract_rejects_escaping_symlinks() {
+        for (name, target, kind) in [
+            ("agent_code/passwd", "/etc/passwd", tar::EntryType::Symlink),
+            ("agent_code/up", "../../etc", tar::EntryType::Symlink),
+            ("agent_code/hard", "/etc/shadow", tar::EntryType::Link),
+        ] {
+            let tmp = tempfile::tempdir().unwrap();
+            let err = extract_archive_bytes(&tar_gz_with_link(name, target, kind), tmp.path())
+                .unwrap_err();
+            assert!(
+                err.to_string().contains("points outside"),
+                "{}: {}",
+                name,
+                err
+            );
+            assert!(!tmp.path().join(name).exists());
+        }
+
+        let tmp = tempfile::tempdir().unwrap();
+        let archive = tar_gz_with_link("agent_code/lib", "../shared", tar::EntryType::Symlink);
+        extract_archive_bytes(&archive, tmp.path()).unwrap();
+        assert!(tmp.path().join("agent_code/lib").is_symlink());
+
+        let none = std::collections::HashSet::new();
+        assert!(check_link_target(Path::new("a/b/l"), Path::new("../../c"), false, &none).is_ok());
+        assert!(
+            check_link_target(Path::new("a/b/l"), Path::new("../../../c"), false, &none).is_err()
+        );
+        assert!(check_link_target(Path::new("a/b/l"), Path::new("../c"), true, &none).is_err());
+    }
+
+    #[test]
+    fn test_extract_rejects_symlink_chain() {
+        // Each link stays inside on its own, but l2 resolves through l1 to
+        // three levels above a/b/c, i.e. outside the extraction dir. The
+        // chain is rejected whichever order the links come in.
+        for order in [["l1", "l2"], ["l2", "l1"]] {
+            let mut tar_gz = Vec::new();
+            {
+                let enc = flate2::write::GzEncoder::new(&mut tar_gz, flate2::Compression::fast());
+                let mut builder = tar::Builder::new(enc);
+                for link in order {
+                    let target = if link == "l1" { "../../.." } else { "l1/../.." };
+                    let mut header = tar::Header::new_gnu();
+                    header.set_entry_type(tar::EntryType::Symlink);
+                    header.set_size(0);
+                    header.set_mode(0o777);
+                    builder
+                        .append_link(&mut header, format!("a/b/c/{}", link), target)
+                        .unwrap();
+                }
+                builder.into_inner().unwrap().finish().unwrap();
+            }
+            let tmp = tempfile::tempdir().unwrap();
+            let err = extract_archive_bytes(&tar_gz, tmp.path()).unwrap_err();
+            assert!(err.to_string().contains("passes through link"), "{}", err);
+            assert!(!tmp.path().join("a/b/c/l2").exists());
+        }
+
+        let links: std::collections::HashSet<PathBuf> =
+            [PathBuf::from("a/b/c/l1")].into_iter().collect();
+        assert!(
+            check_link_target(Path::new("a/b/c/l3"), Path::new("l1"), false, &links).is_ok(),
+            "pointing at a link is fine"
+        );
+    }
+
+    #[test]
+    fn test_extract_rejects_zip_symlink_to_absolute_path() {
+        let mut buf = std::io::Cursor::new(Vec::new());
+        {
+            let mut zip = zip::ZipWriter::new(&mut buf);
+            let options = zip::write::SimpleFileOptions::default();
+            zip.add_symlink("agent_code/passwd", "/etc/passwd", options)
+                .unwrap();
+            zip.start_file("agent_code/agent.py", options).unwrap();
+            std::io::Write::write_all(&mut zip, b"x\n").unwrap();
+            zip.finish().unwrap();
+        }
+        let tmp = tempfile::tempdir().unwrap();
+        let err = extract_archive_bytes(buf.get_ref(), tmp.path()).unwrap_err();
+        assert!(err.to_string().contains("points outside"));
+        assert!(!tmp.path().join("agent_code/agent.py").exists());
+    }
+
+    #[tokio::test]
+    async fn test_download_archive_hashes_and_enforces_limit() {
+        use sha2::{Digest, Sha256};
+        let tmp = tempfile::tempdir().unwrap();
+        let dest = tmp.path().join("archive");
+
+        let url = serve_once(b"archive bytes").await;
+        let downloaded = download_archive(&url, &dest, 1024, 10, |_| Ok(()))
+            .await
+            .unwrap();
+        assert_eq!(downloaded.size, 13);
+        assert_eq!(
+            downloaded.sha256,
+            hex::encode(Sha256::digest(b"archive bytes"))
+        );
+        assert_eq!(std::fs::read(&dest).unwrap(), b"archive bytes");
+
+        let url = serve_once(b"archive bytes").await;
+        let err = download_archive(&url, &dest, 8, 10, |_| Ok(()))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<ArchiveTooLarge>().unwrap().bytes_seen,
+            13
+        );
+
+        let err = download_archive(&url, &dest, 1024, 10, |_| anyhow::bail!("denied"))
+            .await
+            .unwrap_err();
+        assert_eq!(err.to_string(), "denied");
+    }
+
+    #[test]
+    fn test_dedupe_duplicate_tasks() {
+        let tmp = tempfile::tempdir().unwrap();
+        for (dir, prompt) in [("a", "Fix it"), ("b", "Fix it"), ("c", "Other")] {
+            let task_dir = tmp.path().join("tasks").join(dir);
+            std::fs::create_dir_all(&task_dir).unwrap();
+            std::fs::write(
+                task_dir.join("workspace.yaml"),
+                "repo: o/r\nbase_commit: abc\n",
+            )
+            .unwrap();
+            std::fs::write(task_dir.join("prompt.md"), prompt).unwrap();
+        }
+        let mut tasks = load_tasks(tmp.path(), 0).unwrap();
+        // Same id as `c`, as when task sources are merged.
+        let mut dup_id = tasks[2].clone();
+        dup_id.prompt = "Changed".into();
+        tasks.push(dup_id);
+
+        let err = dedupe_tasks(&mut tasks.clone(), DuplicateTaskPolicy::Reject).unwrap_err();
+        assert!(err.to_string().contains("b, c"), "{}", err);
+
+        let dropped = dedupe_tasks(&mut tasks, DuplicateTaskPolicy::Coalesce).unwrap();
+        assert_eq!(dropped, vec!["b", "c"]);
+        let ids: Vec<&str> = tasks.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, vec!["a", "c"]);
+        assert_eq!(tasks[1].prompt, "Other");
+    }
+
+    fn precheck_code(path: &Path) -> &'static str {
+        precheck_archive(path, 0, DEFAULT_ARCHIVE_ROOT_DEPTH)
+            .unwrap_err()
+            .downcast::<AgentCodeError>()
+            .unwrap()
+            .code()
+    }
+
+    fn agent_zip(files: &[(&str, &str)]) -> tempfile::NamedTempFile {
+        let tmp = tempfile::NamedTempFile::new().unwrap();
+        let mut zip = zip::ZipWriter::new(tmp.reopen().unwrap());
+        for (name, content) in files {
+            zip.start_file(*name, zip::write::SimpleFileOptions::default())
+                .unwrap();
+            std::io::Write::write_all(&mut zip, content.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+        tmp
+    }
+
+    #[tokio::test]
+    async fn test_agent_archive_excludes_tasks() {
+        let archive = agent_zip(&[
+            ("tasks/t1/workspace.yaml", "repo: o/r\n"),
+            ("tasks/t1/prompt.md", "fix it"),
+            ("tasks/t1/tests/hidden.sh", "exit 0\n"),
+            ("agent_code/entrypoint", "main.py\n"),
+            ("agent_code/main.py", "import helper\n"),
+            ("agent_code/lib/helper.py", "X = 1\n"),
+        ]);
+        let dest = tempfile::tempdir().unwrap();
+        let extracted = extract_uploaded_archive_file(
+            archive.path(),
+            dest.path(),
+            2,
+            0,
+            DEFAULT_AGENT_LANGUAGE,
+        )
+        .await
+        .unwrap();
+        let packed = extracted.agent_archive.unwrap();
+
+        let agent_base = tempfile::tempdir().unwrap();
+        let agent_base = agent_base.path().join("_agent");
+        extract_archive_bytes(&packed, &agent_base).unwrap();
+        assert!(agent_base.join("agent_code/main.py").is_file());
+        assert!(agent_base.join("agent_code/lib/helper.py").is_file());
+        assert!(!agent_base.join("tasks").exists());
+        let names: Vec<_> = std::fs::read_dir(&agent_base)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["agent_code"]);
+
+        // Packing is deterministic.
+        let dest = tempfile::tempdir().unwrap();
+        let again = extract_uploaded_archive_file(
+            archive.path(),
+            dest.path(),
+            2,
+            0,
+            DEFAULT_AGENT_LANGUAGE,
+        )
+        .await
+        .unwrap();
+        assert_eq!(again.agent_archive.unwrap(), packed);
+    }
+
+    #[tokio::test]
+    async fn test_merge_archives() {
+        let bundle = |task: &str, agent: &str| {
+            agent_zip(&[
+                (&format!("tasks/{}/workspace.yaml", task), "repo: o/r\n"),
+                (&format!("tasks/{}/prompt.md", task), task),
+                ("agent_code/agent.py", agent),
+            ])
+        };
+        let extract = |archive: tempfile::NamedTempFile| async move {
+            let dest = tempfile::tempdir().unwrap();
+            extract_uploaded_archive_file(archive.path(), dest.path(), 2, 0, DEFAULT_AGENT_LANGUAGE)
+                .await
+                .unwrap()
+        };
+
+        let a = extract(bundle("t1", "print(1)\n")).await;
+        let b = extract(bundle("t2", "print(1)\n")).await;
+        let merged = merge_archives(vec![a, b], 0).unwrap();
+        let ids: Vec<&str> = merged.tasks.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, vec!["t1", "t2"]);
+
+        let a = extract(bundle("t1", "print(1)\n")).await;
+        let b = extract(bundle("t2", "print(1)\n")).await;
+        let err = merge_archives(vec![a, b], 1).unwrap_err();
+        assert!(err.downcast_ref::<TooManyTasks>().is_some(), "{}", err);
+
+        let a = extract(bundle("t1", "print(1)\n")).await;
+        let b = extract(bundle("t2", "print(2)\n")).await;
+        let err = merge_archives(vec![a, b], 0).unwrap_err();
+        let err = err.downcast::<AgentCodeError>().unwrap();
+        assert_eq!(err.code(), "agent_code_conflict");
+        assert!(err.to_string().contains("archive 1"));
+
+        // Same entrypoint, different helper module: still a different agent.
+        let multi = |task: &str, helper: &str| {
+            agent_zip(&[
+                (&format!("tasks/{}/workspace.yaml", task), "repo: o/r\n"),
+                (&format!("tasks/{}/prompt.md", task), task),
+                ("agent_code/entrypoint", "main.py\n"),
+                ("agent_code/main.py", "import helper\n"),
+                ("agent_code/helper.py", helper),
+            ])
+        };
+        let a = extract(multi("t1", "X = 1\n")).await;
+        let b = extract(multi("t2", "X = 2\n")).await;
+        let err = merge_archives(vec![a, b], 0).unwrap_err();
+        assert!(err.downcast_ref::<AgentCodeError>().is_some(), "{}", err);
+        let a = extract(multi("t1", "X = 1\n")).await;
+        let b = extract(multi("t2", "X = 1\n")).await;
+        assert!(merge_archives(vec![a, b], 0).is_ok());
+    }
+
+    fn agent_code_error(root: &Path) -> AgentCodeError {
+        let err = load_agent_code(root).unwrap_err();
+        err.downcast::<AgentCodeError>().unwrap()
+    }
+
+    #[test]
+    fn test_whitespace_only_agent_rejected() {
+        let tmp = tempfile::tempdir().unwrap();
+        let agent_dir = tmp.path().join("agent_code");
+        std::fs::create_dir_all(&agent_dir).unwrap();
+        std::fs::write(agent_dir.join("agent.py"), "  \n\t\n").unwrap();
+        assert_eq!(agent_code_error(tmp.path()).code(), "agent_code_empty");
+
+        let archive = agent_zip(&[("agent_code/agent.py", " \n\n ")]);
+        assert_eq!(precheck_code(archive.path()), "agent_code_empty");
+        let archive = agent_zip(&[("agent_code/agent.py", "print(1)\n")]);
+        assert!(precheck_archive(archive.path(), 0, DEFAULT_ARCHIVE_ROOT_DEPTH).is_ok());
+    }
+
+    #[test]
+    fn test_missing_agent_files_rejected() {
+        let tmp = tempfile::tempdir().unwrap();
+        assert_eq!(agent_code_error(tmp.path()).code(), "agent_code_missing");
+
+        let agent_dir = tmp.path().join("agent_code");
+        std::fs::create_dir_all(&agent_dir).unwrap();
+        assert_eq!(agent_code_error(tmp.path()).code(), "no_agent_files");
+
+        // A manifest naming a file that isn't there.
+        std::fs::write(agent_dir.join(AGENT_ENTRYPOINT_FILE), "main.py\n").unwrap();
+        assert_eq!(
+            agent_code_error(tmp.path()).code(),
+            "agent_entrypoint_not_found"
+        );
+
+        let archive = agent_zip(&[("agent_code/entrypoint", "main.py\n")]);
+        assert_eq!(precheck_code(archive.path()), "no_agent_files");
+        let archive = agent_zip(&[("tasks/t1/prompt.md", "Fix it")]);
+        assert_eq!(precheck_code(archive.path()), "agent_code_missing");
+    }
+
+    #[test]
+    fn test_too_many_tasks_rejected() {
+        let tmp = tempfile::tempdir().unwrap();
+        for id in ["t1", "t2", "t3"] {
+            let task_dir = tmp.path().join("tasks").join(id);
+            std::fs::create_dir_all(&task_dir).unwrap();
+            std::fs::write(task_dir.join("workspace.yaml"), "repo: o/r\n").unwrap();
+            std::fs::write(task_dir.join("prompt.md"), "Fix it").unwrap();
+        }
+        let err = load_tasks(tmp.path(), 2).unwrap_err();
+        let err = err.downcast::<TooManyTasks>().unwrap();
+        assert_eq!((err.found, err.max), (3, 2));
+        assert_eq!(load_tasks(tmp.path(), 3).unwrap().len(), 3);
+
+        let files: Vec<(String, &str)> = ["t1", "t2", "t3"]
+            .iter()
+            .flat_map(|id| {
+                [
+                    (format!("bundle/tasks/{}/workspace.yaml", id), "repo: o/r\n"),
+                    (format!("bundle/tasks/{}/prompt.md", id), "Fix it"),
+                ]
+            })
+            .chain([("bundle/agent_code/agent.py".to_string(), "print(1)\n")])
+            .collect();
+        let files: Vec<(&str, &str)> = files.iter().map(|(n, c)| (n.as_str(), *c)).collect();
+        let archive = agent_zip(&files);
+        let precheck = |max| precheck_archive(archive.path(), max, DEFAULT_ARCHIVE_ROOT_DEPTH);
+        let err = precheck(2).unwrap_err();
+        assert!(err.downcast_ref::<TooManyTasks>().is_some(), "{}", err);
+        assert_eq!(precheck(3).unwrap(), Some(3));
+        assert_eq!(precheck(0).unwrap(), Some(3));
+    }
+
+    #[test]
+    fn test_precheck_judges_the_extracted_root() {
+        // Too deep for the root search, so extraction wouldn't find it.
+        let deep = agent_zip(&[("a/b/c/agent_code/agent.py", "print(1)\n")]);
+        assert_eq!(precheck_archive(deep.path(), 0, 3).unwrap(), Some(0));
+        assert!(precheck_archive(deep.path(), 0, 2).is_err());
+
+        // The shallower root wins even though its agent_code is empty.
+        let split = agent_zip(&[
+            ("x/agent_code/agent.py", " \n"),
+            ("x/tasks/t1/prompt.md", "Fix it"),
+            ("x/y/agent_code/agent.py", "print(1)\n"),
+            ("x/y/tasks/t1/prompt.md", "Fix it"),
+            ("x/y/tasks/t2/prompt.md", "Fix it"),
+        ]);
+        assert_eq!(precheck_code(split.path()), "agent_code_empty");
+
+        let nested = agent_zip(&[
+            ("x/agent_code/agent.py", "print(1)\n"),
+            ("x/tasks/t1/prompt.md", "Fix it"),
+            ("x/y/tasks/t2/prompt.md", "Fix it"),
+        ]);
+        assert_eq!(
+            precheck_archive(nested.path(), 0, DEFAULT_ARCHIVE_ROOT_DEPTH).unwrap(),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn test_find_archive_root_double_nested() {
+        let tmp = tempfile::tempdir().unwrap();
+        let root = tmp.path().join("outer").join("inner");
+        std::fs::create_dir_all(root.join("tasks")).unwrap();
+        std::fs::create_dir_all(root.join("agent_code")).unwrap();
+        std::fs::create_dir_all(tmp.path().join("aaa-unrelated/deeper")).unwrap();
+
+        assert_eq!(
+            find_archive_root(tmp.path(), DEFAULT_ARCHIVE_ROOT_DEPTH).unwrap(),
+            root
+        );
+        assert!(find_archive_root(tmp.path(), 1).is_err());
+        assert_eq!(find_archive_root(&root, 0).unwrap(), root);
+    }
+
+    #[test]
+    fn test_archive_schema_describes_workspace_yaml() {
+        let schema = archive_schema(50, 2, "python");
+        assert_eq!(schema["tasks"]["max_tasks"], 50);
+        assert_eq!(schema["agent_code"]["default_language"], "python");
+        for language in schema["agent_code"]["languages"].as_array().unwrap() {
+            let language = language.as_str().unwrap();
+            assert_eq!(normalize_agent_language(language), Some(language));
+        }
+        assert!(schema["tasks"]["required_files"]["prompt.md"].is_string());
+        assert_eq!(schema["tasks"]["test_interpreters"][".py"][0], "python3");
+
+        let workspace = &schema["workspace_schema"];
+        let required: Vec<&str> = workspace["required"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter_map(|v| v.as_str())
+            .collect();
+        assert_eq!(required, ["repo"]);
+        let props = &workspace["properties"];
+        assert!(props["lint"].is_object());
+        assert!(
+            props.get("runtime_install").is_none(),
+            "never read from yaml"
+        );
+        assert_eq!(props["stages"]["type"], "object");
+        let stage = &workspace["definitions"]["TaskStage"];
+        assert!(stage["properties"]["command"].is_object());
+        assert_eq!(
+            archive_schema(0, 2, "go")["tasks"]["max_tasks"],
+            serde_json::Value::Null
+        );
+    }
+
     #[test]
     fn test_parse_workspace_yaml() {
         let yaml = r#"
@@ -544,13 +2084,201 @@ language: "python"
         assert_eq!(config.base_commit.as_deref(), Some("abc123"));
     }
 
+    #[test]
+    fn test_workspace_timeout_overrides() {
+        let yaml = r#"
+repo: "https://github.com/psf/requests"
+test_timeout_secs: 900
+agent_timeout_secs: 7200
+"#;
+        let config: WorkspaceConfig = serde_yaml::from_str(yaml).unwrap();
+        assert_eq!(config.test_timeout_secs, Some(900));
+        assert_eq!(config.test_timeout_secs(300, 1800), 900);
+        // Clamped to the configured maximum.
+        assert_eq!(config.agent_timeout_secs(600, 1800), 1800);
+
+        let config: WorkspaceConfig =
+            serde_yaml::from_str("repo: \"https://github.com/a/b\"\ntest_timeout_secs: 0\n")
+                .unwrap();
+        assert_eq!(config.test_timeout_secs(300, 1800), 300);
+        assert_eq!(config.agent_timeout_secs(600, 1800), 600);
+    }
+
+    #[test]
+    fn test_task_dir_name() {
+        assert_eq!(task_dir_name("task-1").unwrap(), "task-1");
+        assert_eq!(
+            task_dir_name("psf/requests-6028").unwrap(),
+            "psf__requests-6028"
+        );
+        assert_eq!(task_dir_name("v1.2+rc").unwrap(), "v1.2+rc");
+
+        for id in [
+            "",
+            "..",
+            "../../etc",
+            "tasks/../../etc",
+            "/etc/passwd",
+            "a//b",
+            "a/./b",
+            "..\\windows",
+            "task\0x",
+            "task 1",
+            "$(id)",
+            ".hidden",
+            "_journal",
+            "failed",
+            "kept",
+        ] {
+            assert!(task_dir_name(id).is_err(), "{:?}", id);
+        }
+        assert_eq!(
+            task_dir_name(&"a".repeat(201)).unwrap_err().reason,
+            "too long"
+        );
+    }
+
+    #[test]
+    fn test_select_tasks() {
+        let task = |id: &str| SweForgeTask {
+            id: id.to_string(),
+            workspace: serde_yaml::from_str("repo: https://github.com/test/repo\n").unwrap(),
+            prompt: String::new(),
+            test_scripts: Vec::new(),
+            test_source_files: Vec::new(),
+            checks: Vec::new(),
+            swe_forge_fields: None,
+        };
+        let mut tasks = vec![task("a"), task("b"), task("c")];
+
+        select_tasks(&mut tasks, &["c".to_string(), "a".to_string()]).unwrap();
+        let ids: Vec<&str> = tasks.iter().map(|t| t.id.as_str()).collect();
+        assert_eq!(ids, ["a", "c"]);
+
+        let err = select_tasks(&mut tasks, &["a".to_string(), "zz".to_string()]).unwrap_err();
+        assert_eq!(err.downcast_ref::<UnknownTasks>().unwrap().0, ["zz"]);
+        assert_eq!(tasks.len(), 2, "nothing dropped on error");
+    }
+
     #[test]
     fn test_detect_agent_language() {
         let tmp = tempfile::tempdir().unwrap();
         let agent_dir = tmp.path().join("agent_code");
         std::fs::create_dir_all(&agent_dir).unwrap();
         std::fs::write(agent_dir.join("main.py"), "print('hello')").unwrap();
-        assert_eq!(detect_agent_language(tmp.path()), "python");
+        assert_eq!(
+            detect_agent_language(tmp.path(), DEFAULT_AGENT_LANGUAGE).unwrap(),
+            "python"
+        );
+    }
+
+    #[test]
+    fn test_agent_language_manifest_and_fallback() {
+        let tmp = tempfile::tempdir().unwrap();
+        let agent_dir = tmp.path().join("agent_code");
+        std::fs::create_dir_all(&agent_dir).unwrap();
+        std::fs::write(agent_dir.join("Makefile"), "run:\n\t./agent\n").unwrap();
+        std::fs::write(agent_dir.join("agent"), "#!/bin/sh\necho hi\n").unwrap();
+
+        // Nothing to sniff: the configured fallback applies.
+        assert_eq!(
+            detect_agent_language(tmp.path(), DEFAULT_AGENT_LANGUAGE).unwrap(),
+            "python"
+        );
+        assert_eq!(detect_agent_language(tmp.path(), "shell").unwrap(), "shell");
+
+        // A declaration wins over the fallback and over extensions.
+        std::fs::write(agent_dir.join("helper.py"), "print('x')").unwrap();
+        std::fs::write(
+            agent_dir.join(AGENT_LANGUAGE_FILE),
+            "# agent\nlanguage: bash\n",
+        )
+        .unwrap();
+        assert_eq!(
+            detect_agent_language(tmp.path(), "python").unwrap(),
+            "shell"
+        );
+        std::fs::write(agent_dir.join(AGENT_LANGUAGE_FILE), "golang\n").unwrap();
+        assert_eq!(detect_agent_language(tmp.path(), "python").unwrap(), "go");
+
+        // The manifest is not part of the concatenated agent code.
+        assert!(!load_agent_code(tmp.path()).unwrap().contains("golang"));
+
+        std::fs::write(agent_dir.join(AGENT_LANGUAGE_FILE), "cobol\n").unwrap();
+        let err = detect_agent_language(tmp.path(), "python").unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<AgentCodeError>().unwrap().code(),
+            "agent_language_unknown"
+        );
+    }
+
+    #[test]
+    fn test_agent_entrypoint_manifest() {
+        let tmp = tempfile::tempdir().unwrap();
+        let agent_dir = tmp.path().join("agent_code");
+        std::fs::create_dir_all(agent_dir.join("cmd")).unwrap();
+        std::fs::write(agent_dir.join("cmd/main.go"), "package main\n").unwrap();
+        std::fs::write(agent_dir.join("util.go"), "package main\n// util\n").unwrap();
+        std::fs::write(agent_dir.join("agent.py"), "print('x')").unwrap();
+
+        std::fs::write(
+            agent_dir.join(AGENT_ENTRYPOINT_FILE),
+            "entrypoint: cmd/main.go\n",
+        )
+        .unwrap();
+        assert_eq!(
+            read_agent_entrypoint(&agent_dir).unwrap().as_deref(),
+            Some("cmd/main.go")
+        );
+        assert_eq!(load_agent_code(tmp.path()).unwrap(), "package main\n");
+        assert_eq!(detect_agent_language(tmp.path(), "shell").unwrap(), "go");
+
+        std::fs::write(agent_dir.join(AGENT_ENTRYPOINT_FILE), "agent.py\n").unwrap();
+        assert_eq!(
+            detect_agent_language(tmp.path(), "shell").unwrap(),
+            "python"
+        );
+
+        std::fs::write(agent_dir.join(AGENT_ENTRYPOINT_FILE), "../escape.py").unwrap();
+        assert!(read_agent_entrypoint(&agent_dir).is_err());
+        std::fs::write(agent_dir.join(AGENT_ENTRYPOINT_FILE), "missing.go").unwrap();
+        assert!(load_agent_code(tmp.path()).is_err());
+    }
+
+    #[test]
+    fn test_run_tests_py_and_test_command() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path();
+        std::fs::write(
+            dir.join("workspace.yaml"),
+            "repo: https://github.com/test/repo\n",
+        )
+        .unwrap();
+        std::fs::write(dir.join("prompt.md"), "Fix the bug").unwrap();
+        std::fs::create_dir_all(dir.join("tests")).unwrap();
+        std::fs::write(dir.join("tests/run_tests.py"), "print('ok')").unwrap();
+        std::fs::write(dir.join("tests/test_helpers.py"), "x = 1").unwrap();
+
+        let task = parse_task(dir).unwrap();
+        let names: Vec<&str> = task.test_scripts.iter().map(|(n, _)| n.as_str()).collect();
+        assert_eq!(names, vec!["run_tests.py"]);
+        assert_eq!(test_interpreter("run_tests.py"), &["python3"]);
+        assert_eq!(test_interpreter("check.sh"), &["bash"]);
+        assert_eq!(test_interpreter("Makefile"), &["bash"]);
+
+        std::fs::write(
+            dir.join("workspace.yaml"),
+            "repo: https://github.com/test/repo\ntest_command: make test\n",
+        )
+        .unwrap();
+        let task = parse_task(dir).unwrap();
+        assert_eq!(task.test_scripts.len(), 1);
+        assert_eq!(task.test_scripts[0].0, "_test_command.sh");
+        assert!(task.test_scripts[0].1.contains("make test"));
+        assert!(task
+            .test_source_files
+            .iter()
+            .any(|(n, _)| n == "run_tests.py"));
     }
 
     #[test]
@@ -567,6 +2295,7 @@ language: "python"
 
         let task = parse_task(dir).unwrap();
         assert_eq!(task.test_scripts.len(), 2);
+        assert_eq!(task.checks, vec!["pytest tests/", "cargo test"]);
     }
 
     #[test]
@@ -2,7 +2,10 @@ use anyhow::{Context, Result};
 use std::path::Path;
 use tracing::info;
 
-use super::{extract_uploaded_archive, SweForgeTask, WorkspaceConfig};
+use super::{
+    extract_uploaded_archive, SweForgeTask, WorkspaceConfig, DEFAULT_AGENT_LANGUAGE,
+    DEFAULT_ARCHIVE_ROOT_DEPTH,
+};
 use crate::swe_forge::types::{DatasetEntry, HuggingFaceDataset};
 use crate::task::types::SweForgeTaskFields;
 
@@ -28,7 +31,14 @@ impl TaskRegistry {
     }
 
     pub async fn load_from_archive(&mut self, data: &[u8], dest: &Path) -> Result<()> {
-        let extracted = extract_uploaded_archive(data, dest).await?;
+        let extracted = extract_uploaded_archive(
+            data,
+            dest,
+            DEFAULT_ARCHIVE_ROOT_DEPTH,
+            0,
+            DEFAULT_AGENT_LANGUAGE,
+        )
+        .await?;
         info!(
             "Loaded {} tasks from archive (agent language: {})",
             extracted.tasks.len(),
@@ -159,6 +169,15 @@ fn convert_dataset_entry_to_task(entry: &DatasetEntry) -> Result<SweForgeTask> {
         base_commit: Some(entry.base_commit.clone()),
         install,
         language: Some(language),
+        test_command: None,
+        pass_exit_codes: None,
+        weight: None,
+        test_timeout_secs: None,
+        agent_timeout_secs: None,
+        artifacts: Vec::new(),
+        stages: Vec::new(),
+        lint: None,
+        sequential_tests: false,
         fail_to_pass: f2p,
         pass_to_pass: p2p,
         install_config: None,
@@ -190,6 +209,7 @@ fn convert_dataset_entry_to_task(entry: &DatasetEntry) -> Result<SweForgeTask> {
         prompt: entry.problem_statement.clone(),
         test_scripts,
         test_source_files: Vec::new(),
+        checks: Vec::new(),
         swe_forge_fields: Some(swe_forge_fields),
     })
 }
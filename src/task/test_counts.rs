@@ -0,0 +1,225 @@
+/// Individual test counts read from a test script's output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TestCounts {
+    pub total: u32,
+    pub passed: u32,
+    pub failed: u32,
+}
+
+/// Pull test counts out of `output` when it has a summary from a runner we
+/// recognise: cargo's `test result:` lines (summed over test binaries), `go
+/// test -v` `--- PASS`/`--- FAIL` lines, jest's `Tests:` line or pytest's
+/// final summary. Skipped and ignored tests are left out; pytest errors count
+/// as failures. `None` when nothing recognisable is found.
+/// The output is the agent's to shape, so sums saturate at `u32::MAX`
+/// instead of overflowing.
+pub fn parse_test_counts(output: &str) -> Option<TestCounts> {
+    parse_cargo(output)
+        .or_else(|| parse_go(output))
+        .or_else(|| parse_jest(output))
+        .or_else(|| parse_pytest(output))
+}
+
+/// `N word` pairs from a comma- or semicolon-separated summary, e.g.
+/// `3 failed, 10 passed, 2 skipped in 1.2s` → (3, "failed"), (10, "passed"), ...
+fn count_pairs(summary: &str) -> impl Iterator<Item = (u32, &str)> {
+    summary.split([',', ';']).filter_map(|part| {
+        let mut words = part.split_whitespace();
+        let n = words.next()?.parse().ok()?;
+        let word = words.next()?.trim_end_matches('.');
+        Some((n, word))
+    })
+}
+
+fn parse_cargo(output: &str) -> Option<TestCounts> {
+    let mut counts: Option<TestCounts> = None;
+    for line in output.lines() {
+        let Some(rest) = line.trim().strip_prefix("test result: ") else {
+            continue;
+        };
+        let Some((_, summary)) = rest.split_once(". ") else {
+            continue;
+        };
+        let c = counts.get_or_insert_with(TestCounts::default);
+        for (n, word) in count_pairs(summary) {
+            match word {
+                "passed" => c.passed = c.passed.saturating_add(n),
+                "failed" => c.failed = c.failed.saturating_add(n),
+                _ => {}
+            }
+        }
+    }
+    counts.map(with_total)
+}
+
+fn parse_go(output: &str) -> Option<TestCounts> {
+    let mut counts = TestCounts::default();
+    for line in output.lines() {
+        let line = line.trim_start();
+        if line.starts_with("--- PASS:") {
+            counts.passed = counts.passed.saturating_add(1);
+        } else if line.starts_with("--- FAIL:") {
+            counts.failed = counts.failed.saturating_add(1);
+        }
+    }
+    (counts.passed > 0 || counts.failed > 0).then(|| with_total(counts))
+}
+
+fn parse_jest(output: &str) -> Option<TestCounts> {
+    let line = output
+        .lines()
+        .rev()
+        .find_map(|l| l.trim().strip_prefix("Tests:"))?;
+    let mut counts = TestCounts::default();
+    let mut saw_total = false;
+    for (n, word) in count_pairs(line) {
+        match word {
+            "passed" => counts.passed = n,
+            "failed" => counts.failed = n,
+            "total" => saw_total = true,
+            _ => {}
+        }
+    }
+    saw_total.then(|| with_total(counts))
+}
+
+fn parse_pytest(output: &str) -> Option<TestCounts> {
+    output.lines().rev().find_map(|line| {
+        let line = line.trim().trim_matches('=').trim();
+        // The summary always ends with the duration, e.g. "in 0.12s".
+        let (summary, duration) = line.rsplit_once(" in ")?;
+        if !duration.ends_with('s') && !duration.contains("s (") {
+            return None;
+        }
+        let mut counts = TestCounts::default();
+        let mut recognised = false;
+        for (n, word) in count_pairs(summary) {
+            match word {
+                "passed" => counts.passed = n,
+                "failed" | "error" | "errors" => counts.failed = counts.failed.saturating_add(n),
+                "skipped" | "xfailed" | "xpassed" | "deselected" | "warning" | "warnings"
+                | "rerun" => {}
+                _ => return None,
+            }
+            recognised = true;
+        }
+        recognised.then(|| with_total(counts))
+    })
+}
+
+fn with_total(mut counts: TestCounts) -> TestCounts {
+    counts.total = counts.passed.saturating_add(counts.failed);
+    counts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(total: u32, passed: u32, failed: u32) -> Option<TestCounts> {
+        Some(TestCounts {
+            total,
+            passed,
+            failed,
+        })
+    }
+
+    #[test]
+    fn test_pytest_summaries() {
+        let output = "\
+============================= test session starts ==============================
+platform linux -- Python 3.11.4, pytest-7.4.0, pluggy-1.2.0
+rootdir: /repo
+collected 14 items
+
+tests/test_api.py ..F.......s.E.                                         [100%]
+
+==================================== ERRORS ====================================
+___________________ ERROR at setup of test_session_timeout ____________________
+=========================== short test summary info ============================
+FAILED tests/test_api.py::test_redirect_loop - AssertionError: assert 302 == 200
+ERROR tests/test_api.py::test_session_timeout - fixture 'server' not found
+============= 1 failed, 11 passed, 1 skipped, 1 error in 2.34s ==============
+";
+        assert_eq!(parse_test_counts(output), counts(13, 11, 2));
+
+        assert_eq!(
+            parse_test_counts("....\n4 passed, 1 warning in 0.05s\n"),
+            counts(4, 4, 0)
+        );
+        assert_eq!(parse_test_counts("no tests ran in 0.01s\n"), None);
+    }
+
+    #[test]
+    fn test_cargo_summaries_are_summed() {
+        let output = "\
+   Compiling demo v0.1.0 (/repo)
+    Finished test [unoptimized + debuginfo] target(s) in 3.21s
+     Running unittests src/lib.rs (target/debug/deps/demo-1a2b3c)
+
+running 3 tests
+test parser::tests::parses_empty ... ok
+test parser::tests::parses_nested ... FAILED
+test parser::tests::rejects_garbage ... ok
+
+failures:
+
+---- parser::tests::parses_nested stdout ----
+thread 'parser::tests::parses_nested' panicked at src/parser.rs:88:9
+
+test result: FAILED. 2 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.01s
+
+     Running tests/integration.rs (target/debug/deps/integration-4d5e6f)
+
+running 2 tests
+test roundtrip ... ok
+test slow ... ignored
+
+test result: ok. 1 passed; 0 failed; 1 ignored; 0 measured; 0 filtered out; finished in 0.00s
+";
+        assert_eq!(parse_test_counts(output), counts(4, 3, 1));
+    }
+
+    #[test]
+    fn test_go_and_jest_summaries() {
+        let go = "\
+=== RUN   TestAdd
+--- PASS: TestAdd (0.00s)
+=== RUN   TestDiv
+=== RUN   TestDiv/by_zero
+    --- FAIL: TestDiv/by_zero (0.00s)
+--- FAIL: TestDiv (0.00s)
+FAIL
+FAIL\texample.com/calc\t0.004s
+";
+        assert_eq!(parse_test_counts(go), counts(3, 1, 2));
+
+        let jest = "\
+Test Suites: 1 failed, 2 passed, 3 total
+Tests:       1 failed, 7 passed, 8 total
+Snapshots:   0 total
+Time:        1.52 s
+";
+        assert_eq!(parse_test_counts(jest), counts(8, 7, 1));
+    }
+
+    #[test]
+    fn test_huge_counts_saturate() {
+        let max = u32::MAX;
+        let cargo = format!(
+            "test result: ok. {max} passed; 0 failed\ntest result: FAILED. {max} passed; {max} failed\n"
+        );
+        assert_eq!(parse_test_counts(&cargo), counts(max, max, max));
+        let jest = format!("Tests:       {max} failed, {max} passed, {max} total\n");
+        assert_eq!(parse_test_counts(&jest), counts(max, max, max));
+        let pytest = format!("==== {max} failed, {max} errors in 1.0s ====\n");
+        assert_eq!(parse_test_counts(&pytest), counts(max, 0, max));
+    }
+
+    #[test]
+    fn test_unrecognised_output() {
+        assert_eq!(parse_test_counts(""), None);
+        assert_eq!(parse_test_counts("All good\nexit 0\n"), None);
+        assert_eq!(parse_test_counts("ok  \texample.com/calc\t0.004s\n"), None);
+    }
+}
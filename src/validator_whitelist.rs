@@ -1,21 +1,29 @@
 use anyhow::Context;
+use chrono::{DateTime, TimeZone, Utc};
 use parking_lot::RwLock;
 use std::collections::HashSet;
+use std::sync::atomic::{AtomicI64, AtomicU32, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
-use tracing::{info, warn};
+use tracing::{error, info, warn};
 
 const MAX_REFRESH_RETRIES: u32 = 3;
 const BACKOFF_BASE_SECS: u64 = 2;
 
 pub struct ValidatorWhitelist {
     hotkeys: RwLock<HashSet<String>>,
+    /// Refresh rounds (each with its own retries) that failed in a row.
+    consecutive_failures: AtomicU32,
+    /// Unix seconds of the last successful refresh, 0 if there was none.
+    last_success: AtomicI64,
 }
 
 impl ValidatorWhitelist {
     pub fn new() -> Arc<Self> {
         Arc::new(Self {
             hotkeys: RwLock::new(HashSet::new()),
+            consecutive_failures: AtomicU32::new(0),
+            last_success: AtomicI64::new(0),
         })
     }
 
@@ -34,20 +42,77 @@ impl ValidatorWhitelist {
         self.hotkeys.read().len()
     }
 
+    pub fn consecutive_failures(&self) -> u32 {
+        self.consecutive_failures.load(Ordering::Relaxed)
+    }
+
+    pub fn last_success(&self) -> Option<DateTime<Utc>> {
+        match self.last_success.load(Ordering::Relaxed) {
+            0 => None,
+            secs => Utc.timestamp_opt(secs, 0).single(),
+        }
+    }
+
+    /// Whether refreshes have failed `threshold` times in a row. A threshold
+    /// of 0 never trips.
+    pub fn is_degraded(&self, threshold: u32) -> bool {
+        threshold > 0 && self.consecutive_failures() >= threshold
+    }
+
+    fn record_success(&self) {
+        self.consecutive_failures.store(0, Ordering::Relaxed);
+        self.last_success
+            .store(Utc::now().timestamp(), Ordering::Relaxed);
+    }
+
+    fn record_failure(&self, threshold: u32) {
+        let failures = self.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if threshold > 0 && failures >= threshold {
+            error!(
+                consecutive_failures = failures,
+                last_success = ?self.last_success(),
+                "Validator whitelist refresh keeps failing, serving a stale whitelist"
+            );
+        }
+    }
+
+    pub fn render_prometheus(&self) -> String {
+        format!(
+            "# HELP term_executor_whitelist_refresh_failures Consecutive failed validator whitelist refreshes.\n\
+             # TYPE term_executor_whitelist_refresh_failures gauge\n\
+             term_executor_whitelist_refresh_failures {}\n\
+             # HELP term_executor_whitelist_last_success_timestamp Unix time of the last successful whitelist refresh.\n\
+             # TYPE term_executor_whitelist_last_success_timestamp gauge\n\
+             term_executor_whitelist_last_success_timestamp {}\n",
+            self.consecutive_failures(),
+            self.last_success.load(Ordering::Relaxed)
+        )
+    }
+
     #[cfg(test)]
     pub fn insert_for_test(&self, hotkey: &str) {
         self.hotkeys.write().insert(hotkey.to_string());
     }
 
-    pub async fn refresh_loop(self: Arc<Self>, netuid: u16, min_stake_tao: f64, refresh_secs: u64) {
+    pub async fn refresh_loop(
+        self: Arc<Self>,
+        netuid: u16,
+        min_stake_tao: f64,
+        refresh_secs: u64,
+        failure_threshold: u32,
+    ) {
         let mut interval = tokio::time::interval(Duration::from_secs(refresh_secs));
         loop {
             interval.tick().await;
-            self.refresh_once(netuid, min_stake_tao).await;
+            if self.refresh_once(netuid, min_stake_tao).await {
+                self.record_success();
+            } else {
+                self.record_failure(failure_threshold);
+            }
         }
     }
 
-    async fn refresh_once(&self, netuid: u16, min_stake_tao: f64) {
+    async fn refresh_once(&self, netuid: u16, min_stake_tao: f64) -> bool {
         let mut last_err = None;
         for attempt in 0..MAX_REFRESH_RETRIES {
             if attempt > 0 {
@@ -57,7 +122,7 @@ impl ValidatorWhitelist {
             match self.try_refresh(netuid, min_stake_tao).await {
                 Ok(count) => {
                     info!(count, netuid, "Validator whitelist refreshed successfully");
-                    return;
+                    return true;
                 }
                 Err(e) => {
                     warn!(
@@ -75,6 +140,7 @@ impl ValidatorWhitelist {
                 "All retry attempts failed for validator whitelist refresh, keeping cached whitelist"
             );
         }
+        false
     }
 
     async fn try_refresh(&self, netuid: u16, min_stake_tao: f64) -> anyhow::Result<usize> {
@@ -136,4 +202,24 @@ mod tests {
             .insert("5FHneW46xGXgs5mUiveU4sbTyGBzmstUspZC92UhjJM694ty".to_string());
         assert_eq!(wl.validator_count(), 2);
     }
+
+    #[test]
+    fn test_refresh_failures_trip_and_reset() {
+        let wl = ValidatorWhitelist::new();
+        assert!(wl.last_success().is_none());
+
+        wl.record_failure(2);
+        assert!(!wl.is_degraded(2));
+        wl.record_failure(2);
+        assert!(wl.is_degraded(2));
+        assert!(!wl.is_degraded(0));
+        assert!(wl
+            .render_prometheus()
+            .contains("term_executor_whitelist_refresh_failures 2"));
+
+        wl.record_success();
+        assert_eq!(wl.consecutive_failures(), 0);
+        assert!(!wl.is_degraded(2));
+        assert!(wl.last_success().is_some());
+    }
 }
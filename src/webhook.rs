@@ -0,0 +1,256 @@
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::{debug, warn};
+
+use crate::metrics::Metrics;
+use crate::session::Batch;
+use crate::signing::{ResponseSigner, SIGNATURE_HEADER};
+
+/// Per-attempt timeout, so a slow receiver never holds a delivery for long.
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+const WEBHOOK_ATTEMPTS: u32 = 3;
+const WEBHOOK_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// What a consensus round agreed on, echoed in every notification.
+#[derive(Debug, Clone)]
+pub struct ConsensusInfo {
+    pub archive_hash: String,
+    pub votes: usize,
+    pub required: usize,
+}
+
+/// POSTs `consensus_reached` and `batch_complete` notifications to
+/// `CONSENSUS_WEBHOOK_URL`. Deliveries run in their own tasks and are
+/// retried a few times; a webhook that stays down only bumps
+/// `term_executor_webhook_failures`.
+pub struct ConsensusWebhook {
+    client: reqwest::Client,
+    url: String,
+    signer: Option<Arc<ResponseSigner>>,
+    metrics: Arc<Metrics>,
+    retry_delay: Duration,
+}
+
+impl ConsensusWebhook {
+    /// Bodies are signed like responses when `signer` is set, with the hex
+    /// signature in `X-Executor-Signature`.
+    pub fn new(
+        url: &str,
+        signer: Option<Arc<ResponseSigner>>,
+        metrics: Arc<Metrics>,
+    ) -> anyhow::Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()?;
+        Ok(Self {
+            client,
+            url: url.to_string(),
+            signer,
+            metrics,
+            retry_delay: WEBHOOK_RETRY_DELAY,
+        })
+    }
+
+    /// Announce that `batch` was started from consensus, then notify again
+    /// once it completes. Call before the batch is spawned so its
+    /// `batch_complete` event can't be missed.
+    pub fn batch_started(self: &Arc<Self>, batch: &Batch, info: ConsensusInfo) {
+        let mut rx = batch.events_tx.subscribe();
+        let batch_id = batch.id.clone();
+        drop(self.post(payload("consensus_reached", &batch_id, &info, None)));
+
+        let webhook = self.clone();
+        tokio::spawn(async move {
+            loop {
+                match rx.recv().await {
+                    Ok(event) if event.event == "batch_complete" => {
+                        let body = payload("batch_complete", &batch_id, &info, Some(event.data));
+                        let _ = webhook.post(body).await;
+                        break;
+                    }
+                    Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                    Err(RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    /// Deliver `body` in the background. The handle resolves to whether any
+    /// attempt got a 2xx.
+    pub fn post(&self, body: serde_json::Value) -> tokio::task::JoinHandle<bool> {
+        let client = self.client.clone();
+        let url = self.url.clone();
+        let signer = self.signer.clone();
+        let metrics = self.metrics.clone();
+        let retry_delay = self.retry_delay;
+
+        tokio::spawn(async move {
+            let bytes = serde_json::to_vec(&body).unwrap_or_default();
+            let signature = signer.as_ref().map(|s| s.sign(&bytes));
+            for attempt in 1..=WEBHOOK_ATTEMPTS {
+                let mut request = client
+                    .post(&url)
+                    .header(reqwest::header::CONTENT_TYPE, "application/json")
+                    .body(bytes.clone());
+                if let Some(ref signature) = signature {
+                    request = request.header(SIGNATURE_HEADER, signature);
+                }
+                match request.send().await {
+                    Ok(resp) if resp.status().is_success() => return true,
+                    Ok(resp) => debug!(
+                        "Webhook {} answered {} (attempt {})",
+                        url,
+                        resp.status(),
+                        attempt
+                    ),
+                    Err(e) => debug!("Webhook {} failed (attempt {}): {}", url, attempt, e),
+                }
+                if attempt < WEBHOOK_ATTEMPTS {
+                    tokio::time::sleep(retry_delay * attempt).await;
+                }
+            }
+            warn!(
+                "Giving up on webhook {} after {} attempts",
+                url, WEBHOOK_ATTEMPTS
+            );
+            metrics.webhook_failures.fetch_add(1, Ordering::Relaxed);
+            false
+        })
+    }
+}
+
+fn payload(
+    event: &str,
+    batch_id: &str,
+    info: &ConsensusInfo,
+    result: Option<serde_json::Value>,
+) -> serde_json::Value {
+    let mut body = serde_json::json!({
+        "event": event,
+        "archive_hash": info.archive_hash,
+        "batch_id": batch_id,
+        "votes": info.votes,
+        "required": info.required,
+        "timestamp": chrono::Utc::now(),
+    });
+    if let Some(result) = result {
+        body["result"] = result;
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::session::SessionManager;
+    use axum::{http::HeaderMap, routing::post, Router};
+
+    type Received = Arc<parking_lot::Mutex<Vec<(Option<String>, Vec<u8>)>>>;
+
+    /// Webhook receiver that fails the first `fail_first` requests.
+    async fn receiver(fail_first: usize) -> (String, Received) {
+        let received: Received = Default::default();
+        let seen = received.clone();
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let app = Router::new().route(
+            "/hook",
+            post(move |headers: HeaderMap, body: axum::body::Bytes| {
+                let seen = seen.clone();
+                let calls = calls.clone();
+                async move {
+                    if calls.fetch_add(1, Ordering::SeqCst) < fail_first {
+                        return axum::http::StatusCode::BAD_GATEWAY;
+                    }
+                    let signature = headers
+                        .get(SIGNATURE_HEADER)
+                        .and_then(|v| v.to_str().ok())
+                        .map(str::to_string);
+                    seen.lock().push((signature, body.to_vec()));
+                    axum::http::StatusCode::OK
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+        (format!("http://{}/hook", addr), received)
+    }
+
+    fn webhook(url: &str, signer: Option<Arc<ResponseSigner>>) -> Arc<ConsensusWebhook> {
+        let mut webhook = ConsensusWebhook::new(url, signer, Metrics::new()).unwrap();
+        webhook.retry_delay = Duration::from_millis(10);
+        Arc::new(webhook)
+    }
+
+    #[tokio::test]
+    async fn test_signed_notifications_on_start_and_completion() {
+        let (url, received) = receiver(1).await;
+        let signer = Arc::new(ResponseSigner::from_seed_hex(&"22".repeat(32)).unwrap());
+        let hook = webhook(&url, Some(signer.clone()));
+        let batch = SessionManager::new(60).create_batch(1);
+
+        hook.batch_started(
+            &batch,
+            ConsensusInfo {
+                archive_hash: "abc".to_string(),
+                votes: 2,
+                required: 2,
+            },
+        );
+        batch
+            .emit_event("batch_complete", None, serde_json::json!({"passed": 1}))
+            .await;
+
+        for _ in 0..100 {
+            if received.lock().len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let received = received.lock();
+        assert_eq!(received.len(), 2, "first attempt fails, then both land");
+        let bodies: Vec<serde_json::Value> = received
+            .iter()
+            .map(|(_, b)| serde_json::from_slice(b).unwrap())
+            .collect();
+        let started = bodies
+            .iter()
+            .find(|b| b["event"] == "consensus_reached")
+            .unwrap();
+        assert_eq!(started["archive_hash"], "abc");
+        assert_eq!(started["batch_id"], batch.id.as_str());
+        assert_eq!(started["votes"], 2);
+        assert_eq!(started["required"], 2);
+        assert!(started["timestamp"].is_string());
+        let done = bodies
+            .iter()
+            .find(|b| b["event"] == "batch_complete")
+            .unwrap();
+        assert_eq!(done["result"]["passed"], 1);
+
+        for (signature, body) in received.iter() {
+            let signature = signature.as_deref().expect("signed");
+            assert!(crate::signing::verify(
+                &signer.public_key_hex(),
+                body,
+                signature
+            ));
+        }
+        assert_eq!(hook.metrics.webhook_failures.load(Ordering::Relaxed), 0);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_webhook_counts_failure() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        drop(listener);
+
+        let hook = webhook(&url, None);
+        assert!(!hook.post(serde_json::json!({})).await.unwrap());
+        assert_eq!(hook.metrics.webhook_failures.load(Ordering::Relaxed), 1);
+    }
+}
@@ -3,7 +3,8 @@ use axum::{
         ws::{Message, WebSocket},
         Query, State, WebSocketUpgrade,
     },
-    response::Response,
+    http::HeaderMap,
+    response::{IntoResponse, Response},
 };
 use futures::{SinkExt, StreamExt};
 use serde::Deserialize;
@@ -16,16 +17,101 @@ use crate::session::WsEvent;
 
 #[derive(Deserialize)]
 pub struct WsQuery {
-    pub batch_id: String,
+    /// A single batch, or `*` / absent for events from every batch.
+    #[serde(default)]
+    pub batch_id: Option<String>,
 }
 
 pub async fn ws_handler(
     ws: WebSocketUpgrade,
     State(state): State<Arc<AppState>>,
     Query(query): Query<WsQuery>,
+    headers: HeaderMap,
 ) -> Response {
-    let batch_id = query.batch_id;
-    ws.on_upgrade(move |socket| handle_ws(socket, state, batch_id))
+    match query.batch_id.filter(|id| id != "*") {
+        Some(batch_id) => ws.on_upgrade(move |socket| handle_ws(socket, state, batch_id)),
+        None => {
+            // Every batch's results flow through here, so only validators.
+            if let Err(e) = crate::handlers::require_validator(&state, &headers) {
+                return e.into_response();
+            }
+            let Some(rx) = state.sessions.subscribe_all() else {
+                return (
+                    axum::http::StatusCode::SERVICE_UNAVAILABLE,
+                    axum::Json(serde_json::json!({
+                        "error": "too_many_subscribers",
+                        "message": format!(
+                            "At most {} all-batch subscribers are allowed",
+                            crate::session::MAX_FIREHOSE_SUBSCRIBERS
+                        ),
+                    })),
+                )
+                    .into_response();
+            };
+            ws.on_upgrade(move |socket| handle_firehose(socket, rx))
+        }
+    }
+}
+
+/// Forward every batch's events until the client disconnects.
+async fn handle_firehose(socket: WebSocket, rx: broadcast::Receiver<WsEvent>) {
+    info!("WebSocket connected for all batches");
+    let (sender, receiver) = socket.split();
+    tokio::select! {
+        _ = forward_events(rx, sender, "*".to_string()) => {},
+        _ = drain_incoming(receiver) => {},
+    }
+    info!("WebSocket disconnected for all batches");
+}
+
+async fn forward_events(
+    mut rx: broadcast::Receiver<WsEvent>,
+    mut sender: futures::stream::SplitSink<WebSocket, Message>,
+    batch_id: String,
+) {
+    loop {
+        match rx.recv().await {
+            Ok(event) => {
+                let json = match serde_json::to_string(&event) {
+                    Ok(j) => j,
+                    Err(_) => continue,
+                };
+                if sender.send(Message::Text(json)).await.is_err() {
+                    break;
+                }
+            }
+            Err(broadcast::error::RecvError::Lagged(n)) => {
+                debug!("WebSocket lagged by {} messages", n);
+                continue;
+            }
+            Err(broadcast::error::RecvError::Closed) => {
+                let close_msg = serde_json::json!({
+                    "event": "stream_closed",
+                    "batch_id": batch_id,
+                });
+                let close_json = serde_json::to_string(&close_msg).unwrap_or_default();
+                let _ = sender.send(Message::Text(close_json)).await;
+                break;
+            }
+        }
+    }
+}
+
+async fn drain_incoming(mut receiver: futures::stream::SplitStream<WebSocket>) {
+    while let Some(msg) = receiver.next().await {
+        match msg {
+            Ok(Message::Close(_)) => break,
+            Ok(Message::Ping(data)) => {
+                debug!("Received ping");
+                let _ = data;
+            }
+            Err(e) => {
+                warn!("WebSocket receive error: {}", e);
+                break;
+            }
+            _ => {}
+        }
+    }
 }
 
 async fn handle_ws(socket: WebSocket, state: Arc<AppState>, batch_id: String) {
@@ -45,8 +131,8 @@ async fn handle_ws(socket: WebSocket, state: Arc<AppState>, batch_id: String) {
 
     info!("WebSocket connected for batch {}", batch_id);
 
-    let mut rx: broadcast::Receiver<WsEvent> = batch.events_tx.subscribe();
-    let (mut sender, mut receiver) = socket.split();
+    let rx: broadcast::Receiver<WsEvent> = batch.events_tx.subscribe();
+    let (mut sender, receiver) = socket.split();
 
     let current_state = batch.result.lock().await;
     let snapshot = serde_json::json!({
@@ -69,52 +155,8 @@ async fn handle_ws(socket: WebSocket, state: Arc<AppState>, batch_id: String) {
         return;
     }
 
-    let batch_id_send = batch_id.clone();
-    let send_task = tokio::spawn(async move {
-        loop {
-            match rx.recv().await {
-                Ok(event) => {
-                    let json = match serde_json::to_string(&event) {
-                        Ok(j) => j,
-                        Err(_) => continue,
-                    };
-                    if sender.send(Message::Text(json)).await.is_err() {
-                        break;
-                    }
-                }
-                Err(broadcast::error::RecvError::Lagged(n)) => {
-                    debug!("WebSocket lagged by {} messages", n);
-                    continue;
-                }
-                Err(broadcast::error::RecvError::Closed) => {
-                    let close_msg = serde_json::json!({
-                        "event": "stream_closed",
-                        "batch_id": batch_id_send,
-                    });
-                    let close_json = serde_json::to_string(&close_msg).unwrap_or_default();
-                    let _ = sender.send(Message::Text(close_json)).await;
-                    break;
-                }
-            }
-        }
-    });
-
-    let recv_task = tokio::spawn(async move {
-        while let Some(msg) = receiver.next().await {
-            match msg {
-                Ok(Message::Close(_)) => break,
-                Ok(Message::Ping(data)) => {
-                    debug!("Received ping");
-                    let _ = data;
-                }
-                Err(e) => {
-                    warn!("WebSocket receive error: {}", e);
-                    break;
-                }
-                _ => {}
-            }
-        }
-    });
+    let send_task = tokio::spawn(forward_events(rx, sender, batch_id.clone()));
+    let recv_task = tokio::spawn(drain_incoming(receiver));
 
     tokio::select! {
         _ = send_task => {},